@@ -0,0 +1,52 @@
+// Compares looking up global variables in the global environment, whose table
+// hashes GcIds with a multiply, with a HashMap using the default SipHash.
+// Run with cargo bench.
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::Instant;
+
+use scheme::interp::Interp;
+use scheme::parser::Parser;
+use scheme::types::Value;
+
+const ROUNDS: usize = 2_000;
+
+fn main() {
+    let interp = Interp::new();
+    let ids = interp.env.borrow().bindings().into_iter()
+        .map(|(id, _)| id)
+        .collect::<Vec<_>>();
+    let hashed: HashMap<_, _> = interp.env.borrow().bindings().into_iter().collect();
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for id in &ids {
+            black_box(hashed.get(black_box(id)));
+        }
+    }
+    let before = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let env = interp.env.borrow();
+        for id in &ids {
+            black_box(env.lookup(black_box(*id)));
+        }
+    }
+    let after = start.elapsed();
+
+    let lookups = ROUNDS * ids.len();
+    println!("{} global lookups", lookups);
+    println!("  hash map:       {:?} ({:.1} ns each)", before, before.as_nanos() as f64 / lookups as f64);
+    println!("  id-hashed table: {:?} ({:.1} ns each)", after, after.as_nanos() as f64 / lookups as f64);
+
+    // The same through the evaluator, where every call looks up =, - and loop.
+    let text = "(define loop (lambda (n) (if (= n 0) 0 (loop (- n 1)))))";
+    interp.eval(Parser::new(text.as_bytes()).read(&interp).unwrap()).unwrap();
+    let call = Parser::new("(loop 500)".as_bytes()).read(&interp).unwrap();
+    let start = Instant::now();
+    for _ in 0..ROUNDS / 10 {
+        assert_eq!(interp.eval(call).unwrap(), Value::Number(scheme::types::Number::Int(0)));
+    }
+    println!("  (loop 500) x {}: {:?}", ROUNDS / 10, start.elapsed());
+}
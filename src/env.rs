@@ -1,25 +1,63 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{cell::RefCell, collections::{HashMap, HashSet}, hash::{BuildHasherDefault, Hasher}, rc::Rc};
 
 use crate::types::{GcId, SchemeError, Value};
 
+// Symbols are interned to stable GcIds, distinct integers that only need
+// spreading over the table rather than SipHash. A table indexed by GcId
+// would grow with the largest id on the heap instead of with the bindings.
+#[derive(Default)]
+struct IdHasher(u64);
+
+impl Hasher for IdHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_u64(self.0 ^ u64::from(*byte));
+        }
+    }
+
+    fn write_u64(&mut self, id: u64) {
+        self.0 = id.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+
+    fn write_usize(&mut self, id: usize) {
+        self.write_u64(id as u64);
+    }
+}
+
+type Bindings = HashMap<GcId, Value, BuildHasherDefault<IdHasher>>;
 
 pub struct Env {
-    pub bindings: HashMap<GcId, Value>,
+    bindings: Bindings,
+    // The bindings made by define-constant, which can't be assigned again.
+    constants: HashSet<GcId>,
     pub parent: Option<Rc<RefCell<Env>>>,
 }
 
+impl Default for Env {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Env {
-    
+
+    // A new global environment.
     pub fn new() -> Self {
         Self {
-            bindings: HashMap::new(),
+            bindings: Bindings::default(),
+            constants: HashSet::new(),
             parent: None,
         }
     }
 
     pub fn extend(parent: Rc<RefCell<Env>>) -> Rc<RefCell<Env>> {
         Rc::new(RefCell::new(Env {
-            bindings: HashMap::new(),
+            bindings: Bindings::default(),
+            constants: HashSet::new(),
             parent: Some(parent),
         }))
     }
@@ -28,9 +66,30 @@ impl Env {
         self.bindings.insert(key, value);
     }
 
+    // Defines key unless this environment binds it to a constant.
+    pub fn define_checked(&mut self, key: GcId, value: Value) -> Result<(), SchemeError> {
+        if self.is_constant(key) {
+            return Err(SchemeError::EvalError("cannot reassign constant".to_string()));
+        }
+        self.define(key, value);
+        Ok(())
+    }
+
+    pub fn make_constant(&mut self, key: GcId) {
+        self.constants.insert(key);
+    }
+
+    pub fn is_constant(&self, key: GcId) -> bool {
+        self.constants.contains(&key)
+    }
+
+
     pub fn set_bang(&mut self, key: GcId, value: Value) -> Result<(), SchemeError> {
-        if self.bindings.contains_key(&key) {
-            self.bindings.insert(key, value);
+        if self.constants.contains(&key) {
+            return Err(SchemeError::EvalError("cannot reassign constant".to_string()));
+        }
+        if let Some(slot) = self.bindings.get_mut(&key) {
+            *slot = value;
             Ok(())
         } else {
             match &self.parent {
@@ -50,4 +109,12 @@ impl Env {
             }
         }
     }
-}
\ No newline at end of file
+
+    // The (symbol, value) bindings of this environment, not its parents, in
+    // GcId order.
+    pub fn bindings(&self) -> Vec<(GcId, Value)> {
+        let mut bindings: Vec<_> = self.bindings.iter().map(|(key, value)| (*key, *value)).collect();
+        bindings.sort_unstable_by_key(|(key, _)| *key);
+        bindings
+    }
+}
@@ -1,10 +1,13 @@
-use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+use std::{cell::RefCell, collections::{HashMap, HashSet}, fmt, rc::Rc, time::Instant};
 
 use crate::{
-    env::Env, interp::Interp, types::{GcId, SchemeError, SchemeObject, Value}
+    env::Env, interp::Interp, syntax, types::{GcId, Number, SchemeError, SchemeObject, Value}
 };
 
 pub type PrimitiveFn = fn(&Interp, &[Value]) -> Result<Value, SchemeError>;
+// The args a memoized procedure was called with, and the value it returned,
+// by the equal_hash of each arg. Args hashing the same are told apart with equal?.
+pub type MemoCache = Rc<RefCell<HashMap<Vec<u64>, Vec<(Vec<Value>, Value)>>>>;
 
 
 #[derive(Clone)]
@@ -14,16 +17,100 @@ pub struct Closure {
     env: Rc<RefCell<Env>>,
 }
 
+impl Closure {
+
+    pub fn param_count(&self) -> usize {
+        self.params.len()
+    }
+}
+
+// A syntax-rules transformer, the template of the first rule whose pattern
+// matches a use of the macro replaces it.
+pub struct SyntaxRules {
+    pub literals: Box<[GcId]>,
+    pub rules: Box<[(Value, Value)]>,
+    // A weak hash table from each use of the macro to its expansion, which
+    // is computed once rather than each time the use is evaluated.
+    pub expansions: Value,
+}
+
+// Hashable form of the eqv?-comparable values used as hash table keys.
+// Symbols are interned, so they hash cheaply by GcId like any other object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HashKey {
+    Int(i64),
+    Float(u64),
+    Char(u8),
+    Boolean(bool),
+    Object(GcId),
+    Nil,
+    Eof,
+    Unspecified,
+}
+
+impl From<Value> for HashKey {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Number(Number::Int(i)) => HashKey::Int(i),
+            Value::Number(Number::Float(f)) => HashKey::Float(f.to_bits()),
+            Value::Char(ch) => HashKey::Char(ch),
+            Value::Boolean(b) => HashKey::Boolean(b),
+            Value::Object(id) => HashKey::Object(id),
+            Value::Nil => HashKey::Nil,
+            Value::Eof => HashKey::Eof,
+            Value::Unspecified => HashKey::Unspecified,
+        }
+    }
+}
+
+impl From<HashKey> for Value {
+    fn from(key: HashKey) -> Self {
+        match key {
+            HashKey::Int(i) => Value::Number(Number::Int(i)),
+            HashKey::Float(bits) => Value::Number(Number::Float(f64::from_bits(bits))),
+            HashKey::Char(ch) => Value::Char(ch),
+            HashKey::Boolean(b) => Value::Boolean(b),
+            HashKey::Object(id) => Value::Object(id),
+            HashKey::Nil => Value::Nil,
+            HashKey::Eof => Value::Eof,
+            HashKey::Unspecified => Value::Unspecified,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum HeapObject {
     FreeSlot(GcId),
     Pair(Value, Value),
     List(Vec<Value>),
+    Vector(Vec<Value>),
+    Bytevector(Vec<u8>),
     Symbol(String),
     String(String),
     Primitive(PrimitiveFn),
     Closure(Box<Closure>),
-    NaryClosure(Box<Closure>)
+    NaryClosure(Box<Closure>),
+    // The thunk of a lazy promise, made by delay-force, yields another promise.
+    Promise { forced: bool, value: Value, thunk: Value, lazy: bool },
+    // Stands for the thunk of a stream-cons promise: forcing it evaluates the
+    // head and tail expressions in env into a pair.
+    DelayedPair { head: Value, tail: Value, env: Rc<RefCell<Env>> },
+    Continuation(usize),
+    Condition(Box<SchemeError>),
+    Values(Vec<Value>),
+    // The entries of a weak table only last as long as their key is live.
+    HashTable { table: HashMap<HashKey, Value>, weak: bool },
+    OutputString(String),
+    InputString { text: Rc<str>, position: usize },
+    InputBytevector { bytes: Vec<u8>, position: usize },
+    // The converter, if any, is applied to every value the parameter takes.
+    Parameter { value: Value, converter: Option<Value> },
+    Macro(Rc<SyntaxRules>),
+    // Caches the results of proc along with the args they were computed
+    // for, shared by the clones of the object made to apply it.
+    Memoized { proc: Value, cache: MemoCache },
+    // Calls proc with args followed by the args it is called with.
+    Curried { proc: Value, args: Vec<Value> },
     // Other heap-allocated object types can be added here
 }
 
@@ -34,11 +121,26 @@ impl HeapObject {
             Self::FreeSlot(_) => "FreeSlot",
             Self::Pair(..) => "Pair",
             Self::List(_) => "List",
+            Self::Vector(_) => "Vector",
+            Self::Bytevector(_) => "Bytevector",
             Self::Symbol(_) => "Symbol",
             Self::String(_) => "String",
             Self::Primitive(_) => "Primitive",
             Self::Closure(_) => "Closure",
             Self::NaryClosure(_) => "n-Closure",
+            Self::Promise { .. } => "Promise",
+            Self::DelayedPair { .. } => "DelayedPair",
+            Self::Continuation(_) => "Continuation",
+            Self::Condition(_) => "Condition",
+            Self::Macro(_) => "Macro",
+            Self::Values(_) => "Values",
+            Self::HashTable { .. } => "HashTable",
+            Self::OutputString(_) => "OutputString",
+            Self::InputString { .. } => "InputString",
+            Self::InputBytevector { .. } => "InputBytevector",
+            Self::Parameter { .. } => "Parameter",
+            Self::Memoized { .. } => "Memoized",
+            Self::Curried { .. } => "Curried",
         }
     }
 }
@@ -53,6 +155,26 @@ pub enum Keyword {
     True = 4,
     False = 5,
     SetBang = 6,
+    Delay = 7,
+    Guard = 8,
+    Cond = 9,
+    Case = 10,
+    And = 11,
+    Or = 12,
+    Parameterize = 13,
+    DelayForce = 14,
+    StreamCons = 15,
+    DefineValues = 16,
+    When = 17,
+    Unless = 18,
+    Begin = 19,
+    DefineSyntax = 20,
+    LetSyntax = 21,
+    LetrecSyntax = 22,
+    SyntaxRules = 23,
+    Quasiquote = 24,
+    DefineConstant = 25,
+    FluidLet = 26,
 }
 
 fn extract_param_ids(interp: &Interp, params: Value) -> Result<(Vec<GcId>, bool), SchemeError> {
@@ -75,6 +197,178 @@ fn extract_param_ids(interp: &Interp, params: Value) -> Result<(Vec<GcId>, bool)
     Ok((ids, is_nary))
 }
 
+fn eval_body(interp: &Interp, env: &Rc<RefCell<Env>>, body: &[Value]) -> Result<Value, SchemeError> {
+    let mut result = Value::Unspecified;
+    for expr in body {
+        result = expr.eval(interp, env)?;
+    }
+    Ok(result)
+}
+
+// What remains of evaluating a special form or applying a closure: either its
+// value, or an expression in tail position. The latter is evaluated by
+// eval_loop, so that tail calls run in bounded stack.
+enum Tail {
+    Value(Value),
+    Eval(Value, Rc<RefCell<Env>>),
+}
+
+impl Tail {
+    fn finish(self, interp: &Interp) -> Result<Value, SchemeError> {
+        match self {
+            Tail::Value(value) => Ok(value),
+            Tail::Eval(expr, env) => expr.eval(interp, &env),
+        }
+    }
+}
+
+// Evaluates all but the last expression of body, leaving it in tail position.
+fn eval_body_tail(interp: &Interp, env: &Rc<RefCell<Env>>, body: &[Value]) -> Result<Tail, SchemeError> {
+    match body {
+        [] => Ok(Tail::Value(Value::Unspecified)),
+        [init @ .., last] => {
+            for expr in init {
+                expr.eval(interp, env)?;
+            }
+            Ok(Tail::Eval(*last, Rc::clone(env)))
+        },
+    }
+}
+
+// Evaluates a selected clause body, where (=> proc) applies proc to the value
+// that selected the clause.
+fn eval_clause_body(interp: &Interp, env: &Rc<RefCell<Env>>, value: Value, body: &[Value]) -> Result<Tail, SchemeError> {
+    match body {
+        [arrow, proc] if *arrow == interp.lookup("=>") => {
+            let proc = proc.eval(interp, env)?;
+            proc.apply(interp, env, vec![value]).map(Tail::Value)
+        },
+        [arrow, ..] if *arrow == interp.lookup("=>") => Err(SchemeError::EvalError(
+            "=> expects exactly 1 procedure".to_string()
+        )),
+        _ => eval_body_tail(interp, env, body),
+    }
+}
+
+// Evaluates cond-style (test expr ...) clauses, returning None when no test
+// succeeds. A clause without expressions yields the value of its test.
+fn eval_clauses(interp: &Interp, env: &Rc<RefCell<Env>>, clauses: &[Value]) -> Result<Option<Tail>, SchemeError> {
+    let else_id = interp.to_symbol(interp.lookup("else"))?;
+    for clause in clauses {
+        let (test, body) = interp.to_pair(*clause)?;
+        let test_value = match test {
+            Value::Object(id) if id == else_id => Value::Boolean(true),
+            _ => test.eval(interp, env)?,
+        };
+        if test_value.is_truthy() {
+            let body = interp.list_to_vec(body)?;
+            if body.is_empty() {
+                return Ok(Some(Tail::Value(test_value)));
+            }
+            return eval_clause_body(interp, env, test_value, &body).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+// Builds the quasiquoted template, evaluating what is unquoted at depth 1.
+// Nested quasiquotes go one level deeper, and each unquote one level back.
+fn eval_quasiquote(interp: &Interp, env: &Rc<RefCell<Env>>, template: Value, depth: usize) -> Result<Value, SchemeError> {
+    let unquote = interp.lookup("unquote");
+    let unquote_splicing = interp.lookup("unquote-splicing");
+    // The operand of (keyword operand), a form made by the reader for `x ,x or ,@x.
+    let operand = |form: Value, keyword: Value| match interp.is_pair(form) {
+        Some((car, cdr)) if car == keyword => match interp.is_pair(cdr) {
+            Some((operand, Value::Nil)) => Some(operand),
+            _ => None,
+        },
+        _ => None,
+    };
+    let wrap = |keyword: Value, operand: Value| interp.heap.borrow_mut().alloc_list(&[keyword, operand]);
+
+    if let Some(operand) = operand(template, unquote) {
+        return if depth == 1 {
+            operand.eval(interp, env)
+        } else {
+            wrap(unquote, eval_quasiquote(interp, env, operand, depth - 1)?)
+        };
+    }
+    let quasiquote = Value::Object(Keyword::Quasiquote as usize);
+    if let Some(operand) = operand(template, quasiquote) {
+        return wrap(quasiquote, eval_quasiquote(interp, env, operand, depth + 1)?);
+    }
+    if let Some((car, cdr)) = interp.is_pair(template) {
+        // Unquoted expressions are evaluated left to right.
+        let items = match operand(car, unquote_splicing) {
+            Some(operand) if depth == 1 => interp.list_to_vec(operand.eval(interp, env)?)?,
+            Some(operand) => vec![wrap(unquote_splicing, eval_quasiquote(interp, env, operand, depth - 1)?)?],
+            None => vec![eval_quasiquote(interp, env, car, depth)?],
+        };
+        let rest = eval_quasiquote(interp, env, cdr, depth)?;
+        let mut heap = interp.heap.borrow_mut();
+        return items.iter().try_rfold(rest, |acc, item| heap.alloc_pair(*item, acc));
+    }
+    if let Some(items) = interp.is_vector(template).map(|items| items.to_vec()) {
+        let list = interp.heap.borrow_mut().alloc_list(&items)?;
+        let items = interp.list_to_vec(eval_quasiquote(interp, env, list, depth)?)?;
+        return interp.heap.borrow_mut().alloc_vector(items);
+    }
+    Ok(template)
+}
+
+// Evaluates spec, which must yield a syntax-rules transformer.
+fn eval_transformer(interp: &Interp, env: &Rc<RefCell<Env>>, name: &str, spec: Value) -> Result<Value, SchemeError> {
+    let transformer = spec.eval(interp, env)?;
+    match interp.is_macro(transformer) {
+        Some(_) => Ok(transformer),
+        None => Err(SchemeError::EvalError(format!("{} expects a syntax-rules transformer", name))),
+    }
+}
+
+// Warns about datums appearing in more than one case clause, since only the
+// first of those clauses can ever be selected.
+fn check_case_datums(interp: &Interp, clauses: &[&Vec<Value>]) -> Result<(), SchemeError> {
+    let mut seen: Vec<Value> = Vec::new();
+    for datums in clauses {
+        for datum in datums.iter() {
+            if seen.iter().any(|other| interp.is_eqv(*other, *datum)) {
+                interp.warn(&format!(
+                    "case: duplicate datum {} makes its later clause unreachable.", interp.write(*datum)
+                ))?;
+            }
+        }
+        seen.extend(datums.iter().copied());
+    }
+    Ok(())
+}
+
+fn eval_case(interp: &Interp, env: &Rc<RefCell<Env>>, key: Value, clauses: &[Value]) -> Result<Tail, SchemeError> {
+    let else_id = interp.to_symbol(interp.lookup("else"))?;
+    let mut parsed = Vec::new();
+    for clause in clauses {
+        let (datums, body) = interp.to_pair(*clause)?;
+        let datums = match datums {
+            Value::Object(id) if id == else_id => None,
+            _ => Some(interp.list_to_vec(datums)?),
+        };
+        parsed.push((datums, body));
+    }
+    let datums = parsed.iter()
+        .filter_map(|(datums, _)| datums.as_ref())
+        .collect::<Vec<_>>();
+    check_case_datums(interp, &datums)?;
+    for (datums, body) in parsed {
+        let selected = match datums {
+            None => true,
+            Some(datums) => datums.iter().any(|datum| interp.is_eqv(*datum, key)),
+        };
+        if selected {
+            return eval_clause_body(interp, env, key, &interp.list_to_vec(body)?);
+        }
+    }
+    Ok(Tail::Value(Value::Unspecified))
+}
+
 
 impl Keyword {
 
@@ -87,21 +381,57 @@ impl Keyword {
             4 => Some(Keyword::True),
             5 => Some(Keyword::False),
             6 => Some(Keyword::SetBang),
+            7 => Some(Keyword::Delay),
+            8 => Some(Keyword::Guard),
+            9 => Some(Keyword::Cond),
+            10 => Some(Keyword::Case),
+            11 => Some(Keyword::And),
+            12 => Some(Keyword::Or),
+            13 => Some(Keyword::Parameterize),
+            14 => Some(Keyword::DelayForce),
+            15 => Some(Keyword::StreamCons),
+            16 => Some(Keyword::DefineValues),
+            17 => Some(Keyword::When),
+            18 => Some(Keyword::Unless),
+            19 => Some(Keyword::Begin),
+            20 => Some(Keyword::DefineSyntax),
+            21 => Some(Keyword::LetSyntax),
+            22 => Some(Keyword::LetrecSyntax),
+            23 => Some(Keyword::SyntaxRules),
+            24 => Some(Keyword::Quasiquote),
+            25 => Some(Keyword::DefineConstant),
+            26 => Some(Keyword::FluidLet),
             _ => None,
         }
     }
 
-    fn eval(interp: &Interp, env: &Rc<RefCell<Env>>, keyword: Keyword, args: &[Value]) -> Result<Value, SchemeError> {
+    fn eval(interp: &Interp, env: &Rc<RefCell<Env>>, keyword: Keyword, args: &[Value]) -> Result<Tail, SchemeError> {
         match keyword {
             Keyword::If => {
-                if args.len() != 3 {
-                    return Err(SchemeError::EvalError("if expects exactly 3 arguments".to_string()));
+                if args.len() != 2 && args.len() != 3 {
+                    return Err(SchemeError::EvalError("if expects 2 or 3 arguments".to_string()));
+                }
+                if args[0].eval(interp, env)?.is_truthy() {
+                    Ok(Tail::Eval(args[1], Rc::clone(env)))
+                } else {
+                    // A one armed if has no value when its test fails.
+                    Ok(args.get(2).map_or(Tail::Value(Value::Unspecified), |alternative| {
+                        Tail::Eval(*alternative, Rc::clone(env))
+                    }))
                 }
-                let condition = args[0].eval(interp, env)?;
-                match condition {
-                    Value::Boolean(true) => args[1].eval(interp, env),
-                    Value::Boolean(false) => args[2].eval(interp, env),
-                    _ => Err(SchemeError::TypeError("if condition must evaluate to a boolean".to_string())),
+            }
+            Keyword::When | Keyword::Unless => {
+                let name = if matches!(keyword, Keyword::When) { "when" } else { "unless" };
+                match args {
+                    [test, body @ ..] => {
+                        let test = test.eval(interp, env)?.is_truthy();
+                        if test == matches!(keyword, Keyword::When) {
+                            eval_body_tail(interp, env, body)
+                        } else {
+                            Ok(Tail::Value(Value::Unspecified))
+                        }
+                    },
+                    _ => Err(SchemeError::EvalError(format!("{} expects at least 1 argument", name))),
                 }
             }
             Keyword::Define => {
@@ -111,30 +441,79 @@ impl Keyword {
                 let var = &args[0];
                 let value = args[1].eval(interp, env)?;
                 if let Value::Object(var_id) = var {
-                    env.borrow_mut().define(*var_id, value);
-                    Ok(value)
+                    if Rc::ptr_eq(env, &interp.env) {
+                        interp.check_redefinition(*var_id)?;
+                    }
+                    env.borrow_mut().define_checked(*var_id, value)?;
+                    Ok(Tail::Value(value))
                 } else {
                     Err(SchemeError::TypeError("set! first argument must be a variable".to_string()))
                 }
             }
+            Keyword::DefineConstant => {
+                if args.len() != 2 {
+                    return Err(SchemeError::EvalError("define-constant expects exactly 2 arguments".to_string()));
+                }
+                let var_id = interp.to_symbol(args[0])?;
+                let value = args[1].eval(interp, env)?;
+                if Rc::ptr_eq(env, &interp.env) {
+                    interp.check_redefinition(var_id)?;
+                }
+                let mut env = env.borrow_mut();
+                env.define_checked(var_id, value)?;
+                env.make_constant(var_id);
+                Ok(Tail::Value(value))
+            }
+            Keyword::DefineValues => {
+                if args.len() != 2 {
+                    return Err(SchemeError::EvalError("define-values expects exactly 2 arguments".to_string()));
+                }
+                // Formals are as for lambda: (a b), (a b . rest) or a lone rest symbol.
+                let (params, is_nary) = match interp.is_pair(args[0]) {
+                    Some(_) => extract_param_ids(interp, args[0])?,
+                    None if interp.is_nil(args[0]) => (Vec::new(), false),
+                    None => (vec![interp.to_symbol(args[0])?], true),
+                };
+                let value = args[1].eval(interp, env)?;
+                let values = interp.is_values(value).unwrap_or_else(|| vec![value]);
+                let required = if is_nary { params.len() - 1 } else { params.len() };
+                if values.len() < required || (!is_nary && values.len() > required) {
+                    return Err(SchemeError::ArgCountError(format!(
+                        "define-values: expected {} values, but got {}", required, values.len()
+                    )));
+                }
+                let mut env = env.borrow_mut();
+                // Binds none of the formals when any of them is a constant.
+                if params.iter().any(|id| env.is_constant(*id)) {
+                    return Err(SchemeError::EvalError("cannot reassign constant".to_string()));
+                }
+                for (id, value) in params.iter().zip(values.iter()).take(required) {
+                    env.define_checked(*id, *value)?;
+                }
+                if is_nary {
+                    let rest = interp.heap.borrow_mut().alloc_list(&values[required..])?;
+                    env.define_checked(params[required], rest)?;
+                }
+                Ok(Tail::Value(Value::Unspecified))
+            }
             Keyword::Lambda => {
                 match args {
                     [params_value, body @ ..] => {
                         let (params, is_nary) = extract_param_ids(interp, *params_value)?;
                         let mut heap = interp.heap.borrow_mut();
-                        if is_nary {
-                            Ok(heap.alloc_nary_closure(Closure {
+                        Ok(Tail::Value(if is_nary {
+                            heap.alloc_nary_closure(Closure {
                                 params: params.into_boxed_slice(),
                                 body: body.to_vec().into_boxed_slice(),
-                                env: Rc::clone(&interp.env),
-                            }))
+                                env: Rc::clone(env),
+                            })?
                         } else {
-                            Ok(heap.alloc_closure(Closure {
+                            heap.alloc_closure(Closure {
                                 params: params.into_boxed_slice(),
                                 body: body.to_vec().into_boxed_slice(),
-                                env: Rc::clone(&interp.env),
-                            }))
-                        }
+                                env: Rc::clone(env),
+                            })?
+                        }))
                     },
                     _ => Err(SchemeError::EvalError("lambda expects at least 2 arguments".to_string())),
                 }
@@ -143,7 +522,14 @@ impl Keyword {
                 if args.len() != 1 {
                     return Err(SchemeError::EvalError("quote expects exactly 1 argument".to_string()));
                 }
-                Ok(args[0])
+                // The datum was built once by the reader, it is shared and never copied.
+                Ok(Tail::Value(args[0]))
+            }
+            Keyword::Quasiquote => {
+                if args.len() != 1 {
+                    return Err(SchemeError::EvalError("quasiquote expects exactly 1 argument".to_string()));
+                }
+                eval_quasiquote(interp, env, args[0], 1).map(Tail::Value)
             }
             Keyword::SetBang => {
                 if args.len() != 2 {
@@ -153,22 +539,288 @@ impl Keyword {
                 let value = args[1].eval(interp, env)?;
                 if let Value::Object(var_id) = var {
                     env.borrow_mut().set_bang(*var_id, value)?;
-                    Ok(value)
+                    Ok(Tail::Value(Value::Unspecified))
                 } else {
                     Err(SchemeError::TypeError("set! first argument must be a variable".to_string()))
                 }
             }
+            Keyword::Delay | Keyword::DelayForce => {
+                let name = if matches!(keyword, Keyword::Delay) { "delay" } else { "delay-force" };
+                if args.len() != 1 {
+                    return Err(SchemeError::EvalError(format!("{} expects exactly 1 argument", name)));
+                }
+                let mut heap = interp.heap.borrow_mut();
+                let thunk = heap.alloc_closure(Closure {
+                    params: Box::new([]),
+                    body: Box::new([args[0]]),
+                    env: Rc::clone(env),
+                })?;
+                Ok(Tail::Value(heap.alloc_promise(thunk, matches!(keyword, Keyword::DelayForce))?))
+            }
+            Keyword::StreamCons => {
+                if args.len() != 2 {
+                    return Err(SchemeError::EvalError("stream-cons expects exactly 2 arguments".to_string()));
+                }
+                // Neither the head nor the tail is evaluated until the stream is forced.
+                let mut heap = interp.heap.borrow_mut();
+                let thunk = heap.alloc(HeapObject::DelayedPair { head: args[0], tail: args[1], env: Rc::clone(env) })?;
+                Ok(Tail::Value(heap.alloc_promise(thunk, false)?))
+            }
+            Keyword::Cond => {
+                Ok(eval_clauses(interp, env, args)?.unwrap_or(Tail::Value(Value::Unspecified)))
+            }
+            Keyword::Case => {
+                match args {
+                    [key, clauses @ ..] => {
+                        let key = key.eval(interp, env)?;
+                        eval_case(interp, env, key, clauses)
+                    },
+                    _ => Err(SchemeError::EvalError("case expects at least 1 argument".to_string())),
+                }
+            }
+            Keyword::And | Keyword::Or => {
+                // The last operand is in tail position, the others stop early.
+                let is_and = matches!(keyword, Keyword::And);
+                match args {
+                    [] => Ok(Tail::Value(Value::Boolean(is_and))),
+                    [init @ .., last] => {
+                        for arg in init {
+                            let result = arg.eval(interp, env)?;
+                            if result.is_truthy() != is_and {
+                                return Ok(Tail::Value(result));
+                            }
+                        }
+                        Ok(Tail::Eval(*last, Rc::clone(env)))
+                    },
+                }
+            }
+            Keyword::Begin => eval_body_tail(interp, env, args),
+            Keyword::SyntaxRules => {
+                match args {
+                    [literals, rules @ ..] => {
+                        let literals = interp.list_to_vec(*literals)?.into_iter()
+                            .map(|literal| interp.to_symbol(literal))
+                            .collect::<Result<Vec<GcId>, SchemeError>>()?;
+                        let rules = rules.iter()
+                            .map(|rule| match interp.list_to_vec(*rule)?.as_slice() {
+                                [pattern, template] if interp.is_pair(*pattern).is_some() => Ok((*pattern, *template)),
+                                _ => Err(SchemeError::EvalError(
+                                    "syntax-rules expects (pattern template) rules".to_string()
+                                )),
+                            })
+                            .collect::<Result<Vec<(Value, Value)>, SchemeError>>()?;
+                        let mut heap = interp.heap.borrow_mut();
+                        let expansions = heap.alloc_hash_table(true)?;
+                        Ok(Tail::Value(heap.alloc_macro(SyntaxRules {
+                            literals: literals.into_boxed_slice(),
+                            rules: rules.into_boxed_slice(),
+                            expansions,
+                        })?))
+                    },
+                    _ => Err(SchemeError::EvalError("syntax-rules expects at least 1 argument".to_string())),
+                }
+            }
+            Keyword::DefineSyntax => {
+                match args {
+                    [name, spec] => {
+                        let name = interp.to_symbol(*name)?;
+                        let transformer = eval_transformer(interp, env, "define-syntax", *spec)?;
+                        env.borrow_mut().define(name, transformer);
+                        Ok(Tail::Value(Value::Unspecified))
+                    },
+                    _ => Err(SchemeError::EvalError("define-syntax expects exactly 2 arguments".to_string())),
+                }
+            }
+            Keyword::LetSyntax | Keyword::LetrecSyntax => {
+                let name = if matches!(keyword, Keyword::LetSyntax) { "let-syntax" } else { "letrec-syntax" };
+                match args {
+                    [bindings, body @ ..] => {
+                        // The macros are only bound within the body. Expansions are
+                        // evaluated where the macro is used, so letrec-syntax only
+                        // differs in the environment its transformers are built in.
+                        let body_env = Env::extend(Rc::clone(env));
+                        let spec_env = if matches!(keyword, Keyword::LetSyntax) { env } else { &body_env };
+                        for binding in interp.list_to_vec(*bindings)? {
+                            match interp.list_to_vec(binding)?.as_slice() {
+                                [keyword, spec] => {
+                                    let keyword = interp.to_symbol(*keyword)?;
+                                    let transformer = eval_transformer(interp, spec_env, name, *spec)?;
+                                    body_env.borrow_mut().define(keyword, transformer);
+                                },
+                                _ => return Err(SchemeError::EvalError(format!(
+                                    "{} expects (keyword transformer) bindings", name
+                                ))),
+                            }
+                        }
+                        eval_body_tail(interp, &body_env, body)
+                    },
+                    _ => Err(SchemeError::EvalError(format!("{} expects at least 1 argument", name))),
+                }
+            }
+            Keyword::Parameterize => {
+                match args {
+                    [bindings, body @ ..] => {
+                        let mut params = Vec::new();
+                        for binding in interp.list_to_vec(*bindings)? {
+                            match interp.list_to_vec(binding)?.as_slice() {
+                                [param, value] => {
+                                    let id = interp.to_object(param.eval(interp, env)?)?;
+                                    let value = interp.convert_parameter(id, value.eval(interp, env)?)?;
+                                    params.push((id, value));
+                                },
+                                _ => return Err(SchemeError::EvalError(
+                                    "parameterize expects (parameter value) bindings".to_string()
+                                )),
+                            }
+                        }
+                        // Installs the new values, then restores the old ones on exit, error or not.
+                        let mut heap = interp.heap.borrow_mut();
+                        for (id, value) in params.iter_mut() {
+                            std::mem::swap(heap.parameter_mut(*id)?, value);
+                        }
+                        drop(heap);
+                        let result = eval_body(interp, env, body);
+                        let mut heap = interp.heap.borrow_mut();
+                        for (id, value) in params.iter().rev() {
+                            *heap.parameter_mut(*id)? = *value;
+                        }
+                        result.map(Tail::Value)
+                    },
+                    _ => Err(SchemeError::EvalError("parameterize expects at least 1 argument".to_string())),
+                }
+            }
+            Keyword::FluidLet => {
+                match args {
+                    [bindings, body @ ..] => {
+                        let mut vars = Vec::new();
+                        for binding in interp.list_to_vec(*bindings)? {
+                            match interp.list_to_vec(binding)?.as_slice() {
+                                [var, value] => {
+                                    let id = interp.to_symbol(*var)?;
+                                    if env.borrow().lookup(id).is_none() {
+                                        return Err(SchemeError::UnboundVariable(format!(
+                                            "fluid-let: unbound variable {}", interp.write(*var)
+                                        )));
+                                    }
+                                    vars.push((id, value.eval(interp, env)?));
+                                },
+                                _ => return Err(SchemeError::EvalError(
+                                    "fluid-let expects (variable value) bindings".to_string()
+                                )),
+                            }
+                        }
+                        // Assigns the new values, then restores the old ones on exit, error or not.
+                        let restore = |assigned: &[(GcId, Value)]| -> Result<(), SchemeError> {
+                            for (id, value) in assigned.iter().rev() {
+                                env.borrow_mut().set_bang(*id, *value)?;
+                            }
+                            Ok(())
+                        };
+                        for i in 0..vars.len() {
+                            let (id, value) = vars[i];
+                            let old = env.borrow().lookup(id).unwrap_or(Value::Unspecified);
+                            let assigned = env.borrow_mut().set_bang(id, value);
+                            if let Err(error) = assigned {
+                                restore(&vars[..i])?;
+                                return Err(error);
+                            }
+                            vars[i].1 = old;
+                        }
+                        let result = eval_body(interp, env, body);
+                        restore(&vars)?;
+                        result.map(Tail::Value)
+                    },
+                    _ => Err(SchemeError::EvalError("fluid-let expects at least 1 argument".to_string())),
+                }
+            }
+            Keyword::Guard => {
+                match args {
+                    [spec, body @ ..] => {
+                        let (var, clauses) = interp.to_pair(*spec)?;
+                        let var_id = interp.to_symbol(var)?;
+                        match interp.with_guard(|| eval_body(interp, env, body)) {
+                            // Continuations are not errors, let them unwind.
+                            Err(error) if !matches!(error, SchemeError::Continuation(..)) => {
+                                let condition = match error {
+                                    SchemeError::Raised(value) => value,
+                                    _ => interp.heap.borrow_mut().alloc_condition(error.clone())?,
+                                };
+                                let guard_env = Env::extend(Rc::clone(env));
+                                guard_env.borrow_mut().define(var_id, condition);
+                                let clauses = interp.list_to_vec(clauses)?;
+                                match eval_clauses(interp, &guard_env, &clauses)? {
+                                    Some(tail) => tail.finish(interp).map(Tail::Value),
+                                    // Raised again, for the handlers outside of the guard.
+                                    None => interp.raise(error, false).map(Tail::Value),
+                                }
+                            },
+                            result => result.map(Tail::Value),
+                        }
+                    },
+                    _ => Err(SchemeError::EvalError("guard expects at least 1 argument".to_string())),
+                }
+            }
             _ => {
-                return Err(SchemeError::EvalError("not implemented".to_string()));
+                Err(SchemeError::EvalError("not implemented".to_string()))
             }
         }
     }
 }
 
 
+// The objects found reachable so far by Heap::collect, and those whose
+// children remain to be marked.
+struct Marker {
+    marked: Vec<bool>,
+    pending: Vec<GcId>,
+    envs: HashSet<*const RefCell<Env>>,
+}
+
+impl Marker {
+
+    fn mark(&mut self, value: Value) {
+        if let Value::Object(id) = value
+            && !self.marked[id] {
+            self.marked[id] = true;
+            self.pending.push(id);
+        }
+    }
+
+    fn mark_env(&mut self, env: &Rc<RefCell<Env>>) {
+        if !self.envs.insert(Rc::as_ptr(env)) {
+            return;
+        }
+        let env = env.borrow();
+        env.bindings().into_iter().for_each(|(_, value)| self.mark(value));
+        if let Some(parent) = &env.parent {
+            self.mark_env(parent);
+        }
+    }
+
+    // Whether the entries under key are still reachable.
+    fn is_live(&self, key: &HashKey) -> bool {
+        match key {
+            HashKey::Object(id) => self.marked[*id],
+            _ => true,
+        }
+    }
+}
+
 pub struct Heap {
     objects: Vec<HeapObject>,
     symbols: HashMap<String, GcId>,
+    // The slots freed by collect, reused by the next allocations.
+    free: Vec<GcId>,
+    allocations: usize,
+    max_size: Option<usize>,
+    // Objects found live by the last collection.
+    live: usize,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Heap {
@@ -177,6 +829,10 @@ impl Heap {
         let mut heap = Self {
             objects: Vec::new(),
             symbols: HashMap::new(),
+            free: Vec::new(),
+            allocations: 0,
+            max_size: None,
+            live: 0,
         };
         // Pre-intern keywords
         heap.intern_special_keywwords();
@@ -198,6 +854,46 @@ impl Heap {
         assert!(false_id == Keyword::False as usize, "Keyword '#f' should have GcId 5");
         let set_bang_id = self.intern_symbol_to_gcid("set!");
         assert!(set_bang_id == Keyword::SetBang as usize, "Keyword 'set!' should have GcId 6");
+        let delay_id = self.intern_symbol_to_gcid("delay");
+        assert!(delay_id == Keyword::Delay as usize, "Keyword 'delay' should have GcId 7");
+        let guard_id = self.intern_symbol_to_gcid("guard");
+        assert!(guard_id == Keyword::Guard as usize, "Keyword 'guard' should have GcId 8");
+        let cond_id = self.intern_symbol_to_gcid("cond");
+        assert!(cond_id == Keyword::Cond as usize, "Keyword 'cond' should have GcId 9");
+        let case_id = self.intern_symbol_to_gcid("case");
+        assert!(case_id == Keyword::Case as usize, "Keyword 'case' should have GcId 10");
+        let and_id = self.intern_symbol_to_gcid("and");
+        assert!(and_id == Keyword::And as usize, "Keyword 'and' should have GcId 11");
+        let or_id = self.intern_symbol_to_gcid("or");
+        assert!(or_id == Keyword::Or as usize, "Keyword 'or' should have GcId 12");
+        let parameterize_id = self.intern_symbol_to_gcid("parameterize");
+        assert!(parameterize_id == Keyword::Parameterize as usize, "Keyword 'parameterize' should have GcId 13");
+        let delay_force_id = self.intern_symbol_to_gcid("delay-force");
+        assert!(delay_force_id == Keyword::DelayForce as usize, "Keyword 'delay-force' should have GcId 14");
+        let stream_cons_id = self.intern_symbol_to_gcid("stream-cons");
+        assert!(stream_cons_id == Keyword::StreamCons as usize, "Keyword 'stream-cons' should have GcId 15");
+        let define_values_id = self.intern_symbol_to_gcid("define-values");
+        assert!(define_values_id == Keyword::DefineValues as usize, "Keyword 'define-values' should have GcId 16");
+        let when_id = self.intern_symbol_to_gcid("when");
+        assert!(when_id == Keyword::When as usize, "Keyword 'when' should have GcId 17");
+        let unless_id = self.intern_symbol_to_gcid("unless");
+        assert!(unless_id == Keyword::Unless as usize, "Keyword 'unless' should have GcId 18");
+        let begin_id = self.intern_symbol_to_gcid("begin");
+        assert!(begin_id == Keyword::Begin as usize, "Keyword 'begin' should have GcId 19");
+        let define_syntax_id = self.intern_symbol_to_gcid("define-syntax");
+        assert!(define_syntax_id == Keyword::DefineSyntax as usize, "Keyword 'define-syntax' should have GcId 20");
+        let let_syntax_id = self.intern_symbol_to_gcid("let-syntax");
+        assert!(let_syntax_id == Keyword::LetSyntax as usize, "Keyword 'let-syntax' should have GcId 21");
+        let letrec_syntax_id = self.intern_symbol_to_gcid("letrec-syntax");
+        assert!(letrec_syntax_id == Keyword::LetrecSyntax as usize, "Keyword 'letrec-syntax' should have GcId 22");
+        let syntax_rules_id = self.intern_symbol_to_gcid("syntax-rules");
+        assert!(syntax_rules_id == Keyword::SyntaxRules as usize, "Keyword 'syntax-rules' should have GcId 23");
+        let quasiquote_id = self.intern_symbol_to_gcid("quasiquote");
+        assert!(quasiquote_id == Keyword::Quasiquote as usize, "Keyword 'quasiquote' should have GcId 24");
+        let define_constant_id = self.intern_symbol_to_gcid("define-constant");
+        assert!(define_constant_id == Keyword::DefineConstant as usize, "Keyword 'define-constant' should have GcId 25");
+        let fluid_let_id = self.intern_symbol_to_gcid("fluid-let");
+        assert!(fluid_let_id == Keyword::FluidLet as usize, "Keyword 'fluid-let' should have GcId 26");
     }
 
     pub fn get(&self, id: GcId) -> &HeapObject {
@@ -210,7 +906,7 @@ impl Heap {
 
     fn intern_symbol_to_gcid(&mut self, name: &str) -> GcId {
         if let Some(&id) = self.symbols.get(name) {
-            return id;
+            id
         } else {
             let id: GcId = self.objects.len();
             self.objects.push(HeapObject::Symbol(name.to_string()));
@@ -223,15 +919,193 @@ impl Heap {
         Value::Object(self.intern_symbol_to_gcid(name))
     }
 
-    pub fn alloc_pair(&mut self, car: Value, cdr: Value) -> Value {
+    // Reuses a free slot if any, and only grows the heap up to its maximum size.
+    fn alloc(&mut self, obj: HeapObject) -> Result<Value, SchemeError> {
+        if let Some(id) = self.free.pop() {
+            self.allocations += 1;
+            self.objects[id] = obj;
+            return Ok(Value::Object(id));
+        }
+        if self.max_size.is_some_and(|max_size| self.objects.len() >= max_size) {
+            return Err(SchemeError::EvalError("heap exhausted".to_string()));
+        }
+        self.allocations += 1;
         let id: GcId = self.objects.len();
-        self.objects.push(HeapObject::Pair(car, cdr));
-        Value::Object(id)
+        self.objects.push(obj);
+        Ok(Value::Object(id))
+    }
+
+    // Number of objects on the heap, interned symbols included.
+    pub fn size(&self) -> usize {
+        self.objects.len()
+    }
+
+    // Number of free slots, awaiting reuse, included in the size.
+    pub fn free_count(&self) -> usize {
+        self.objects.iter().filter(|obj| matches!(obj, HeapObject::FreeSlot(_))).count()
+    }
+
+    pub fn symbol_count(&self) -> usize {
+        self.symbols.len()
+    }
+
+    // Drops every object, symbols included, added since the heap held size
+    // objects. Values still referring to them must not be used anymore.
+    pub fn truncate(&mut self, size: usize) {
+        self.objects.truncate(size);
+        self.symbols.retain(|_, id| *id < size);
+        self.free.retain(|id| *id < size);
+        self.live = self.live.min(size);
+    }
+
+    // Frees every object that can't be reached from the roots or from the
+    // bindings of envs, returning how many were freed. Symbols stay interned
+    // and are never freed. No Value to a freed object may be used afterwards,
+    // the caller must make sure they are all among the roots.
+    pub fn collect(&mut self, roots: &[Value], envs: &[Rc<RefCell<Env>>]) -> usize {
+        let mut marker = Marker {
+            marked: self.objects.iter().map(|obj| matches!(obj, HeapObject::Symbol(_))).collect(),
+            pending: Vec::new(),
+            envs: HashSet::new(),
+        };
+        roots.iter().for_each(|root| marker.mark(*root));
+        envs.iter().for_each(|env| marker.mark_env(env));
+        let mut weak_tables = Vec::new();
+        loop {
+            while let Some(id) = marker.pending.pop() {
+                if matches!(self.objects[id], HeapObject::HashTable { weak: true, .. }) {
+                    weak_tables.push(id);
+                } else {
+                    self.mark_children(id, &mut marker);
+                }
+            }
+            // A weak table keeps the values of its live keys alive, which may
+            // in turn keep other keys alive.
+            for id in &weak_tables {
+                if let HeapObject::HashTable { table, .. } = &self.objects[*id] {
+                    for (key, value) in table {
+                        if marker.is_live(key) {
+                            marker.mark(*value);
+                        }
+                    }
+                }
+            }
+            if marker.pending.is_empty() {
+                break;
+            }
+        }
+        for id in weak_tables {
+            if let HeapObject::HashTable { table, .. } = &mut self.objects[id] {
+                table.retain(|key, _| marker.is_live(key));
+            }
+        }
+        let mut freed = 0;
+        for (id, obj) in self.objects.iter_mut().enumerate() {
+            if !marker.marked[id] && !matches!(obj, HeapObject::FreeSlot(_)) {
+                *obj = HeapObject::FreeSlot(id);
+                self.free.push(id);
+                freed += 1;
+            }
+        }
+        self.live = self.objects.len() - self.free.len();
+        freed
+    }
+
+    fn mark_children(&self, id: GcId, marker: &mut Marker) {
+        match &self.objects[id] {
+            HeapObject::Pair(car, cdr) => {
+                marker.mark(*car);
+                marker.mark(*cdr);
+            },
+            HeapObject::List(items) | HeapObject::Vector(items) | HeapObject::Values(items) => {
+                items.iter().for_each(|item| marker.mark(*item));
+            },
+            HeapObject::Closure(closure) | HeapObject::NaryClosure(closure) => {
+                closure.body.iter().for_each(|expr| marker.mark(*expr));
+                marker.mark_env(&closure.env);
+            },
+            HeapObject::Promise { value, thunk, .. } => {
+                marker.mark(*value);
+                marker.mark(*thunk);
+            },
+            HeapObject::DelayedPair { head, tail, env } => {
+                marker.mark(*head);
+                marker.mark(*tail);
+                marker.mark_env(env);
+            },
+            HeapObject::Condition(error) => {
+                error.values().into_iter().for_each(|value| marker.mark(value));
+            },
+            HeapObject::HashTable { table, .. } => {
+                for (key, value) in table {
+                    if let HashKey::Object(key) = key {
+                        marker.mark(Value::Object(*key));
+                    }
+                    marker.mark(*value);
+                }
+            },
+            HeapObject::Parameter { value, converter } => {
+                marker.mark(*value);
+                converter.iter().for_each(|converter| marker.mark(*converter));
+            },
+            HeapObject::Macro(rules) => {
+                for (pattern, template) in &rules.rules {
+                    marker.mark(*pattern);
+                    marker.mark(*template);
+                }
+                marker.mark(rules.expansions);
+            },
+            HeapObject::Memoized { proc, cache } => {
+                marker.mark(*proc);
+                for (args, value) in cache.borrow().values().flatten() {
+                    args.iter().for_each(|arg| marker.mark(*arg));
+                    marker.mark(*value);
+                }
+            },
+            HeapObject::Curried { proc, args } => {
+                marker.mark(*proc);
+                args.iter().for_each(|arg| marker.mark(*arg));
+            },
+            HeapObject::FreeSlot(_) | HeapObject::Bytevector(_) | HeapObject::Symbol(_)
+            | HeapObject::String(_) | HeapObject::Primitive(_) | HeapObject::Continuation(_)
+            | HeapObject::OutputString(_) | HeapObject::InputString { .. }
+            | HeapObject::InputBytevector { .. } => {},
+        }
+    }
+
+    // Caps the number of objects on the heap, None for no cap. Allocations
+    // past the cap fail with a "heap exhausted" error.
+    pub fn set_max_size(&mut self, max_size: Option<usize>) {
+        self.max_size = max_size;
+    }
+
+    // Whether a capped heap is worth collecting before it runs out: once half
+    // the room left by the last collection has been allocated.
+    pub fn wants_collection(&self) -> bool {
+        self.max_size.is_some_and(|max_size| {
+            let used = self.objects.len() - self.free.len();
+            used >= self.live + max_size.saturating_sub(self.live) / 2
+        })
+    }
+
+    // Whether the live objects fill the heap up to its maximum size, free
+    // slots left by a collection don't count.
+    pub fn is_exhausted(&self) -> bool {
+        self.max_size.is_some_and(|max_size| self.objects.len() - self.free.len() >= max_size)
+    }
+
+    // Number of objects allocated so far, interned symbols excepted.
+    pub fn allocation_count(&self) -> usize {
+        self.allocations
+    }
+
+    pub fn alloc_pair(&mut self, car: Value, cdr: Value) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::Pair(car, cdr))
     }
 
     pub fn last(&self, car: Value) -> Result<Value, SchemeError> {
         let mut tail = car;
-        while let Value::Object(id) = car {
+        while let Value::Object(id) = tail {
             match self.get(id) {
                 HeapObject::Pair(_, cdr) => {
                     if matches!(cdr, Value::Nil) {
@@ -243,9 +1117,21 @@ impl Heap {
                 _ => break
             }
         } 
-        return Err(SchemeError::TypeError(format!(
-                "Expected a Pair, but got a {}.", car.type_name()
-            )));
+        Err(SchemeError::TypeError(format!(
+                "Expected a Pair, but got a {}.", tail.type_name()
+            )))
+    }
+
+    pub fn setcar(&mut self, id: GcId, value: Value) -> Result<Value, SchemeError> {
+        match self.get_mut(id) {
+            HeapObject::Pair(car, _) => {
+                *car = value;
+                Ok(value)
+            },
+            obj => Err(SchemeError::TypeError(format!(
+                "Expected a Pair, but got a {} instead.", obj.type_name()
+            )))
+        }
     }
 
     pub fn setcdr(&mut self, id: GcId, value: Value) -> Result<Value, SchemeError> {
@@ -260,34 +1146,215 @@ impl Heap {
         }
     }
 
-    pub fn alloc_list(&mut self, items: &[Value]) -> Value {
-        items.into_iter().rfold(Value::Nil, |acc, val| {
+    pub fn alloc_list(&mut self, items: &[Value]) -> Result<Value, SchemeError> {
+        items.iter().try_rfold(Value::Nil, |acc, val| {
             self.alloc_pair(*val, acc)
         })
     }
 
-    pub fn alloc_string(&mut self, s: impl Into<String>) -> Value {
-        let id: GcId = self.objects.len();
-        self.objects.push(HeapObject::String(s.into()));
-        Value::Object(id)
+    pub fn alloc_vector(&mut self, items: Vec<Value>) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::Vector(items))
+    }
+
+    pub fn alloc_bytevector(&mut self, bytes: Vec<u8>) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::Bytevector(bytes))
     }
 
-    pub fn alloc_primitive(&mut self, func: PrimitiveFn) -> Value {
+    pub fn bytevector_mut(&mut self, id: GcId) -> Result<&mut Vec<u8>, SchemeError> {
+        match self.get_mut(id) {
+            HeapObject::Bytevector(bytes) => Ok(bytes),
+            obj => Err(SchemeError::TypeError(format!(
+                "Expected a Bytevector, but got a {} instead.", obj.type_name()
+            )))
+        }
+    }
+
+    pub fn alloc_string(&mut self, s: impl Into<String>) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::String(s.into()))
+    }
+
+    pub fn alloc_primitive(&mut self, func: PrimitiveFn) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::Primitive(func))
+    }
+
+    // Allocates a primitive the interpreter is defined with. Like interning a
+    // symbol this never fails, and may take the heap past its maximum size.
+    pub fn define_primitive(&mut self, func: PrimitiveFn) -> Value {
+        self.allocations += 1;
         let id: GcId = self.objects.len();
         self.objects.push(HeapObject::Primitive(func));
         Value::Object(id)
     }
 
-    pub fn alloc_closure(&mut self, closure: Closure) -> Value {
-        let id: GcId = self.objects.len();
-        self.objects.push(HeapObject::Closure(Box::new(closure)));
-        Value::Object(id)
+    pub fn alloc_closure(&mut self, closure: Closure) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::Closure(Box::new(closure)))
     }
 
-    pub fn alloc_nary_closure(&mut self, closure: Closure) -> Value {
-        let id: GcId = self.objects.len();
-        self.objects.push(HeapObject::NaryClosure(Box::new(closure)));
-        Value::Object(id)
+    pub fn alloc_nary_closure(&mut self, closure: Closure) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::NaryClosure(Box::new(closure)))
+    }
+
+    pub fn alloc_parameter(&mut self, value: Value, converter: Option<Value>) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::Parameter { value, converter })
+    }
+
+    pub fn parameter_mut(&mut self, id: GcId) -> Result<&mut Value, SchemeError> {
+        match self.get_mut(id) {
+            HeapObject::Parameter { value, .. } => Ok(value),
+            obj => Err(SchemeError::TypeError(format!(
+                "Expected a Parameter, but got a {} instead.", obj.type_name()
+            )))
+        }
+    }
+
+    pub fn alloc_promise(&mut self, thunk: Value, lazy: bool) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::Promise { forced: false, value: Value::Nil, thunk, lazy })
+    }
+
+    // A promise of the call (func arg ...), with args evaluated in env once forced.
+    pub fn alloc_delayed_call(&mut self, func: Value, args: &[Value], env: &Rc<RefCell<Env>>, lazy: bool) -> Result<Value, SchemeError> {
+        let mut call = vec![func];
+        call.extend_from_slice(args);
+        let call = self.alloc_list(&call)?;
+        let thunk = self.alloc_closure(Closure {
+            params: Box::new([]),
+            body: Box::new([call]),
+            env: Rc::clone(env),
+        })?;
+        self.alloc_promise(thunk, lazy)
+    }
+
+    pub fn alloc_resolved_promise(&mut self, value: Value) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::Promise { forced: true, value, thunk: Value::Nil, lazy: false })
+    }
+
+    pub fn alloc_continuation(&mut self, escape_id: usize) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::Continuation(escape_id))
+    }
+
+    pub fn alloc_values(&mut self, values: &[Value]) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::Values(values.to_vec()))
+    }
+
+    pub fn string_mut(&mut self, id: GcId) -> Result<&mut String, SchemeError> {
+        match self.get_mut(id) {
+            HeapObject::String(s) => Ok(s),
+            obj => Err(SchemeError::TypeError(format!(
+                "Expected a String, but got a {} instead.", obj.type_name()
+            )))
+        }
+    }
+
+    pub fn alloc_hash_table(&mut self, weak: bool) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::HashTable { table: HashMap::new(), weak })
+    }
+
+    pub fn hash_table_mut(&mut self, id: GcId) -> Result<&mut HashMap<HashKey, Value>, SchemeError> {
+        match self.get_mut(id) {
+            HeapObject::HashTable { table, .. } => Ok(table),
+            obj => Err(SchemeError::TypeError(format!(
+                "Expected a HashTable, but got a {} instead.", obj.type_name()
+            )))
+        }
+    }
+
+    pub fn alloc_output_string(&mut self) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::OutputString(String::new()))
+    }
+
+    pub fn output_string_mut(&mut self, id: GcId) -> Result<&mut String, SchemeError> {
+        match self.get_mut(id) {
+            HeapObject::OutputString(buffer) => Ok(buffer),
+            obj => Err(SchemeError::TypeError(format!(
+                "Expected an OutputString port, but got a {} instead.", obj.type_name()
+            )))
+        }
+    }
+
+    pub fn alloc_input_string(&mut self, text: impl Into<Rc<str>>) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::InputString { text: text.into(), position: 0 })
+    }
+
+    // The text is shared, so that it can be read without holding the heap.
+    pub fn input_string_mut(&mut self, id: GcId) -> Result<(&Rc<str>, &mut usize), SchemeError> {
+        match self.get_mut(id) {
+            HeapObject::InputString { text, position } => Ok((text, position)),
+            obj => Err(SchemeError::TypeError(format!(
+                "Expected an InputString port, but got a {} instead.", obj.type_name()
+            )))
+        }
+    }
+
+    pub fn alloc_input_bytevector(&mut self, bytes: Vec<u8>) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::InputBytevector { bytes, position: 0 })
+    }
+
+    pub fn input_bytevector_mut(&mut self, id: GcId) -> Result<(&[u8], &mut usize), SchemeError> {
+        match self.get_mut(id) {
+            HeapObject::InputBytevector { bytes, position } => Ok((bytes, position)),
+            obj => Err(SchemeError::TypeError(format!(
+                "Expected an InputBytevector port, but got a {} instead.", obj.type_name()
+            )))
+        }
+    }
+
+    pub fn alloc_memoized(&mut self, proc: Value) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::Memoized { proc, cache: Rc::new(RefCell::new(HashMap::new())) })
+    }
+
+    pub fn alloc_curried(&mut self, proc: Value, args: Vec<Value>) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::Curried { proc, args })
+    }
+
+    pub fn alloc_macro(&mut self, rules: SyntaxRules) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::Macro(Rc::new(rules)))
+    }
+
+    pub fn alloc_condition(&mut self, error: SchemeError) -> Result<Value, SchemeError> {
+        self.alloc(HeapObject::Condition(Box::new(error)))
+    }
+
+    // Makes the promise id continue with the promise its delay-force body
+    // returned, so that forcing a chain of them iterates rather than recurses.
+    pub fn chain_promise(&mut self, id: GcId, next: Value) -> Result<(), SchemeError> {
+        let next_state = match next {
+            Value::Object(next_id) => match self.get(next_id) {
+                HeapObject::Promise { forced, value, thunk, lazy } => Some((*forced, *value, *thunk, *lazy)),
+                _ => None,
+            },
+            _ => None,
+        };
+        match self.get_mut(id) {
+            // Forced again while computing its own value, keeps the first result.
+            HeapObject::Promise { forced: true, .. } => Ok(()),
+            HeapObject::Promise { forced, value, thunk, lazy } => {
+                match next_state {
+                    Some(state) => (*forced, *value, *thunk, *lazy) = state,
+                    // Anything but a promise is taken as the value.
+                    None => (*forced, *value, *thunk) = (true, next, Value::Nil),
+                }
+                Ok(())
+            },
+            obj => Err(SchemeError::TypeError(format!(
+                "Expected a Promise, but got a {} instead.", obj.type_name()
+            )))
+        }
+    }
+
+    pub fn resolve_promise(&mut self, id: GcId, result: Value) -> Result<Value, SchemeError> {
+        match self.get_mut(id) {
+            // A promise forced again while computing its own value keeps the first result.
+            HeapObject::Promise { forced: true, value, .. } => Ok(*value),
+            HeapObject::Promise { forced, value, thunk, .. } => {
+                *forced = true;
+                *value = result;
+                *thunk = Value::Nil;
+                Ok(result)
+            },
+            obj => Err(SchemeError::TypeError(format!(
+                "Expected a Promise, but got a {} instead.", obj.type_name()
+            )))
+        }
     }
 
 }
@@ -300,148 +1367,413 @@ impl Apply for Value {
     fn apply(&self, interp: &Interp, _env: &Rc<RefCell<Env>>, args: Vec<Value>) 
         -> Result<Value, SchemeError> 
     {
-        let obj = {
+        let (id, obj) = {
             let heap = interp.heap.borrow();
             match self {
-                Value::Object(id) => heap.get(*id).clone(),
+                Value::Object(id) => (*id, heap.get(*id).clone()),
                 _ => return Err(SchemeError::TypeError("Attempted to apply a non-object value".to_string())),
             }
         };
-    
-        match obj {
-            HeapObject::Closure(closure) => {
-                if closure.params.len() != args.len() {
-                    return Err(SchemeError::EvalError("Incorrect number of arguments passed to closure".to_string()));
-                }
-                let new_env = Env::extend(closure.env.clone());
-                for (param_id, arg_value) in closure.params.iter().zip(args.iter()) {
-                    new_env.borrow_mut().define(*param_id, *arg_value);
-                }
-                let mut result = Value::Nil;
-                for expr in &closure.body {
-                    result = expr.eval(interp, &new_env)?;
-                }
-                Ok(result)
-            },
-            HeapObject::NaryClosure(closure) => {
-                let new_env = Env::extend(closure.env.clone());
-                let mut index = 0;
-                while index < closure.params.len() - 1 {
-                    new_env.borrow_mut().define(closure.params[index], args[index]);
-                    index += 1;
-                }
-                let rest = interp.heap.borrow_mut().alloc_list(&args[index..]);
-                new_env.borrow_mut().define(closure.params[index], rest);
-                let mut result = Value::Nil;
-                for expr in &closure.body {
-                    result = expr.eval(interp, &new_env)?;
-                }
-                Ok(result)
-            },
-            HeapObject::Primitive(pr) => pr(interp, &args),
-            _ => Err(SchemeError::TypeError("Attempted to apply a non-primitive object".to_string())),
+        if interp.is_profiling() {
+            let start = Instant::now();
+            let result = apply_object(interp, obj, args);
+            interp.record_call(id, start.elapsed());
+            result
+        } else {
+            apply_object(interp, obj, args)
         }
     }
 }
 
+fn apply_object(interp: &Interp, obj: HeapObject, args: Vec<Value>) -> Result<Value, SchemeError> {
+    apply_tail(interp, obj, args)?.finish(interp)
+}
 
+// Applies func as a call in tail position. Calls are timed as a whole when
+// profiling, so they are then applied in full rather than left to the caller.
+fn apply_value_tail(interp: &Interp, env: &Rc<RefCell<Env>>, func: Value, args: Vec<Value>) -> Result<Tail, SchemeError> {
+    match func {
+        _ if interp.is_profiling() => func.apply(interp, env, args).map(Tail::Value),
+        Value::Object(id) => {
+            let obj = interp.heap.borrow().get(id).clone();
+            apply_tail(interp, obj, args)
+        },
+        _ => Err(SchemeError::TypeError("Attempted to apply a non-object value".to_string())),
+    }
+}
 
-impl SchemeObject for GcId {
+// Binds a closure's arguments and leaves the last expression of its body to
+// the caller, other applicable objects are applied right away.
+fn apply_tail(interp: &Interp, obj: HeapObject, args: Vec<Value>) -> Result<Tail, SchemeError> {
+    match obj {
+        HeapObject::Closure(closure) => {
+            if closure.params.len() != args.len() {
+                return Err(SchemeError::EvalError("Incorrect number of arguments passed to closure".to_string()));
+            }
+            let new_env = Env::extend(closure.env.clone());
+            for (param_id, arg_value) in closure.params.iter().zip(args.iter()) {
+                new_env.borrow_mut().define(*param_id, *arg_value);
+            }
+            eval_body_tail(interp, &new_env, &closure.body)
+        },
+        HeapObject::NaryClosure(closure) => {
+            if args.len() < closure.params.len() - 1 {
+                return Err(SchemeError::EvalError("Incorrect number of arguments passed to closure".to_string()));
+            }
+            let new_env = Env::extend(closure.env.clone());
+            let mut index = 0;
+            while index < closure.params.len() - 1 {
+                new_env.borrow_mut().define(closure.params[index], args[index]);
+                index += 1;
+            }
+            let rest = interp.heap.borrow_mut().alloc_list(&args[index..])?;
+            new_env.borrow_mut().define(closure.params[index], rest);
+            eval_body_tail(interp, &new_env, &closure.body)
+        },
+        HeapObject::Primitive(pr) => pr(interp, &args).map(Tail::Value),
+        HeapObject::Parameter { value, .. } => {
+            if !args.is_empty() {
+                return Err(SchemeError::ArgCountError(format!(
+                    "Parameter expects no args, but got {}.", args.len()
+                )));
+            }
+            Ok(Tail::Value(value))
+        },
+        HeapObject::Memoized { proc, cache } => {
+            let key = args.iter().map(|arg| interp.equal_hash(*arg)).collect::<Vec<_>>();
+            let cached = cache.borrow().get(&key).and_then(|entries| entries.iter()
+                .find(|(cached_args, _)| cached_args.iter().zip(&args).all(|(a, b)| interp.is_equal(*a, *b)))
+                .map(|(_, value)| *value));
+            match cached {
+                Some(value) => Ok(Tail::Value(value)),
+                None => {
+                    let value = proc.apply(interp, &interp.env, args.clone())?;
+                    cache.borrow_mut().entry(key).or_default().push((args, value));
+                    Ok(Tail::Value(value))
+                },
+            }
+        },
+        HeapObject::Curried { proc, args: mut all_args } => {
+            all_args.extend(args);
+            let Value::Object(id) = proc else {
+                return Err(SchemeError::TypeError("Attempted to apply a non-object value".to_string()));
+            };
+            let obj = interp.heap.borrow().get(id).clone();
+            apply_tail(interp, obj, all_args)
+        },
+        HeapObject::Continuation(escape_id) => {
+            match args.as_slice() {
+                [] => Err(SchemeError::Continuation(escape_id, Value::Nil)),
+                [value] => Err(SchemeError::Continuation(escape_id, *value)),
+                _ => Err(SchemeError::ArgCountError(format!(
+                    "Continuation expects at most 1 arg, but got {}.", args.len()
+                ))),
+            }
+        },
+        _ => Err(SchemeError::TypeError("Attempted to apply a non-primitive object".to_string())),
+    }
+}
 
-    fn eval(&self, interp: &Interp, env: &Rc<RefCell<Env>>) -> Result<Value, SchemeError> {
-        let id = *self;
-        let obj = {
-            let heap = interp.heap.borrow();
-            heap.get(id).clone()
-        };
-        
+
+// Pairs and vectors found on a cycle are written with datum labels, as in
+// #0=(1 . #0#), so that writing a circular structure terminates.
+#[derive(Default)]
+struct DatumLabels {
+    labelled: HashSet<GcId>,
+    assigned: HashMap<GcId, usize>,
+}
+
+// Which pairs and vectors get labels: those on a cycle for write, all those
+// met more than once for write-shared, none for write-simple.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Labelling {
+    Cycles,
+    Shared,
+    Never,
+}
+
+// Finds the pairs and vectors reachable more than once from value.
+fn find_shared(interp: &Interp, value: Value, seen: &mut HashSet<GcId>, shared: &mut HashSet<GcId>) {
+    let mut p = value;
+    while let Value::Object(id) = p {
+        let obj = interp.heap.borrow().get(id).clone();
         match obj {
-            HeapObject::Pair(car, cdr) => {
-                if let Value::Object(func_id) = car 
-                    && let Some(keyword) = Keyword::from_id(func_id) {
-                    // Special form handling - no args eval.
-                    let args = interp.fold_list(
-                        cdr,
-                        Vec::new(), 
-                        |mut acc, arg| {
-                            acc.push(arg);
-                            Ok(acc)
-                        });
-                        Keyword::eval(interp, env, keyword, &args?)
-                } else {
-                    // Regular function call with arg eval.
-                    let args = interp.fold_list(
-                        cdr,
-                        Vec::new(), 
-                        |mut acc, arg| {
-                            let value = arg.eval(interp, env)?;
-                            acc.push(value);
-                            Ok(acc)
-                        });
-                    let func = car.eval(interp, env)?;
-                    func.apply(interp, env, args?)
-                }
+            HeapObject::Pair(..) | HeapObject::Vector(_) if !seen.insert(id) => {
+                shared.insert(id);
+                break;
             },
-            HeapObject::List(elements) => {
-                match elements.as_slice() {
-                    [] => Ok(Value::Nil),
-                    [func, rest @ ..] => {
-                        if let Value::Object(func_id) = func 
-                            && let Some(keyword) = Keyword::from_id(*func_id) {
-                                // Special form handling
-                                Keyword::eval(interp, env, keyword, rest)
-                        } else {
-                            // Fallback if not a pecial form.
-                            let args = rest.iter()
-                                .map(|arg| arg.eval(interp, env))
-                                .collect::<Result<Vec<Value>, SchemeError>>()?;
-                            func.eval(interp, env)?.apply(interp, env, args)
-                        }
-                    }    
-                }
+            HeapObject::Pair(car, cdr) => {
+                find_shared(interp, car, seen, shared);
+                p = cdr;
             },
-            HeapObject::Symbol(name) => {
-                match env.borrow().lookup(id) {
-                    Some(value) => return Ok(value),
-                    None => {
-                        return Err(SchemeError::UnboundVariable(format!("Unbound symbol: {}", name)))
-                    },
+            HeapObject::Vector(items) => {
+                for item in items {
+                    find_shared(interp, item, seen, shared);
                 }
+                break;
             },
-            HeapObject::FreeSlot(_) => Err(SchemeError::ImplementationError(format!(
-                "Request to evaluate FreeSlot at {}", id
-            ))),
-            _ => Ok(Value::Object(id))
+            _ => break,
         }
     }
+}
 
-    fn is_false(&self) -> bool {
-        return *self == Keyword::False as usize;
+// Writes value in write mode, labelling structure as asked. With
+// Labelling::Never, writing a circular structure does not terminate.
+pub fn write_labelled(interp: &Interp, f: &mut fmt::Formatter<'_>, value: Value, labelling: Labelling) -> fmt::Result {
+    let mut labels = DatumLabels::default();
+    match labelling {
+        Labelling::Cycles => {
+            find_cycles(interp, value, &mut HashSet::new(), &mut HashSet::new(), &mut labels.labelled);
+        },
+        Labelling::Shared => find_shared(interp, value, &mut HashSet::new(), &mut labels.labelled),
+        Labelling::Never => {},
     }
-    
-    fn write_to(&self, interp: &Interp, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let id = *self;
-        let heap = interp.heap.borrow();
-        let obj = heap.get(id);
+    write_datum(interp, f, value, true, &mut labels)
+}
+
+// Walks cdr chains iteratively and only recurses into cars and vector
+// elements. An object met again while still on the current path is cyclic.
+fn find_cycles(interp: &Interp, value: Value, path: &mut HashSet<GcId>, done: &mut HashSet<GcId>, cyclic: &mut HashSet<GcId>) {
+    let mut chain = Vec::new();
+    let mut p = value;
+    while let Value::Object(id) = p {
+        if path.contains(&id) {
+            cyclic.insert(id);
+            break;
+        }
+        if done.contains(&id) {
+            break;
+        }
+        let obj = interp.heap.borrow().get(id).clone();
         match obj {
             HeapObject::Pair(car, cdr) => {
-                let mut p = cdr.clone();
-                write!(f, "(")?;
-                car.write_to(interp, f)?;
-                loop {
-                    if let Some((cadr, cddr)) = interp.is_pair(p) { 
+                path.insert(id);
+                chain.push(id);
+                find_cycles(interp, car, path, done, cyclic);
+                p = cdr;
+            },
+            HeapObject::Vector(items) => {
+                path.insert(id);
+                chain.push(id);
+                for item in items {
+                    find_cycles(interp, item, path, done, cyclic);
+                }
+                break;
+            },
+            _ => break,
+        }
+    }
+    for id in chain {
+        path.remove(&id);
+        done.insert(id);
+    }
+}
+
+// Writes the label of a labelled object, returns true when it was already
+// written, in which case only its #n# reference is needed.
+fn write_label(f: &mut fmt::Formatter<'_>, id: GcId, labels: &mut DatumLabels) -> Result<bool, fmt::Error> {
+    if !labels.labelled.contains(&id) {
+        return Ok(false);
+    }
+    if let Some(label) = labels.assigned.get(&id) {
+        write!(f, "#{}#", label)?;
+        return Ok(true);
+    }
+    let label = labels.assigned.len();
+    labels.assigned.insert(id, label);
+    write!(f, "#{}=", label)?;
+    Ok(false)
+}
+
+// The reader abbreviation of a quote form head, as in 'x for (quote x).
+fn quote_abbreviation(interp: &Interp, head: Value) -> Option<&'static str> {
+    let Value::Object(id) = head else {
+        return None;
+    };
+    match interp.heap.borrow().get(id) {
+        HeapObject::Symbol(name) => match name.as_str() {
+            "quote" => Some("'"),
+            "quasiquote" => Some("`"),
+            "unquote" => Some(","),
+            "unquote-splicing" => Some(",@"),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn write_datum(interp: &Interp, f: &mut fmt::Formatter<'_>, value: Value, write: bool, labels: &mut DatumLabels) -> fmt::Result {
+    let Value::Object(id) = value else {
+        return value.write_to(interp, f, write);
+    };
+    let obj = interp.heap.borrow().get(id).clone();
+    match obj {
+        HeapObject::Pair(car, cdr) => {
+            if write_label(f, id, labels)? {
+                return Ok(());
+            }
+            // Only a two element list abbreviates, and not when its tail needs a label.
+            if let Value::Object(cdr_id) = cdr
+                && !labels.labelled.contains(&cdr_id)
+                && let Some(prefix) = quote_abbreviation(interp, car)
+                && let Ok((quoted, Value::Nil)) = interp.to_pair(cdr) {
+                write!(f, "{}", prefix)?;
+                return write_datum(interp, f, quoted, write, labels);
+            }
+            write!(f, "(")?;
+            write_datum(interp, f, car, write, labels)?;
+            let mut p = cdr;
+            loop {
+                match p {
+                    Value::Nil => break,
+                    Value::Object(next) if !labels.labelled.contains(&next) && interp.is_pair(p).is_some() => {
+                        let (cadr, cddr) = interp.to_pair(p).map_err(|_| fmt::Error)?;
                         write!(f, " ")?;
-                        cadr.write_to(interp, f)?;
+                        write_datum(interp, f, cadr, write, labels)?;
                         p = cddr;
-                    } else if interp.is_nil(p) {
-                        break;
-                    } else {
+                    },
+                    // A labelled tail is written in dotted form.
+                    _ => {
                         write!(f, " . ")?;
-                        p.write_to(interp, f)?;
+                        write_datum(interp, f, p, write, labels)?;
                         break;
                     }
                 }
-                write!(f, ")")
+            }
+            write!(f, ")")
+        },
+        HeapObject::Vector(elements) => {
+            if write_label(f, id, labels)? {
+                return Ok(());
+            }
+            write!(f, "#(")?;
+            for (i, e) in elements.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " ")?;
+                }
+                write_datum(interp, f, *e, write, labels)?;
+            }
+            write!(f, ")")
+        },
+        _ => value.write_to(interp, f, write),
+    }
+}
+
+// Evaluates the expression id up to its tail position, if any. Calls are
+// recorded for the evaluation entered at frame.
+fn eval_step(interp: &Interp, id: GcId, env: &Rc<RefCell<Env>>, frame: usize) -> Result<Tail, SchemeError> {
+    let obj = {
+        let heap = interp.heap.borrow();
+        heap.get(id).clone()
+    };
+    
+    match obj {
+        HeapObject::Pair(car, cdr) => {
+            if let Value::Object(func_id) = car 
+                && let Some(keyword) = Keyword::from_id(func_id) {
+                // Special form handling - no args eval.
+                let args = interp.fold_list_strict(
+                    cdr,
+                    Vec::new(), 
+                    |mut acc, arg| {
+                        acc.push(arg);
+                        Ok(acc)
+                    });
+                    Keyword::eval(interp, env, keyword, &args?)
+            } else {
+                // The operator comes first, a macro use is replaced by its expansion.
+                let func = car.eval(interp, env)?;
+                if let Some(rules) = interp.is_macro(func) {
+                    let expansion = syntax::expand_use(interp, &rules, Value::Object(id))?;
+                    return Ok(Tail::Eval(expansion, Rc::clone(env)));
+                }
+                // Regular function call with arg eval.
+                interp.note_call(frame, car);
+                let args = interp.fold_list_strict(
+                    cdr,
+                    Vec::new(), 
+                    |mut acc, arg| {
+                        let value = interp.single_value(arg.eval(interp, env)?)?;
+                        acc.push(value);
+                        Ok(acc)
+                    });
+                apply_value_tail(interp, env, func, args?)
+            }
+        },
+        HeapObject::List(elements) => {
+            match elements.as_slice() {
+                [] => Ok(Tail::Value(Value::Nil)),
+                [func, rest @ ..] => {
+                    if let Value::Object(func_id) = func 
+                        && let Some(keyword) = Keyword::from_id(*func_id) {
+                            // Special form handling
+                            Keyword::eval(interp, env, keyword, rest)
+                    } else {
+                        // Fallback if not a pecial form.
+                        interp.note_call(frame, *func);
+                        let args = rest.iter()
+                            .map(|arg| interp.single_value(arg.eval(interp, env)?))
+                            .collect::<Result<Vec<Value>, SchemeError>>()?;
+                        apply_value_tail(interp, env, func.eval(interp, env)?, args)
+                    }
+                }    
+            }
+        },
+        HeapObject::Symbol(name) => {
+            match env.borrow().lookup(id) {
+                Some(value) => Ok(Tail::Value(value)),
+                None => {
+                    Err(SchemeError::UnboundVariable(format!("Unbound symbol: {}", name)))
+                },
+            }
+        },
+        HeapObject::FreeSlot(_) => Err(SchemeError::ImplementationError(format!(
+            "Request to evaluate FreeSlot at {}", id
+        ))),
+        _ => Ok(Tail::Value(Value::Object(id)))
+    }
+}
+
+// Expressions in tail position are evaluated here in turn, rather than
+// recursively, so that loops written as tail calls use bounded stack.
+fn eval_loop(interp: &Interp, id: GcId, env: &Rc<RefCell<Env>>, frame: usize) -> Result<Value, SchemeError> {
+    let mut tail = eval_step(interp, id, env, frame)?;
+    loop {
+        tail = match tail {
+            Tail::Eval(Value::Object(id), env) if interp.optimize_tail_calls() => {
+                interp.collect_between_steps(Value::Object(id), &env);
+                eval_step(interp, id, &env, frame)?
+            },
+            Tail::Eval(expr, env) => return expr.eval(interp, &env),
+            Tail::Value(value) => return Ok(value),
+        };
+    }
+}
+
+impl SchemeObject for GcId {
+
+    fn eval(&self, interp: &Interp, env: &Rc<RefCell<Env>>) -> Result<Value, SchemeError> {
+        let frame = interp.enter_eval()?;
+        let result = eval_loop(interp, *self, env, frame);
+        interp.leave_eval(frame);
+        result
+    }
+
+    fn is_false(&self) -> bool {
+        // #f is an immediate Value, heap objects are never false.
+        false
+    }
+    
+    fn write_to(&self, interp: &Interp, f: &mut fmt::Formatter<'_>, write: bool) -> fmt::Result {
+        let id = *self;
+        let heap = interp.heap.borrow();
+        match heap.get(id) {
+            // Structures are walked without holding on to a heap borrow.
+            HeapObject::Pair(..) | HeapObject::Vector(_) => {
+                drop(heap);
+                let mut labels = DatumLabels::default();
+                find_cycles(interp, Value::Object(id), &mut HashSet::new(), &mut HashSet::new(), &mut labels.labelled);
+                write_datum(interp, f, Value::Object(id), write, &mut labels)
             },
             HeapObject::List(elements) => {
                 write!(f, "(")?;
@@ -449,15 +1781,61 @@ impl SchemeObject for GcId {
                     if i > 0 {
                         write!(f, " ")?; // Add a space before every element EXCEPT the first
                     }
-                    e.write_to(interp, f)?;
+                    e.write_to(interp, f, write)?;
+                }
+                write!(f, ")")
+            },
+            HeapObject::Bytevector(bytes) => {
+                write!(f, "#u8(")?;
+                for (i, byte) in bytes.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", byte)?;
                 }
                 write!(f, ")")
             },
             HeapObject::Symbol(s) => write!(f, "{}", s),
-            HeapObject::String(s) => write!(f, "\"{}\"", s),
+            HeapObject::String(s) if write => {
+                write!(f, "\"")?;
+                for ch in s.chars() {
+                    match ch {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\r' => write!(f, "\\r")?,
+                        ch => write!(f, "{}", ch)?,
+                    }
+                }
+                write!(f, "\"")
+            },
+            HeapObject::String(s) => write!(f, "{}", s),
             HeapObject::Primitive(pr) => write!(f, "<primitive {:p}>", pr),
             HeapObject::Closure(_) => write!(f, "<closure {}>", id),
             HeapObject::NaryClosure(_) => write!(f, "<n-closure {}>", id),
+            HeapObject::Promise { .. } => write!(f, "<promise {}>", id),
+            HeapObject::DelayedPair { .. } => write!(f, "<delayed-pair {}>", id),
+            HeapObject::Continuation(_) => write!(f, "<continuation {}>", id),
+            HeapObject::Condition(error) => write!(f, "<condition {}>", error.message()),
+            HeapObject::HashTable { table, weak: false } => write!(f, "<hash-table {} ({})>", id, table.len()),
+            HeapObject::HashTable { table, weak: true } => write!(f, "<weak-hash-table {} ({})>", id, table.len()),
+            HeapObject::OutputString(_) => write!(f, "<output-string {}>", id),
+            HeapObject::InputString { .. } => write!(f, "<input-string {}>", id),
+            HeapObject::InputBytevector { .. } => write!(f, "<input-bytevector {}>", id),
+            HeapObject::Parameter { .. } => write!(f, "<parameter {}>", id),
+            HeapObject::Macro(_) => write!(f, "<macro {}>", id),
+            HeapObject::Memoized { .. } => write!(f, "<memoized {}>", id),
+            HeapObject::Curried { .. } => write!(f, "<curried {}>", id),
+            HeapObject::Values(values) => {
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    v.write_to(interp, f, write)?;
+                }
+                Ok(())
+            },
             HeapObject::FreeSlot(_) => write!(f, "*** FREE SLOT ***")
         }
     }
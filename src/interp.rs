@@ -1,35 +1,166 @@
-use std::cell::{RefCell};
-use std::collections::HashMap;
+use std::cell::{Cell, Ref, RefCell};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::fs::File;
+use std::io::{self, BufReader, Write};
 use std::process;
 use std::rc::Rc;
+use std::time::Duration;
 
-use crate::heap::HeapObject;
-use crate::parser::Parser;
+use crate::heap::{Apply, HashKey, HeapObject, SyntaxRules};
+use crate::parser::{is_identifier, is_symbol_char, Parser};
+use crate::macros::{arg_count_error, arg_type_error};
 use crate::{all_of_type, check_arity, extract_args, heap};
 use crate::types::{DisplayWrapper, GcId, Number, SchemeError, SchemeObject, Value};
 
+// Nested evaluations allowed before failing. Each costs up to about 8KB of
+// stack in debug builds (1KB in release), so this fits the 8MB stack of a
+// main thread; embedders running on a larger stack may raise it with
+// set_max_depth.
+pub const DEFAULT_MAX_DEPTH: usize = 800;
+// The number of calls listed by the trace of a recursion overflow.
+const TRACE_LENGTH: usize = 10;
+// The largest width or digits param of a format directive.
+const MAX_FORMAT_PARAM: usize = 4096;
+// The longest string or bytevector built from a length argument alone.
+const MAX_FILL_LENGTH: usize = 1 << 28;
+// The most parts of a structure equal_hash looks at.
+const EQUAL_HASH_LIMIT: usize = 64;
+// The most warnings kept for take_warnings, older ones are dropped.
+const MAX_WARNINGS: usize = 100;
+
+type WarningHandler = Box<dyn Fn(&str)>;
+
 pub struct Interp {
     pub heap: RefCell<heap::Heap>,
     pub env: Rc<RefCell<crate::env::Env>>,
+    next_escape_id: Cell<usize>,
+    fold_case: Cell<bool>,
+    warnings_as_errors: Cell<bool>,
+    // Warnings go to the handler if one is set, and are otherwise kept, up
+    // to MAX_WARNINGS, until taken.
+    warning_handler: RefCell<Option<WarningHandler>>,
+    warnings: RefCell<VecDeque<String>>,
+    profiling: Cell<bool>,
+    profile: RefCell<HashMap<GcId, (u64, Duration)>>,
+    // Parameter holding the number of digits floats print with, #f for the
+    // shortest representation that reads back as the same float.
+    float_print_precision: Value,
+    // The primitive shared by the objects of make-object, each holding its
+    // methods as the curried arg.
+    object_dispatcher: Value,
+    // The heap size and the global bindings once initialized, see reset.
+    initial_heap_size: usize,
+    initial_bindings: Vec<(GcId, Value)>,
+    // The installed exception handlers, innermost last. None marks a guard,
+    // which catches by unwinding the stack rather than by being called.
+    handlers: RefCell<Vec<Option<Value>>>,
+    // Tail positions are evaluated in a loop unless turned off for debugging,
+    // every call then grows the stack.
+    optimize_tail_calls: Cell<bool>,
+    // Evaluations nested deeper than max_depth fail, rather than overflow the
+    // stack, with a trace of the innermost calls.
+    max_depth: Cell<usize>,
+    depth: Cell<usize>,
+    // The operator of each call being evaluated, innermost last.
+    calls: RefCell<Vec<Value>>,
+    // vector-map and vector-for-each stop at the shortest vector when set,
+    // rather than failing on vectors of different lengths.
+    vector_map_shortest: Cell<bool>,
+    // Set by (gc), which collects once the outermost evaluation is done.
+    gc_requested: Cell<bool>,
+    // Set while Interp::eval evaluates a top level form, whose tail calls
+    // may then collect a capped heap, see collect_between_steps.
+    in_top_level_form: Cell<bool>,
+    // Global defines replacing a primitive warn, once per name, when set.
+    warn_on_redefinition: Cell<bool>,
+    redefined: RefCell<HashSet<GcId>>,
+    // The property list of each symbol, set by put! and read by get, as
+    // key and value pairs compared with eq?.
+    plists: RefCell<HashMap<GcId, Vec<(Value, Value)>>>,
+    // The documentation of primitives, by the symbol they're defined as.
+    docs: RefCell<HashMap<GcId, String>>,
+    // The thunks registered by at-exit, run most recent first by exit.
+    exit_hooks: RefCell<Vec<Value>>,
+    // Called by exit with the exit code once the hooks ran, process::exit
+    // unless replaced, as tests do.
+    exit_handler: RefCell<Box<dyn Fn(i32)>>,
+}
+
+impl Default for Interp {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interp {
     pub fn new() -> Self {
-        let global_env = crate::env::Env {
-            bindings: HashMap::new(),
-            parent: None,
-        };
+        let global_env = crate::env::Env::new();
         let env_handle = Rc::new(RefCell::new(global_env));
         let heap_handlee = RefCell::new(heap::Heap::new());
-        let interp = Self {
+        let float_print_precision = {
+            let mut heap = heap_handlee.borrow_mut();
+            let converter = heap.define_primitive(convert_float_precision);
+            heap.alloc_parameter(Value::Boolean(false), Some(converter))
+                .expect("A new heap has no maximum size.")
+        };
+        let object_dispatcher = heap_handlee.borrow_mut().define_primitive(dispatch_method);
+        let mut interp = Self {
             heap: heap_handlee,
             env: env_handle,
+            next_escape_id: Cell::new(0),
+            fold_case: Cell::new(false),
+            warnings_as_errors: Cell::new(false),
+            warning_handler: RefCell::new(None),
+            warnings: RefCell::new(VecDeque::new()),
+            profiling: Cell::new(false),
+            profile: RefCell::new(HashMap::new()),
+            float_print_precision,
+            object_dispatcher,
+            initial_heap_size: 0,
+            initial_bindings: Vec::new(),
+            handlers: RefCell::new(Vec::new()),
+            optimize_tail_calls: Cell::new(true),
+            max_depth: Cell::new(DEFAULT_MAX_DEPTH),
+            depth: Cell::new(0),
+            calls: RefCell::new(Vec::new()),
+            vector_map_shortest: Cell::new(false),
+            gc_requested: Cell::new(false),
+            in_top_level_form: Cell::new(false),
+            warn_on_redefinition: Cell::new(true),
+            redefined: RefCell::new(HashSet::new()),
+            plists: RefCell::new(HashMap::new()),
+            docs: RefCell::new(HashMap::new()),
+            exit_hooks: RefCell::new(Vec::new()),
+            exit_handler: RefCell::new(Box::new(|code| process::exit(code))),
         };
         interp.init();
+        interp.initial_heap_size = interp.heap.borrow().size();
+        interp.initial_bindings = interp.env.borrow().bindings();
         interp
     }
 
+    // Forgets all user definitions and the objects allocated since init,
+    // keeping the keywords and primitives. Cheaper than a new Interp, but any
+    // Value obtained before the reset is left dangling.
+    pub fn reset(&self) {
+        self.heap.borrow_mut().truncate(self.initial_heap_size);
+        let mut env = self.env.borrow_mut();
+        *env = crate::env::Env::new();
+        for (id, value) in &self.initial_bindings {
+            env.define(*id, *value);
+        }
+        self.warnings.borrow_mut().clear();
+        self.profile.borrow_mut().clear();
+        self.handlers.borrow_mut().clear();
+        self.calls.borrow_mut().clear();
+        self.redefined.borrow_mut().clear();
+        self.plists.borrow_mut().clear();
+        self.exit_hooks.borrow_mut().clear();
+        self.depth.set(0);
+    }
+
     pub fn define(&self, name: &str, value: Value) {
         let symbol = self.heap.borrow_mut().intern_symbol(name);
         if let Value::Object(id) = symbol {
@@ -38,10 +169,18 @@ impl Interp {
     }
 
     pub fn define_primitive(&self, name: &str, func: heap::PrimitiveFn) {
-        let prim = self.heap.borrow_mut().alloc_primitive(func);
+        let prim = self.heap.borrow_mut().define_primitive(func);
         self.define(name, prim);
     }
 
+    // Defines a primitive along with the documentation (help 'name) returns.
+    pub fn define_primitive_doc(&self, name: &str, func: heap::PrimitiveFn, doc: &str) {
+        self.define_primitive(name, func);
+        if let Value::Object(id) = self.heap.borrow_mut().intern_symbol(name) {
+            self.docs.borrow_mut().insert(id, doc.to_string());
+        }
+    }
+
     fn init(&self) {
         self.define("#t", Value::Boolean(true));
         self.define("#f", Value::Boolean(false));
@@ -49,6 +188,8 @@ impl Interp {
         self.define_primitive("number?", primitive_number_p);
         self.define_primitive("integer?", primitive_integer_p);
         self.define_primitive("float?", primitive_float_p);
+        self.define_primitive("even?", primitive_even_p);
+        self.define_primitive("odd?", primitive_odd_p);
         self.define_primitive("+", primitive_add);
         self.define_primitive("-", primitive_sub);
         self.define_primitive("*", primitive_mul);
@@ -59,9 +200,48 @@ impl Interp {
         self.define_primitive(">", primitive_number_gt);
         self.define_primitive("<=", primitive_number_lte);
         self.define_primitive(">=", primitive_number_gte);
+        self.define_primitive("approx=?", primitive_approx_eq);
         self.define_primitive("max", primitive_number_max);
         self.define_primitive("min", primitive_number_min);
-
+        self.define_primitive("abs", primitive_abs);
+        self.define_primitive("floor", primitive_floor);
+        self.define_primitive("ceiling", primitive_ceiling);
+        self.define_primitive("truncate", primitive_truncate);
+        self.define_primitive("round", primitive_round);
+        self.define_primitive("expt", primitive_expt);
+        self.define_primitive("gcd", primitive_gcd);
+        self.define_primitive("lcm", primitive_lcm);
+        self.define_primitive("exact?", primitive_exact_p);
+        self.define_primitive("inexact?", primitive_inexact_p);
+        self.define_primitive("exact-nonnegative-integer?", primitive_exact_nonnegative_integer_p);
+        self.define_primitive("number->string", primitive_number_to_string);
+        self.define_primitive("string->number", primitive_string_to_number);
+        self.define_primitive("valid-symbol-char?", primitive_valid_symbol_char_p);
+        self.define_primitive("identifier?", primitive_identifier_p);
+
+        // Initialize integer division functions.
+        self.define_primitive("truncate/", primitive_truncate_div);
+        self.define_primitive("truncate-quotient", primitive_truncate_quotient);
+        self.define_primitive("truncate-remainder", primitive_truncate_remainder);
+        self.define_primitive("floor/", primitive_floor_div);
+        self.define_primitive("floor-quotient", primitive_floor_quotient);
+        self.define_primitive("floor-remainder", primitive_floor_remainder);
+        self.define_primitive("euclidean/", primitive_euclidean_div);
+        self.define_primitive("euclidean-quotient", primitive_euclidean_quotient);
+        self.define_primitive("euclidean-remainder", primitive_euclidean_remainder);
+        self.define_primitive("quotient", primitive_truncate_quotient);
+        self.define_primitive("remainder", primitive_truncate_remainder);
+        self.define_primitive("modulo", primitive_floor_remainder);
+
+
+        // Initialize type predicates.
+        self.define_primitive("boolean?", primitive_boolean_p);
+        self.define_primitive("not", primitive_not);
+        self.define_primitive("symbol?", primitive_symbol_p);
+        self.define_primitive("string?", primitive_string_p);
+        self.define_primitive("pair?", primitive_pair_p);
+        self.define_primitive("vector?", primitive_vector_p);
+        self.define_primitive("unspecified?", primitive_unspecified_p);
 
         // Initialize character functions.
         self.define_primitive("char?", primitive_char_p);
@@ -86,23 +266,194 @@ impl Interp {
         self.define_primitive("char-ci>=?", primitive_char_ci_gte);
 
 
+        // Initialize string functions.
+        self.define_primitive("format", primitive_format);
+        self.define_primitive("string-prefix?", primitive_string_prefix_p);
+        self.define_primitive("string-suffix?", primitive_string_suffix_p);
+        self.define_primitive("substring?", primitive_substring_p);
+        self.define_primitive("string-index", primitive_string_index);
+        self.define_primitive("string-index-right", primitive_string_index_right);
+        self.define_primitive("string-count", primitive_string_count);
+        self.define_primitive("make-string", primitive_make_string);
+        self.define_primitive("string-length", primitive_string_length);
+        self.define_primitive("string-ref", primitive_string_ref);
+        self.define_primitive("string-set!", primitive_string_set);
+        self.define_primitive("string-copy", primitive_string_copy);
+        self.define_primitive("string-fill!", primitive_string_fill);
+        self.define_primitive("string-take", primitive_string_take);
+        self.define_primitive("string-drop", primitive_string_drop);
+        self.define_primitive("string-take-right", primitive_string_take_right);
+        self.define_primitive("string-drop-right", primitive_string_drop_right);
+        self.define_primitive("string=?", primitive_string_eq);
+        self.define_primitive("string<?", primitive_string_lt);
+        self.define_primitive("string<=?", primitive_string_lte);
+        self.define_primitive("string>?", primitive_string_gt);
+        self.define_primitive("string>=?", primitive_string_gte);
+        self.define_primitive("string-ci=?", primitive_string_ci_eq);
+        self.define_primitive("string-ci<?", primitive_string_ci_lt);
+        self.define_primitive("string-ci<=?", primitive_string_ci_lte);
+        self.define_primitive("string-ci>?", primitive_string_ci_gt);
+        self.define_primitive("string-ci>=?", primitive_string_ci_gte);
+
         // Initialize list functions.
         self.define_primitive("list", primitive_list);
         self.define_primitive("append", primitive_append);
         self.define_primitive("length", primitive_length);
         self.define_primitive("list?", primitive_list_p);
+        self.define_primitive("length+", primitive_length_plus);
         self.define_primitive("null?", primitive_null_p);
-        self.define_primitive("cons", primitive_list_cons);
-        self.define_primitive("car", primitive_list_car);
-        self.define_primitive("cdr", primitive_list_cdr);
+        self.define_primitive_doc("cons", primitive_list_cons,
+            "(cons a b) returns a new pair of a and b.");
+        self.define_primitive_doc("car", primitive_list_car,
+            "(car pair) returns the first element of pair.");
+        self.define_primitive_doc("cdr", primitive_list_cdr,
+            "(cdr pair) returns the second element of pair.");
+        self.define_primitive("set-car!", primitive_set_car);
+        self.define_primitive("set-cdr!", primitive_set_cdr);
+        self.define_primitive("eq?", primitive_eq_p);
+        self.define_primitive("eqv?", primitive_eqv_p);
+        self.define_primitive("equal?", primitive_equal_p);
+        self.define_primitive("alist-merge", primitive_alist_merge);
+        self.define_primitive("alist-update", primitive_alist_update);
+        self.define_primitive("del-assq", primitive_del_assq);
+        self.define_primitive("memq", primitive_memq);
+        self.define_primitive("memv", primitive_memv);
+        self.define_primitive("member", primitive_member);
+        self.define_primitive("assq", primitive_assq);
+        self.define_primitive("assv", primitive_assv);
+        self.define_primitive("assoc", primitive_assoc);
+
+        // Initialize higher-order and control functions.
+        self.define_primitive("procedure?", primitive_procedure_p);
+        self.define_primitive("apply", primitive_apply);
+        self.define_primitive("map", primitive_map);
+        self.define_primitive("for-each", primitive_for_each);
+        self.define_primitive("memoize", primitive_memoize);
+        self.define_primitive("curry", primitive_curry);
+        self.define_primitive("make-object", primitive_make_object);
+        self.define_primitive("send", primitive_send);
+        self.define_primitive("filter", primitive_filter);
+        self.define_primitive("find", primitive_find);
+        self.define_primitive("find-tail", primitive_find_tail);
+        self.define_primitive("list-index", primitive_list_index);
+        self.define_primitive("delete-duplicates", primitive_delete_duplicates);
+        self.define_primitive("zip", primitive_zip);
+        self.define_primitive("unzip1", primitive_unzip1);
+        self.define_primitive("unzip", primitive_unzip);
+        self.define_primitive("fold", primitive_fold);
+        self.define_primitive("sort", primitive_sort);
+        self.define_primitive("max-by", primitive_max_by);
+        self.define_primitive("min-by", primitive_min_by);
+        self.define_primitive("call/cc", primitive_call_cc);
+        self.define_primitive("call-with-current-continuation", primitive_call_cc);
+        self.define_primitive("dynamic-wind", primitive_dynamic_wind);
+        self.define_primitive("values", primitive_values);
+        self.define_primitive("call-with-values", primitive_call_with_values);
+
+        // Initialize vector functions.
+        self.define_primitive("vector", primitive_vector);
+        self.define_primitive("vector-length", primitive_vector_length);
+        self.define_primitive("vector-ref", primitive_vector_ref);
+        self.define_primitive("vector->list", primitive_vector_to_list);
+        self.define_primitive("vector-map", primitive_vector_map);
+        self.define_primitive("vector-for-each", primitive_vector_for_each);
+        self.define_primitive("list->vector", primitive_list_to_vector);
+        self.define_primitive("slice", primitive_slice);
+
+        // Initialize bytevector functions.
+        self.define_primitive("bytevector?", primitive_bytevector_p);
+        self.define_primitive("make-bytevector", primitive_make_bytevector);
+        self.define_primitive("bytevector-length", primitive_bytevector_length);
+        self.define_primitive("bytevector-u8-ref", primitive_bytevector_u8_ref);
+        self.define_primitive("bytevector-u8-set!", primitive_bytevector_u8_set);
+        self.define_primitive("bytevector->list", primitive_bytevector_to_list);
+        self.define_primitive("list->bytevector", primitive_list_to_bytevector);
+
+        // Initialize hash table functions.
+        self.define_primitive("make-hash-table", primitive_make_hash_table);
+        self.define_primitive("make-weak-hash-table", primitive_make_weak_hash_table);
+        self.define_primitive("hash-table?", primitive_hash_table_p);
+        self.define_primitive("hash-set!", primitive_hash_set);
+        self.define_primitive("hash-ref", primitive_hash_ref);
+        self.define_primitive("hash-table-ref/default", primitive_hash_table_ref_default);
+        self.define_primitive("hash-table-contains?", primitive_hash_table_contains_p);
+        self.define_primitive("hash-delete!", primitive_hash_delete);
+        self.define_primitive("hash-count", primitive_hash_count);
+        self.define_primitive("hash-table-update!", primitive_hash_table_update);
+        self.define_primitive("hash-table-walk", primitive_hash_table_walk);
+        self.define_primitive("hash-table-fold", primitive_hash_table_fold);
+
+        // Initialize symbol property list functions.
+        self.define_primitive("put!", primitive_put);
+        self.define_primitive("get", primitive_get);
+
+        // Initialize documentation functions.
+        self.define_primitive_doc("help", primitive_help,
+            "(help 'name) returns the documentation of primitive name, or #f.");
+        self.define_primitive_doc("apropos", primitive_apropos,
+            "(apropos string) returns the sorted bound symbols whose name contains string.");
+
+        // Initialize parameter functions.
+        self.define_primitive("make-parameter", primitive_make_parameter);
+        self.define("float-print-precision", self.float_print_precision);
+
+        // Initialize promise functions.
+        self.define_primitive("force", primitive_force);
+        self.define_primitive("make-promise", primitive_make_promise);
+
+        // Initialize stream functions, streams are promises of () or of a
+        // pair of a value and a stream.
+        let stream_null = self.heap.borrow_mut().alloc_resolved_promise(Value::Nil)
+            .expect("A new heap has no maximum size.");
+        self.define("stream-null", stream_null);
+        self.define_primitive("stream-null?", primitive_stream_null_p);
+        self.define_primitive("stream-car", primitive_stream_car);
+        self.define_primitive("stream-cdr", primitive_stream_cdr);
+        self.define_primitive("stream-take", primitive_stream_take);
+        self.define_primitive("stream-map", primitive_stream_map);
+        self.define_primitive("stream->list", primitive_stream_to_list);
+        self.define_primitive("promise?", primitive_promise_p);
+
+        // Initialize output functions.
+        self.define_primitive("write", primitive_write);
+        self.define_primitive("write-shared", primitive_write_shared);
+        self.define_primitive("write-simple", primitive_write_simple);
+        self.define_primitive("display", primitive_display);
+        self.define_primitive("newline", primitive_newline);
+        self.define_primitive("open-output-string", primitive_open_output_string);
+        self.define_primitive("get-output-string", primitive_get_output_string);
+        self.define_primitive("flush-output-port", primitive_flush_output_port);
+
+        // Initialize input functions.
+        self.define_primitive("open-input-string", primitive_open_input_string);
+        self.define_primitive("read", primitive_read);
+        self.define_primitive("read-string", primitive_read_string);
+        self.define_primitive("open-input-bytevector", primitive_open_input_bytevector);
+        self.define_primitive("read-u8", primitive_read_u8);
+        self.define_primitive("eof-object", primitive_eof_object);
+        self.define_primitive("eof-object?", primitive_eof_object_p);
 
         // Initialize system primitive functions.
+        self.define_primitive("error", primitive_error);
+        self.define_primitive("raise", primitive_raise);
+        self.define_primitive("raise-continuable", primitive_raise_continuable);
+        self.define_primitive("with-exception-handler", primitive_with_exception_handler);
+        self.define_primitive("condition?", primitive_condition_p);
+        self.define_primitive("condition-message", primitive_condition_message);
         self.define_primitive("debug", primitive_debug);
+        self.define_primitive("set-profiling!", primitive_set_profiling);
+        self.define_primitive("profile-report", primitive_profile_report);
+        self.define_primitive("gc", primitive_gc);
+        self.define_primitive("gc-stats", primitive_gc_stats);
         self.define_primitive("load", primitive_load);
         self.define_primitive("quit", primitive_quit);
         self.define_primitive("exit", primitive_quit);
+        self.define_primitive("at-exit", primitive_at_exit);
     }
 
+    // Stops at the first non-pair cdr, so the tail of an improper list is
+    // silently ignored, see fold_list_strict. Each pair is read with a short
+    // lived heap borrow, func is free to allocate or mutate the heap.
     pub fn fold_list<T, F>(&self, list: Value, init: T, mut func: F)  
         -> Result<T, SchemeError> 
         where 
@@ -117,31 +468,436 @@ impl Interp {
         Ok(acc)
     }
 
+    // Like fold_list, but errors out on an improper list rather than
+    // dropping its tail. The heap isn't borrowed while func runs either.
+    pub fn fold_list_strict<T, F>(&self, list: Value, init: T, mut func: F)
+        -> Result<T, SchemeError>
+        where
+        F: FnMut(T, Value) -> Result<T, SchemeError>
+    {
+        let mut p = list;
+        let mut acc = init;
+        while let Some((car, cdr)) = self.is_pair(p) {
+            acc = func(acc, car)?;
+            p = cdr;
+        }
+        if !self.is_nil(p) {
+            return Err(SchemeError::TypeError(format!(
+                "Expected a proper list, but {} ends in {}.", self.write(list), self.write(p)
+            )));
+        }
+        Ok(acc)
+    }
+
+
+    pub fn list_to_vec(&self, list: Value) -> Result<Vec<Value>, SchemeError> {
+        self.fold_list_strict(list, Vec::new(), |mut acc, item| {
+            acc.push(item);
+            Ok(acc)
+        })
+    }
 
     pub fn lookup(&self, name: &str) -> Value {
+        if self.fold_case.get() {
+            self.heap.borrow_mut().intern_symbol(&name.to_lowercase())
+        } else {
+            self.heap.borrow_mut().intern_symbol(name)
+        }
+    }
+
+    // When set, symbols are lowercased as they are interned by the reader.
+    pub fn set_fold_case(&self, fold_case: bool) {
+        self.fold_case.set(fold_case);
+    }
+
+    pub fn fold_case(&self) -> bool {
+        self.fold_case.get()
+    }
+
+    pub fn symbol(&self, name: &str) -> Value {
         self.heap.borrow_mut().intern_symbol(name)
     }
 
+    pub fn string(&self, s: impl Into<String>) -> Result<Value, SchemeError> {
+        self.heap.borrow_mut().alloc_string(s)
+    }
+
+    pub fn pair(&self, car: Value, cdr: Value) -> Result<Value, SchemeError> {
+        self.heap.borrow_mut().alloc_pair(car, cdr)
+    }
+
+    pub fn list(&self, items: Vec<Value>) -> Result<Value, SchemeError> {
+        self.heap.borrow_mut().alloc_list(&items)
+    }
+
     pub fn eval(&self, obj: Value)  -> Result<Value, SchemeError> {
-        obj.eval(self, &self.env) 
+        let top_level = self.depth.get() == 0;
+        if top_level {
+            self.in_top_level_form.set(true);
+        }
+        let result = obj.eval(self, &self.env);
+        if top_level {
+            self.in_top_level_form.set(false);
+        }
+        // Nothing but the result refers to the heap once back at the top level.
+        // Running out of heap also collects, before the error is reported, so
+        // that the next evaluation has room to allocate.
+        if self.depth.get() == 0 && (self.gc_requested.take() || self.heap.borrow().is_exhausted()) {
+            let roots = match &result {
+                Ok(value) => vec![*value],
+                Err(error) => error.values(),
+            };
+            self.collect_garbage(&roots);
+        }
+        result
+    }
+
+    // Frees the heap objects no longer reachable from the global environment,
+    // the interpreter state, or the given roots, and returns how many were
+    // freed. Any other Value held by the caller may be left dangling.
+    pub fn collect_garbage(&self, roots: &[Value]) -> usize {
+        self.collect_from(roots, std::slice::from_ref(&self.env))
+    }
+
+    // Collects a capped heap between the tail calls of a top level form, so
+    // that a loop with a bounded live set runs for as long as it takes. The
+    // form's own loop then holds nothing but the pending expression and its
+    // environment, nested evaluations are all done with.
+    pub(crate) fn collect_between_steps(&self, expr: Value, env: &Rc<RefCell<crate::env::Env>>) {
+        if self.in_top_level_form.get() && self.depth.get() == 1 && self.heap.borrow().wants_collection() {
+            self.collect_from(&[expr], &[Rc::clone(&self.env), Rc::clone(env)]);
+        }
+    }
+
+    fn collect_from(&self, roots: &[Value], envs: &[Rc<RefCell<crate::env::Env>>]) -> usize {
+        let mut roots = roots.to_vec();
+        roots.push(self.float_print_precision);
+        roots.push(self.object_dispatcher);
+        roots.extend(self.initial_bindings.iter().map(|(_, value)| *value));
+        roots.extend(self.handlers.borrow().iter().flatten());
+        roots.extend(self.calls.borrow().iter());
+        roots.extend(self.profile.borrow().keys().map(|id| Value::Object(*id)));
+        roots.extend(self.plists.borrow().values().flatten().flat_map(|(key, value)| [*key, *value]));
+        roots.extend(self.exit_hooks.borrow().iter());
+        self.heap.borrow_mut().collect(&roots, envs)
+    }
+
+    // Evaluates for the REPL, where an unbound symbol error also suggests the
+    // closest bound names, as in "Unbound symbol: cbr, did you mean `car` or `cdr`?".
+    // The last three values shown are bound to *1, *2 and *3, most recent first.
+    pub fn repl_eval(&self, obj: Value) -> Result<Value, SchemeError> {
+        match self.eval(obj) {
+            Ok(value) => {
+                if value != Value::Unspecified {
+                    self.push_result(value);
+                }
+                Ok(value)
+            },
+            Err(SchemeError::UnboundVariable(message)) => {
+                let suggestions = message.strip_prefix("Unbound symbol: ")
+                    .map(|name| self.closest_bound_names(name))
+                    .unwrap_or_default();
+                if suggestions.is_empty() {
+                    Err(SchemeError::UnboundVariable(message))
+                } else {
+                    let suggestions = suggestions.iter()
+                        .map(|name| format!("`{}`", name))
+                        .collect::<Vec<_>>();
+                    Err(SchemeError::UnboundVariable(format!(
+                        "{}, did you mean {}?", message, suggestions.join(" or ")
+                    )))
+                }
+            },
+            result => result,
+        }
+    }
+
+    fn push_result(&self, value: Value) {
+        let names = ["*1", "*2", "*3"];
+        let previous = names.map(|name| self.to_symbol(self.lookup(name)).ok()
+            .and_then(|id| self.env.borrow().lookup(id)));
+        self.define(names[0], value);
+        for (name, value) in names[1..].iter().zip(previous) {
+            if let Some(value) = value {
+                self.define(name, value);
+            }
+        }
+    }
+
+    // The global names nearest to name by edit distance, in alphabetical
+    // order, if close enough to be a likely typo.
+    pub fn closest_bound_names(&self, name: &str) -> Vec<String> {
+        let max_distance = (name.len() / 3).max(1);
+        let heap = self.heap.borrow();
+        let mut candidates = self.env.borrow().bindings().into_iter()
+            .filter_map(|(id, _)| match heap.get(id) {
+                HeapObject::Symbol(bound) if bound != name => Some(bound.clone()),
+                _ => None,
+            })
+            .map(|bound| (edit_distance(name, &bound), bound))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .collect::<Vec<_>>();
+        candidates.sort();
+        let best = candidates.first().map(|(distance, _)| *distance);
+        candidates.into_iter()
+            .take_while(|(distance, _)| Some(*distance) == best)
+            .map(|(_, bound)| bound)
+            .collect()
     }
 
     pub fn display(&self, obj: Value) -> String {
-        let wrapper = DisplayWrapper{ obj: &obj, interp: self };
+        let wrapper = DisplayWrapper{ obj: &obj, interp: self, write: false };
+        wrapper.to_string()
+    }
+
+    pub fn write(&self, obj: Value) -> String {
+        let wrapper = DisplayWrapper{ obj: &obj, interp: self, write: true };
         wrapper.to_string()
     }
 
+    // Like write, but labels all shared structure rather than only cycles.
+    pub fn write_shared(&self, obj: Value) -> String {
+        self.write_labelled(obj, heap::Labelling::Shared)
+    }
+
+    // Like write, without any labels, it never terminates on cyclic structures.
+    pub fn write_simple(&self, obj: Value) -> String {
+        self.write_labelled(obj, heap::Labelling::Never)
+    }
+
+    fn write_labelled(&self, obj: Value, labelling: heap::Labelling) -> String {
+        struct Labelled<'a>(&'a Interp, Value, heap::Labelling);
+        impl std::fmt::Display for Labelled<'_> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                heap::write_labelled(self.0, f, self.1, self.2)
+            }
+        }
+        Labelled(self, obj, labelling).to_string()
+    }
+
+    // Reports a suspicious but legal construct to the warning handler, or
+    // fails when warnings are configured to be errors.
+    pub fn warn(&self, message: &str) -> Result<(), SchemeError> {
+        if self.warnings_as_errors.get() {
+            return Err(SchemeError::EvalError(message.to_string()));
+        }
+        if let Some(handler) = &*self.warning_handler.borrow() {
+            handler(message);
+            return Ok(());
+        }
+        let mut warnings = self.warnings.borrow_mut();
+        if warnings.len() == MAX_WARNINGS {
+            warnings.pop_front();
+        }
+        warnings.push_back(message.to_string());
+        Ok(())
+    }
+
+    // Sends warnings to handler rather than keeping them for take_warnings,
+    // the REPL prints them as they come.
+    pub fn set_warning_handler(&self, handler: impl Fn(&str) + 'static) {
+        *self.warning_handler.borrow_mut() = Some(Box::new(handler));
+    }
+
+    pub fn set_exit_handler(&self, handler: impl Fn(i32) + 'static) {
+        *self.exit_handler.borrow_mut() = Box::new(handler);
+    }
+
+    // Runs the at-exit hooks, most recent first, then the exit handler. A
+    // failing hook only warns, so that the others still run.
+    pub fn exit(&self, code: i32) {
+        loop {
+            let hook = self.exit_hooks.borrow_mut().pop();
+            let Some(hook) = hook else { break };
+            if let Err(e) = hook.apply(self, &self.env, Vec::new()) {
+                let _ = self.warn(&format!("at-exit: {}", self.display_error(&e)));
+            }
+        }
+        (self.exit_handler.borrow())(code);
+    }
+
+    // Called on global defines, warns the first time a name bound to a
+    // primitive at startup is given a new value, a common source of confusion.
+    pub fn check_redefinition(&self, id: GcId) -> Result<(), SchemeError> {
+        if !self.warn_on_redefinition.get() || self.redefined.borrow().contains(&id) {
+            return Ok(());
+        }
+        // The initial bindings come from the global env, in GcId order.
+        let Ok(index) = self.initial_bindings.binary_search_by_key(&id, |(key, _)| *key) else {
+            return Ok(());
+        };
+        let original = self.initial_bindings[index].1;
+        let is_primitive = self.is_object(original).is_some_and(|obj| {
+            matches!(self.heap.borrow().get(obj), HeapObject::Primitive(_))
+        });
+        if is_primitive {
+            self.redefined.borrow_mut().insert(id);
+            self.warn(&format!("define: redefining the built-in primitive {}.", self.write(Value::Object(id))))?;
+        }
+        Ok(())
+    }
+
+    pub fn set_warn_on_redefinition(&self, warn: bool) {
+        self.warn_on_redefinition.set(warn);
+    }
+
+    pub fn set_warnings_as_errors(&self, as_errors: bool) {
+        self.warnings_as_errors.set(as_errors);
+    }
+
+    // The warnings kept since last taken, oldest first.
+    pub fn take_warnings(&self) -> Vec<String> {
+        self.warnings.take().into()
+    }
+
+    pub fn set_profiling(&self, profiling: bool) {
+        self.profiling.set(profiling);
+    }
+
+    pub fn is_profiling(&self) -> bool {
+        self.profiling.get()
+    }
+
+    pub fn set_optimize_tail_calls(&self, optimize: bool) {
+        self.optimize_tail_calls.set(optimize);
+    }
+
+    pub fn optimize_tail_calls(&self) -> bool {
+        self.optimize_tail_calls.get()
+    }
+
+    pub fn set_vector_map_shortest(&self, shortest: bool) {
+        self.vector_map_shortest.set(shortest);
+    }
+
+    // Allocations fail with a "heap exhausted" error once the heap holds
+    // max_size objects, the garbage is then collected before the error is
+    // reported. Garbage is also collected between the tail calls of a top
+    // level form as the heap fills up, but not within a nested call, whose
+    // allocations must fit in the room left.
+    pub fn set_max_heap_size(&self, max_size: Option<usize>) {
+        self.heap.borrow_mut().set_max_size(max_size);
+    }
+
+    pub fn set_max_depth(&self, max_depth: usize) {
+        self.max_depth.set(max_depth);
+    }
+
+    // Enters a nested evaluation, returning the number of calls on record,
+    // which leave_eval restores.
+    pub fn enter_eval(&self) -> Result<usize, SchemeError> {
+        if self.depth.get() >= self.max_depth.get() {
+            return Err(SchemeError::OverflowError(format!(
+                "Maximum recursion depth {} exceeded, most recent calls: {}",
+                self.max_depth.get(), self.call_trace(TRACE_LENGTH)
+            )));
+        }
+        self.depth.set(self.depth.get() + 1);
+        Ok(self.calls.borrow().len())
+    }
+
+    pub fn leave_eval(&self, frame: usize) {
+        self.calls.borrow_mut().truncate(frame);
+        self.depth.set(self.depth.get() - 1);
+    }
+
+    // Records the call the evaluation entered at frame is making. A call in
+    // tail position replaces the one it was made from.
+    pub fn note_call(&self, frame: usize, operator: Value) {
+        let mut calls = self.calls.borrow_mut();
+        calls.truncate(frame);
+        calls.push(operator);
+    }
+
+    // The operators of the innermost calls, most recent first.
+    fn call_trace(&self, length: usize) -> String {
+        let calls = self.calls.borrow();
+        let mut trace = calls.iter().rev().take(length)
+            .map(|operator| self.write(*operator))
+            .collect::<Vec<String>>()
+            .join(" <- ");
+        if calls.len() > length {
+            trace.push_str(&format!(" <- ... {} more", calls.len() - length));
+        }
+        trace
+    }
+
+    pub fn record_call(&self, id: GcId, elapsed: Duration) {
+        let mut profile = self.profile.borrow_mut();
+        let (calls, total) = profile.entry(id).or_insert((0, Duration::ZERO));
+        *calls += 1;
+        *total += elapsed;
+    }
+
+    // Builds a ((name calls seconds) ...) alist, most called first. Procedures
+    // bound at the top level are named by their symbol.
+    pub fn profile_report(&self) -> Result<Value, SchemeError> {
+        let names: HashMap<GcId, GcId> = self.env.borrow().bindings().into_iter()
+            .filter_map(|(symbol, value)| self.is_object(value).map(|id| (id, symbol)))
+            .collect();
+        let mut entries: Vec<(GcId, u64, Duration)> = self.profile.borrow().iter()
+            .map(|(id, (calls, total))| (*id, *calls, *total))
+            .collect();
+        entries.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let mut heap = self.heap.borrow_mut();
+        let rows = entries.into_iter()
+            .map(|(id, calls, total)| {
+                let name = Value::Object(*names.get(&id).unwrap_or(&id));
+                heap.alloc_list(&[
+                    name,
+                    Value::Number(Number::Int(calls as i64)),
+                    Value::Number(Number::Float(total.as_secs_f64())),
+                ])
+            })
+            .collect::<Result<Vec<Value>, SchemeError>>()?;
+        heap.alloc_list(&rows)
+    }
+
+    pub fn display_error(&self, error: &SchemeError) -> String {
+        match error {
+            SchemeError::UserError(message, irritants) => {
+                let mut text = message.clone();
+                for irritant in irritants {
+                    text.push(' ');
+                    text.push_str(&self.write(*irritant));
+                }
+                text
+            },
+            SchemeError::Continuation(_, value) => format!(
+                "Continuation invoked outside of its extent with {}", self.write(*value)
+            ),
+            SchemeError::Raised(value) => format!("Uncaught raise of {}", self.write(*value)),
+            e => format!("{:?}", e),
+        }
+    }
+
     pub fn is_nil(&self, value: Value) -> bool {
         matches!(value, Value::Nil)
     }
 
+    // A proper list ends in Nil. The fast pointer walks two cdrs for every
+    // one of the slow pointer, and catches up with it on a cyclic list.
     pub fn is_list(&self, value: Value) -> bool {
-        if let Some(id) = self.is_object(value) {
-            matches!(self.heap.borrow().get(id), HeapObject::Pair(..))
-        } else if matches!(value, Value::Nil) {
-            true
-        } else {
-            false
+        self.proper_length(value).is_some()
+    }
+
+    // The length of a proper list, None for improper or circular ones.
+    pub fn proper_length(&self, value: Value) -> Option<usize> {
+        let mut slow = value;
+        let mut fast = value;
+        let mut length = 0;
+        loop {
+            for _ in 0..2 {
+                match self.is_pair(fast) {
+                    Some((_, cdr)) => fast = cdr,
+                    None => return self.is_nil(fast).then_some(length),
+                }
+                length += 1;
+            }
+            slow = self.is_pair(slow).map_or(Value::Nil, |(_, cdr)| cdr);
+            if fast == slow {
+                return None;
+            }
         }
     }
 
@@ -195,7 +951,16 @@ impl Interp {
                 return true;
             }
         }
-        return false;
+        false
+    }
+
+    // Like is_string without the copy, but borrowing the heap as is_vector does.
+    pub fn borrow_string(&self, value: Value) -> Option<Ref<'_, str>> {
+        let id = self.is_object(value)?;
+        Ref::filter_map(self.heap.borrow(), |heap| match heap.get(id) {
+            HeapObject::String(s) => Some(s.as_str()),
+            _ => None,
+        }).ok()
     }
 
     pub fn to_string(&self, value: Value, buf: &mut String) -> Result<bool, SchemeError> {
@@ -248,6 +1013,170 @@ impl Interp {
         }
     }
 
+    // Numbers and chars are immediate values, so eq? can't be any stricter
+    // than eqv? without telling apart equal values.
+    pub fn is_eq(&self, a: Value, b: Value) -> bool {
+        self.is_eqv(a, b)
+    }
+
+    pub fn is_eqv(&self, a: Value, b: Value) -> bool {
+        match (a, b) {
+            // Exact and inexact numbers are never eqv?, even when numerically equal,
+            // unlike with =. Floats are compared bitwise, so 0.0 and -0.0 differ.
+            (Value::Number(Number::Int(x)), Value::Number(Number::Int(y))) => x == y,
+            (Value::Number(Number::Float(x)), Value::Number(Number::Float(y))) => x.to_bits() == y.to_bits(),
+            (Value::Number(_), Value::Number(_)) => false,
+            _ => a == b,
+        }
+    }
+
+    // Terminates on cyclic structures: a pair of pairs or vectors met again
+    // while being compared is assumed equal, any actual difference is still
+    // found along another path.
+    pub fn is_equal(&self, a: Value, b: Value) -> bool {
+        self.is_equal_seen(a, b, &mut HashSet::new())
+    }
+
+    // A hash agreeing with equal?, equal values hash the same. Structures are
+    // walked in order up to EQUAL_HASH_LIMIT parts, which bounds the time
+    // taken by large and cyclic ones, the parts left out don't count.
+    pub fn equal_hash(&self, value: Value) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        let mut pending = vec![value];
+        let mut budget = EQUAL_HASH_LIMIT;
+        while budget > 0 && let Some(value) = pending.pop() {
+            budget -= 1;
+            let Some(id) = self.is_object(value) else {
+                HashKey::from(value).hash(&mut hasher);
+                continue;
+            };
+            match self.heap.borrow().get(id) {
+                HeapObject::Pair(car, cdr) => {
+                    "pair".hash(&mut hasher);
+                    pending.extend([*cdr, *car]);
+                },
+                HeapObject::Vector(items) => {
+                    "vector".hash(&mut hasher);
+                    items.len().hash(&mut hasher);
+                    pending.extend(items.iter().take(budget).rev());
+                },
+                HeapObject::String(text) => text.hash(&mut hasher),
+                HeapObject::Bytevector(bytes) => bytes.hash(&mut hasher),
+                _ => HashKey::Object(id).hash(&mut hasher),
+            }
+        }
+        hasher.finish()
+    }
+
+    fn is_equal_seen(&self, mut a: Value, mut b: Value, seen: &mut HashSet<(GcId, GcId)>) -> bool {
+        // Walks down cdrs in a loop so that long lists don't grow the stack.
+        loop {
+            // Numbers and other atoms are compared as with eqv?.
+            if self.is_eqv(a, b) {
+                return true;
+            }
+            let (Some(id_a), Some(id_b)) = (self.is_object(a), self.is_object(b)) else {
+                return false;
+            };
+            let (obj_a, obj_b) = {
+                let heap = self.heap.borrow();
+                (heap.get(id_a).clone(), heap.get(id_b).clone())
+            };
+            match (obj_a, obj_b) {
+                (HeapObject::Pair(car_a, cdr_a), HeapObject::Pair(car_b, cdr_b)) => {
+                    if !seen.insert((id_a, id_b)) {
+                        return true;
+                    }
+                    if !self.is_equal_seen(car_a, car_b, seen) {
+                        return false;
+                    }
+                    (a, b) = (cdr_a, cdr_b);
+                },
+                (HeapObject::String(s_a), HeapObject::String(s_b)) => return s_a == s_b,
+                (HeapObject::Bytevector(b_a), HeapObject::Bytevector(b_b)) => return b_a == b_b,
+                (HeapObject::Vector(v_a), HeapObject::Vector(v_b)) => {
+                    if !seen.insert((id_a, id_b)) {
+                        return true;
+                    }
+                    return v_a.len() == v_b.len()
+                        && v_a.iter().zip(v_b.iter()).all(|(x, y)| self.is_equal_seen(*x, *y, seen));
+                },
+                _ => return false,
+            }
+        }
+    }
+
+    pub fn is_values(&self, value: Value) -> Option<Vec<Value>> {
+        let id = self.is_object(value)?;
+        match self.heap.borrow().get(id) {
+            HeapObject::Values(values) => Some(values.clone()),
+            _ => None,
+        }
+    }
+
+    // Multiple values are only accepted by call-with-values.
+    pub fn single_value(&self, value: Value) -> Result<Value, SchemeError> {
+        match self.is_values(value) {
+            Some(values) => Err(SchemeError::EvalError(format!(
+                "Expected a single value, but got {} values.", values.len()
+            ))),
+            None => Ok(value),
+        }
+    }
+
+    pub fn is_procedure(&self, value: Value) -> bool {
+        self.is_object(value).is_some_and(|id| matches!(
+            self.heap.borrow().get(id),
+            HeapObject::Primitive(_) | HeapObject::Closure(_)
+                | HeapObject::NaryClosure(_) | HeapObject::Continuation(_)
+                | HeapObject::Parameter { .. } | HeapObject::Memoized { .. }
+                | HeapObject::Curried { .. }
+        ))
+    }
+
+    // The least and, if bounded, the most args a closure accepts. None for
+    // other procedures, whose arity is only checked when they are called.
+    pub fn closure_arity(&self, value: Value) -> Option<(usize, Option<usize>)> {
+        let id = self.is_object(value)?;
+        match self.heap.borrow().get(id) {
+            HeapObject::Closure(closure) => Some((closure.param_count(), Some(closure.param_count()))),
+            HeapObject::NaryClosure(closure) => Some((closure.param_count() - 1, None)),
+            _ => None,
+        }
+    }
+
+    // The procedure passed as argument position (1-based) of name.
+    pub fn check_procedure(&self, name: &str, value: Value, position: usize) -> Result<Value, SchemeError> {
+        if self.is_procedure(value) {
+            Ok(value)
+        } else {
+            Err(arg_type_error(name, "Procedure", position))
+        }
+    }
+
+    pub fn is_macro(&self, value: Value) -> Option<Rc<SyntaxRules>> {
+        let id = self.is_object(value)?;
+        match self.heap.borrow().get(id) {
+            HeapObject::Macro(rules) => Some(Rc::clone(rules)),
+            _ => None,
+        }
+    }
+
+    // Borrows the heap, the items must be copied out before allocating.
+    pub fn is_vector(&self, value: Value) -> Option<Ref<'_, [Value]>> {
+        let id = self.is_object(value)?;
+        Ref::filter_map(self.heap.borrow(), |heap| match heap.get(id) {
+            HeapObject::Vector(items) => Some(items.as_slice()),
+            _ => None,
+        }).ok()
+    }
+
+    pub fn to_vector(&self, value: Value) -> Result<Ref<'_, [Value]>, SchemeError> {
+        self.is_vector(value).ok_or_else(|| SchemeError::TypeError(format!(
+            "Expected a Vector, but got a {}.", value.type_name()
+        )))
+    }
+
     pub fn to_symbol(&self, value: Value) -> Result<GcId, SchemeError> {
         let id = self.to_object(value)?;
         match self.heap.borrow().get(id) {
@@ -258,10 +1187,185 @@ impl Interp {
         }
     }
 
+    pub fn call_cc(&self, func: Value) -> Result<Value, SchemeError> {
+        let escape_id = self.next_escape_id.get();
+        self.next_escape_id.set(escape_id + 1);
+        let k = self.heap.borrow_mut().alloc_continuation(escape_id)?;
+        match func.apply(self, &self.env, vec![k]) {
+            Err(SchemeError::Continuation(target, value)) if target == escape_id => Ok(value),
+            result => result,
+        }
+    }
+
+    pub fn with_exception_handler(&self, handler: Value, thunk: Value) -> Result<Value, SchemeError> {
+        self.handlers.borrow_mut().push(Some(handler));
+        let result = thunk.apply(self, &self.env, Vec::new());
+        self.handlers.borrow_mut().pop();
+        result
+    }
+
+    // Runs body with a guard as the current exception handler, so that the
+    // handlers installed outside of it are not called for its raises.
+    pub fn with_guard(&self, body: impl FnOnce() -> Result<Value, SchemeError>) -> Result<Value, SchemeError> {
+        self.handlers.borrow_mut().push(None);
+        let result = body();
+        self.handlers.borrow_mut().pop();
+        result
+    }
+
+    // Calls the current exception handler with the raised object, or the
+    // condition wrapping error, while the handlers outside of it are installed.
+    // Without a handler, or under a guard, the error unwinds the stack instead.
+    // Only a continuable raise may return, with the value of the handler.
+    pub fn raise(&self, error: SchemeError, continuable: bool) -> Result<Value, SchemeError> {
+        let handler = match self.handlers.borrow().last() {
+            Some(Some(handler)) => *handler,
+            _ => return Err(error),
+        };
+        let obj = match error {
+            SchemeError::Raised(value) => value,
+            error => self.heap.borrow_mut().alloc_condition(error)?,
+        };
+        let installed = self.handlers.borrow_mut().pop();
+        let result = match handler.apply(self, &self.env, vec![obj]) {
+            Ok(value) if continuable => Ok(value),
+            Ok(_) => self.raise(SchemeError::EvalError(format!(
+                "Exception handler returned from non-continuable raise of {}", self.write(obj)
+            )), false),
+            Err(error) => Err(error),
+        };
+        self.handlers.borrow_mut().extend(installed);
+        result
+    }
+
+    // Continuations only escape, so the thunk can't be re-entered and before
+    // runs exactly once. Exception handlers are called where raise is, inside
+    // the thunk, while guard clauses run once it has unwound, after the after.
+    pub fn dynamic_wind(&self, before: Value, thunk: Value, after: Value) -> Result<Value, SchemeError> {
+        before.apply(self, &self.env, Vec::new())?;
+        let result = thunk.apply(self, &self.env, Vec::new());
+        // The after thunk runs whether the thunk returned or unwound, by an
+        // error, a raise or an escaping continuation.
+        after.apply(self, &self.env, Vec::new())?;
+        result
+    }
+
+    // Runs a value through the parameter's converter, if it has one.
+    pub fn convert_parameter(&self, id: GcId, value: Value) -> Result<Value, SchemeError> {
+        let converter = match self.heap.borrow().get(id) {
+            HeapObject::Parameter { converter, .. } => *converter,
+            obj => return Err(SchemeError::TypeError(format!(
+                "Expected a Parameter, but got a {} instead.", obj.type_name()
+            ))),
+        };
+        match converter {
+            Some(converter) => converter.apply(self, &self.env, vec![value]),
+            None => Ok(value),
+        }
+    }
+
+    pub fn format_number(&self, number: Number) -> String {
+        let precision = match self.float_print_precision {
+            Value::Object(id) => match self.heap.borrow().get(id) {
+                HeapObject::Parameter { value: Value::Number(Number::Int(digits)), .. } => Some(*digits as usize),
+                _ => None,
+            },
+            _ => None,
+        };
+        match (number, precision) {
+            (Number::Float(f), Some(digits)) => format!("{:.*}", digits, f),
+            _ => number.to_string(),
+        }
+    }
+
+    pub fn force(&self, value: Value) -> Result<Value, SchemeError> {
+        let Some(id) = self.is_object(value) else {
+            return Ok(value);
+        };
+        loop {
+            let (thunk, lazy) = match self.heap.borrow().get(id) {
+                HeapObject::Promise { forced: true, value, .. } => return Ok(*value),
+                HeapObject::Promise { thunk, lazy, .. } => (*thunk, *lazy),
+                // Forcing a non-promise simply returns it.
+                _ => return Ok(value),
+            };
+            let result = match self.is_object(thunk).map(|thunk| self.heap.borrow().get(thunk).clone()) {
+                Some(HeapObject::DelayedPair { head, tail, env }) => {
+                    let head = self.single_value(head.eval(self, &env)?)?;
+                    let tail = self.single_value(tail.eval(self, &env)?)?;
+                    self.heap.borrow_mut().alloc_pair(head, tail)?
+                },
+                _ => thunk.apply(self, &self.env, Vec::new())?,
+            };
+            if !lazy {
+                return self.heap.borrow_mut().resolve_promise(id, result);
+            }
+            self.heap.borrow_mut().chain_promise(id, result)?;
+        }
+    }
+
+    // Runs func on the table passed as the first argument of name.
+    pub fn with_hash_table<T, F>(&self, name: &str, value: Value, func: F) -> Result<T, SchemeError>
+        where
+        F: FnOnce(&mut HashMap<HashKey, Value>) -> T
+    {
+        let type_error = || arg_type_error(name, "HashTable", 1);
+        let id = self.to_object(value).map_err(|_| type_error())?;
+        let mut heap = self.heap.borrow_mut();
+        Ok(func(heap.hash_table_mut(id).map_err(|_| type_error())?))
+    }
+
+    // Sends text to the given port, or to stdout when there is none.
+    pub fn write_output(&self, port: Option<Value>, text: &str) -> Result<(), SchemeError> {
+        match port {
+            Some(port) => {
+                let id = self.to_object(port)?;
+                self.heap.borrow_mut().output_string_mut(id)?.push_str(text);
+            },
+            None => print!("{}", text),
+        }
+        Ok(())
+    }
+
+    // Output strings hold all their text already, only stdout needs flushing.
+    pub fn flush_output(&self, port: Option<Value>) -> Result<(), SchemeError> {
+        match port {
+            Some(port) => {
+                let id = self.to_object(port)?;
+                self.heap.borrow_mut().output_string_mut(id)?;
+            },
+            None => io::stdout().flush().map_err(|e| SchemeError::EvalError(format!(
+                "flush-output-port: {}.", e
+            )))?,
+        }
+        Ok(())
+    }
+
+    // Reads the next datum from an input string port, or the eof object.
+    pub fn read_port(&self, port: Value) -> Result<Value, SchemeError> {
+        let id = self.to_object(port)?;
+        let (text, start) = {
+            let mut heap = self.heap.borrow_mut();
+            let (text, position) = heap.input_string_mut(id)?;
+            (Rc::clone(text), *position)
+        };
+        // Parsing goes no further than the datum, whatever is left after it.
+        let mut parser = Parser::new(&text.as_bytes()[start..]);
+        let result = if parser.at_eof() {
+            Ok(Value::Eof)
+        } else {
+            parser.read(self)
+        };
+        let mut heap = self.heap.borrow_mut();
+        let (_, position) = heap.input_string_mut(id)?;
+        *position = start + parser.position();
+        result
+    }
+
     pub fn load(&self, filename: &str) -> Result<Value, SchemeError> {
         match File::open(filename) {
             Ok(input) => {
-                let mut parser = Parser::new(input);
+                let mut parser = Parser::new(BufReader::new(input));
                 let mut retval = Value::Nil;
                 while let Ok(expr) = parser.read(self) {
                     if matches!(expr, Value::Nil) {
@@ -280,141 +1384,881 @@ impl Interp {
 }
 
 fn primitive_add(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    let nums = all_of_type!(args, Value::Number, "Number");
-    let sum = nums.into_iter()
-        .fold(Number::Int(0), |acc, n| acc  + n);
-    Ok(Value::Number(sum))
+    let nums = all_of_type!("+", args, Value::Number, "Number");
+    let sum = nums.iter()
+        .fold(Number::Int(0), |acc, n| acc  + *n);
+    Ok(Value::Number(Number::contagion(&nums, sum)))
 }
 
 fn primitive_sub(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    let nums = all_of_type!(args, Value::Number, "Number");
-    if nums.is_empty() {
-        return Err(SchemeError::ArgCountError(
-            "- expects at least one arg.".to_string()
-        ))
-    }
+    check_arity!("-", args, 1..);
+    let nums = all_of_type!("-", args, Value::Number, "Number");
 
-    let mut iter = nums.into_iter();
+    let mut iter = nums.iter().copied();
     let init = iter.next().unwrap();
-    let sub = if let None = iter.clone().next() {
+    let sub = if iter.clone().next().is_none() {
         - init
     } else {
         iter.fold(init, |acc, n| acc - n)
     };
-    Ok(Value::Number(sub))
+    Ok(Value::Number(Number::contagion(&nums, sub)))
 }
 
 fn primitive_div(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    let nums = all_of_type!(args, Value::Number, "Number");
-    if nums.is_empty() {
-        return Err(SchemeError::ArgCountError(
-            "- expects at least one arg.".to_string()
-        ))
+    check_arity!("/", args, 1..);
+    let nums = all_of_type!("/", args, Value::Number, "Number");
+
+    // Only an exact zero is an error, inexact ones give infinities or NaN.
+    let divisors = if nums.len() == 1 { &nums[..] } else { &nums[1..] };
+    if divisors.iter().any(|n| matches!(n, Number::Int(0))) {
+        return Err(SchemeError::DivisionByZero("/: division by zero.".to_string()));
     }
 
-    let mut iter = nums.into_iter();
+    let mut iter = nums.iter().copied();
     let init = iter.next().unwrap();
-    let div = if let None = iter.clone().next() {
-        Number::Float(1.0) / init
+    let div = if iter.clone().next().is_none() {
+        Number::Int(1) / init
     } else {
         iter.fold(init, |acc, n| acc / n)
     };
-    Ok(Value::Number(div))
+    Ok(Value::Number(Number::contagion(&nums, div)))
 }
 
 
 fn primitive_mul(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    let nums = all_of_type!(args, Value::Number, "Number");
-    let mul = nums.into_iter()
-        .fold(Number::Int(1), |acc, n| acc * n);
-    Ok(Value::Number(mul))
+    let nums = all_of_type!("*", args, Value::Number, "Number");
+    let mul = nums.iter()
+        .fold(Number::Int(1), |acc, n| acc * *n);
+    Ok(Value::Number(Number::contagion(&nums, mul)))
 }
 
 fn primitive_rem(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 2, a: Number, b: Number);
-    Ok(Value::Number(*a % *b))
+    extract_args!("%", args, 2, a: Number, b: Number);
+    if let Number::Int(0) = b {
+        return Err(SchemeError::DivisionByZero("%: division by zero.".to_string()));
+    }
+    Ok(Value::Number(Number::contagion(&[*a, *b], *a % *b)))
 }
 
-fn primitive_quit(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 1, exit_code: Number);
-    match i32::try_from(*exit_code) {
-        Ok(code) => process::exit(code),
+// (quit [code]) exits with code, 0 by default, once the at-exit hooks ran.
+fn primitive_quit(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let exit_code = match args {
+        [] => Number::Int(0),
+        [Value::Number(n)] => *n,
+        [_] => return Err(arg_type_error("quit", "Number", 1)),
+        _ => return Err(arg_count_error("quit", "0 to 1", args.len())),
+    };
+    match i32::try_from(exit_code) {
+        Ok(code) => {
+            interp.exit(code);
+            Ok(Value::Unspecified)
+        },
         Err(_) => Err(SchemeError::OverflowError(format!(
             "Overflow while converting {} to i32", exit_code)
         ))
     }
+}
 
+// (at-exit thunk) has thunk called when quit or exit is, the thunks
+// registered last are called first.
+fn primitive_at_exit(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("at-exit", args, 1);
+    interp.check_procedure("at-exit", args[0], 1)?;
+    interp.exit_hooks.borrow_mut().push(args[0]);
+    Ok(Value::Unspecified)
 }
 
 fn primitive_number_eq(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 2, a: Number, b: Number);
-    return Ok(Value::Boolean(a == b))
+    extract_args!("=", args, 2, a: Number, b: Number);
+    Ok(Value::Boolean(a == b))
 }
 
 fn primitive_number_lt(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 2, a: Number, b: Number);
-    return Ok(Value::Boolean(a < b))
+    extract_args!("<", args, 2, a: Number, b: Number);
+    Ok(Value::Boolean(a < b))
 }
 
 fn primitive_number_lte(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 2, a: Number, b: Number);
-    return Ok(Value::Boolean(a <= b))
+    extract_args!("<=", args, 2, a: Number, b: Number);
+    Ok(Value::Boolean(a <= b))
 }
 
 fn primitive_number_gt(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 2, a: Number, b: Number);
-    return Ok(Value::Boolean(a > b))
+    extract_args!(">", args, 2, a: Number, b: Number);
+    Ok(Value::Boolean(a > b))
 }
 
 fn primitive_number_gte(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 2, a: Number, b: Number);
-    return Ok(Value::Boolean(a >= b))
+    extract_args!(">=", args, 2, a: Number, b: Number);
+    Ok(Value::Boolean(a >= b))
 }
 
-fn primitive_number_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    check_arity!(args, 1);
+// Levenshtein distance between two strings, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// Tolerance of approx=? when none is given.
+const DEFAULT_EPSILON: f64 = 1e-9;
+
+// (approx=? a b [eps]) is true when |a - b| <= eps.
+fn primitive_approx_eq(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("approx=?", args, 2..=3);
+    let nums = all_of_type!("approx=?", args, Value::Number, "Number");
+    let as_float = |n: Number| match n {
+        Number::Int(i) => i as f64,
+        Number::Float(f) => f,
+    };
+    let eps = nums.get(2).map_or(DEFAULT_EPSILON, |eps| as_float(*eps));
+    Ok(Value::Boolean((as_float(nums[0]) - as_float(nums[1])).abs() <= eps))
+}
+
+fn primitive_number_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("number?", args, 1);
     Ok(Value::Boolean(interp.is_number(args[0]).is_some()))
 }
 
 fn primitive_integer_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    check_arity!(args, 1);
+    check_arity!("integer?", args, 1);
     Ok(Value::Boolean(interp.is_integer(args[0]).is_some()))
 }
 
 fn primitive_float_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    check_arity!(args, 1);
+    check_arity!("float?", args, 1);
     Ok(Value::Boolean(interp.is_float(args[0]).is_some()))
 }
 
+fn primitive_even_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("even?", args, 1);
+    let (_, ints) = integer_operands("even?", args)?;
+    Ok(Value::Boolean(ints[0] % 2 == 0))
+}
+
+fn primitive_odd_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("odd?", args, 1);
+    let (_, ints) = integer_operands("odd?", args)?;
+    Ok(Value::Boolean(ints[0] % 2 != 0))
+}
+
+fn primitive_boolean_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("boolean?", args, 1);
+    Ok(Value::Boolean(matches!(args[0], Value::Boolean(_))))
+}
+
+fn primitive_unspecified_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("unspecified?", args, 1);
+    Ok(Value::Boolean(args[0] == Value::Unspecified))
+}
+
+fn primitive_not(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("not", args, 1);
+    Ok(Value::Boolean(args[0].is_false()))
+}
+
+fn primitive_symbol_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("symbol?", args, 1);
+    Ok(Value::Boolean(interp.to_symbol(args[0]).is_ok()))
+}
+
+fn primitive_string_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("string?", args, 1);
+    let is_string = interp.is_object(args[0]).is_some_and(|id| {
+        matches!(interp.heap.borrow().get(id), HeapObject::String(_))
+    });
+    Ok(Value::Boolean(is_string))
+}
+
+fn primitive_pair_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("pair?", args, 1);
+    Ok(Value::Boolean(interp.is_pair(args[0]).is_some()))
+}
+
+fn primitive_vector_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("vector?", args, 1);
+    Ok(Value::Boolean(interp.is_vector(args[0]).is_some()))
+}
+
 fn primitive_number_max(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    let nums = all_of_type!(args, Value::Number, "Number");
-    if nums.is_empty() {
-        return Err(SchemeError::ArgCountError(
-            "max expects at least one arg.".to_string()));
-    }
+    check_arity!("max", args, 1..);
+    let nums = all_of_type!("max", args, Value::Number, "Number");
     let init = nums[0];
-    let ret = nums.into_iter()
-        .fold(init, |a, b| if a > b { a } else { b });
-    Ok(Value::Number(ret))
+    let ret = nums.iter()
+        .fold(init, |a, b| if a > *b { a } else { *b });
+    Ok(Value::Number(Number::contagion(&nums, ret)))
 }
 
 fn primitive_number_min(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    let nums = all_of_type!(args, Value::Number, "Number");
-    if nums.is_empty() {
-        return Err(SchemeError::ArgCountError(
-            "min expects at least one arg.".to_string()));
-    }
+    check_arity!("min", args, 1..);
+    let nums = all_of_type!("min", args, Value::Number, "Number");
     let init = nums[0];
-    let ret = nums.into_iter()
-        .fold(init, |a, b| if a < b { a } else { b });
-    Ok(Value::Number(ret))
+    let ret = nums.iter()
+        .fold(init, |a, b| if a < *b { a } else { *b });
+    Ok(Value::Number(Number::contagion(&nums, ret)))
+}
+
+// Strings hold bytes as chars do, only ASCII bytes can be stored into them
+// without breaking their UTF-8 encoding.
+fn ascii_char(name: &str, value: Value, position: usize) -> Result<u8, SchemeError> {
+    match value {
+        Value::Char(ch) if ch.is_ascii() => Ok(ch),
+        _ => Err(arg_type_error(name, "ASCII Char", position)),
+    }
+}
+
+fn string_index(name: &str, s: &str, index: Value) -> Result<usize, SchemeError> {
+    checked_index(name, s.len(), index)
+}
+
+// Checks the index, passed as argument 2, against the sequence length.
+fn checked_index(name: &str, len: usize, index: Value) -> Result<usize, SchemeError> {
+    let index = match index {
+        Value::Number(Number::Int(i)) => i,
+        _ => return Err(arg_type_error(name, "Int", 2)),
+    };
+    usize::try_from(index).ok()
+        .filter(|i| *i < len)
+        .ok_or_else(|| SchemeError::EvalError(format!(
+            "{}: index {} out of range for length {}.", name, index, len
+        )))
+}
+
+// (make-string k [fill]) fills with spaces by default.
+fn primitive_make_string(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("make-string", args, 1..=2);
+    let length = interp.as_integer(args[0]).map_err(|_| arg_type_error("make-string", "Int", 1))?;
+    let length = usize::try_from(length).map_err(|_| {
+        SchemeError::EvalError("make-string: negative length.".to_string())
+    })?;
+    let fill = match args.get(1) {
+        Some(fill) => ascii_char("make-string", *fill, 2)?,
+        None => b' ',
+    };
+    let bytes = filled("make-string", length, fill)?;
+    let text = String::from_utf8(bytes).expect("An ASCII fill is valid UTF-8.");
+    interp.heap.borrow_mut().alloc_string(text)
+}
+
+// Length copies of fill, failing with an error rather than aborting when
+// length is over MAX_FILL_LENGTH or can't be allocated.
+fn filled(name: &str, length: usize, fill: u8) -> Result<Vec<u8>, SchemeError> {
+    let too_long = || SchemeError::EvalError(format!("{}: length {} is too long.", name, length));
+    if length > MAX_FILL_LENGTH {
+        return Err(too_long());
+    }
+    let mut bytes = Vec::new();
+    bytes.try_reserve_exact(length).map_err(|_| too_long())?;
+    bytes.resize(length, fill);
+    Ok(bytes)
+}
+
+fn primitive_string_length(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("string-length", args, 1);
+    let text = interp.borrow_string(args[0]).ok_or_else(|| arg_type_error("string-length", "String", 1))?;
+    Ok(Value::Number(Number::Int(text.len() as i64)))
+}
+
+fn primitive_string_ref(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("string-ref", args, 2);
+    let text = interp.borrow_string(args[0]).ok_or_else(|| arg_type_error("string-ref", "String", 1))?;
+    let index = string_index("string-ref", &text, args[1])?;
+    Ok(Value::Char(text.as_bytes()[index]))
+}
+
+fn primitive_string_set(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("string-set!", args, 3);
+    let type_error = || arg_type_error("string-set!", "String", 1);
+    let id = interp.to_object(args[0]).map_err(|_| type_error())?;
+    let ch = ascii_char("string-set!", args[2], 3)?;
+    let mut heap = interp.heap.borrow_mut();
+    let text = heap.string_mut(id).map_err(|_| type_error())?;
+    let index = string_index("string-set!", text, args[1])?;
+    if !text.is_char_boundary(index + 1) {
+        return Err(SchemeError::EvalError(format!(
+            "string-set!: index {} is inside a non-ASCII character.", index
+        )));
+    }
+    text.replace_range(index..index + 1, (ch as char).encode_utf8(&mut [0; 4]));
+    Ok(Value::Unspecified)
+}
+
+// (string-copy s [start [end]]) returns a fresh, independently mutable string.
+fn primitive_string_copy(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("string-copy", args, 1..=3);
+    let mut text = String::new();
+    interp.to_string(args[0], &mut text).map_err(|_| arg_type_error("string-copy", "String", 1))?;
+    let bound = |position: usize, default: usize| -> Result<usize, SchemeError> {
+        match args.get(position - 1) {
+            Some(value) => {
+                let index = interp.as_integer(*value)
+                    .map_err(|_| arg_type_error("string-copy", "Int", position))?;
+                usize::try_from(index).ok()
+                    .filter(|i| *i <= text.len())
+                    .ok_or_else(|| SchemeError::EvalError(format!(
+                        "string-copy: index {} out of range for length {}.", index, text.len()
+                    )))
+            },
+            None => Ok(default),
+        }
+    };
+    let start = bound(2, 0)?;
+    let end = bound(3, text.len())?;
+    let copy = text.get(start..end.max(start)).ok_or_else(|| SchemeError::EvalError(
+        "string-copy: range splits a non-ASCII character.".to_string()
+    ))?;
+    interp.heap.borrow_mut().alloc_string(copy)
+}
+
+// Splits (name s n) at the nth char from the start, or from the end when
+// from_end is set, returning the prefix and the suffix.
+fn split_string(
+    interp: &Interp, name: &str, args: &[Value], from_end: bool
+) -> Result<(String, String), SchemeError> {
+    check_arity!(name, args, 2);
+    let mut text = String::new();
+    interp.to_string(args[0], &mut text).map_err(|_| arg_type_error(name, "String", 1))?;
+    let count = interp.as_integer(args[1]).map_err(|_| arg_type_error(name, "Int", 2))?;
+    let count = usize::try_from(count).ok()
+        .filter(|n| *n <= text.len())
+        .ok_or_else(|| SchemeError::EvalError(format!(
+            "{}: count {} out of range for length {}.", name, interp.write(args[1]), text.len()
+        )))?;
+    let at = if from_end { text.len() - count } else { count };
+    if !text.is_char_boundary(at) {
+        return Err(SchemeError::EvalError(format!("{}: count splits a non-ASCII character.", name)));
+    }
+    let suffix = text.split_off(at);
+    Ok((text, suffix))
+}
+
+fn primitive_string_take(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let (prefix, _) = split_string(interp, "string-take", args, false)?;
+    interp.heap.borrow_mut().alloc_string(prefix)
+}
+
+fn primitive_string_drop(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let (_, suffix) = split_string(interp, "string-drop", args, false)?;
+    interp.heap.borrow_mut().alloc_string(suffix)
+}
+
+fn primitive_string_take_right(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let (_, suffix) = split_string(interp, "string-take-right", args, true)?;
+    interp.heap.borrow_mut().alloc_string(suffix)
+}
+
+fn primitive_string_drop_right(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let (prefix, _) = split_string(interp, "string-drop-right", args, true)?;
+    interp.heap.borrow_mut().alloc_string(prefix)
+}
+
+fn primitive_string_fill(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("string-fill!", args, 2);
+    let type_error = || arg_type_error("string-fill!", "String", 1);
+    let id = interp.to_object(args[0]).map_err(|_| type_error())?;
+    let ch = ascii_char("string-fill!", args[1], 2)?;
+    let mut heap = interp.heap.borrow_mut();
+    let text = heap.string_mut(id).map_err(|_| type_error())?;
+    *text = (ch as char).to_string().repeat(text.len());
+    Ok(Value::Unspecified)
+}
+
+fn primitive_abs(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("abs", args, 1, n: Number);
+    match n {
+        Number::Int(i) => i.checked_abs().map(|i| Value::Number(Number::Int(i))).ok_or_else(|| {
+            SchemeError::OverflowError(format!("abs: overflow on {}.", i))
+        }),
+        Number::Float(f) => Ok(Value::Number(Number::Float(f.abs()))),
+    }
+}
+
+// Rounding preserves exactness: integers are returned as is, and floats
+// are rounded to the nearest integral float.
+fn round_number(name: &str, args: &[Value], round: fn(f64) -> f64) -> Result<Value, SchemeError> {
+    extract_args!(name, args, 1, n: Number);
+    match *n {
+        Number::Int(_) => Ok(Value::Number(*n)),
+        Number::Float(f) => Ok(Value::Number(Number::Float(round(f)))),
+    }
+}
+
+fn primitive_floor(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    round_number("floor", args, f64::floor)
+}
+
+fn primitive_ceiling(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    round_number("ceiling", args, f64::ceil)
+}
+
+fn primitive_truncate(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    round_number("truncate", args, f64::trunc)
+}
+
+// Rounds to even on ties, as R7RS requires: (round 2.5) is 2.0.
+fn primitive_round(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    round_number("round", args, f64::round_ties_even)
+}
+
+// Exact by repeated squaring, or None on overflow.
+fn checked_pow(mut base: i64, mut exponent: u64) -> Option<i64> {
+    let mut result: i64 = 1;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result.checked_mul(base)?;
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            base = base.checked_mul(base)?;
+        }
+    }
+    Some(result)
+}
+
+// (expt base exponent) is exact for an exact base raised to a non-negative
+// integer. Without rationals, negative exponents give a float: (expt 2 -1) is 0.5.
+fn primitive_expt(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("expt", args, 2, base: Number, exponent: Number);
+    let result = match (*base, *exponent) {
+        (Number::Int(0), Number::Int(e)) if e < 0 => {
+            return Err(SchemeError::DivisionByZero("expt: 0 raised to a negative power.".to_string()));
+        },
+        (Number::Int(b), Number::Int(e)) if e >= 0 => {
+            let power = checked_pow(b, e as u64).ok_or_else(|| {
+                SchemeError::OverflowError(format!("expt: overflow on {} to the {}.", b, e))
+            })?;
+            Number::Int(power)
+        },
+        (Number::Int(b), Number::Int(e)) => Number::Float((b as f64).powf(e as f64)),
+        (b, e) => {
+            let as_float = |n: Number| match n {
+                Number::Int(i) => i as f64,
+                Number::Float(f) => f,
+            };
+            Number::Float(as_float(b).powf(as_float(e)))
+        },
+    };
+    Ok(Value::Number(result))
+}
+
+// Integer operands of gcd and lcm, which also accept integral floats.
+fn integer_operands(name: &str, args: &[Value]) -> Result<(Vec<Number>, Vec<i64>), SchemeError> {
+    let nums = all_of_type!(name, args, Value::Number, "Number");
+    let ints = nums.iter().enumerate().map(|(i, n)| match n {
+        Number::Int(i) => Ok(*i),
+        Number::Float(f) if f.fract() == 0.0 => Ok(*f as i64),
+        _ => Err(arg_type_error(name, "Integer", i + 1)),
+    }).collect::<Result<Vec<_>, SchemeError>>()?;
+    Ok((nums, ints))
+}
+
+// On magnitudes, as the gcd of i64::MIN and 0 only fits in a u64.
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn primitive_gcd(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let (nums, ints) = integer_operands("gcd", args)?;
+    let ret = ints.into_iter().map(i64::unsigned_abs).fold(0, gcd);
+    let ret = i64::try_from(ret)
+        .map_err(|_| SchemeError::OverflowError("gcd: overflow.".to_string()))?;
+    Ok(Value::Number(Number::contagion(&nums, Number::Int(ret))))
+}
+
+fn primitive_lcm(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let (nums, ints) = integer_operands("lcm", args)?;
+    let ret = ints.into_iter().map(i64::unsigned_abs).try_fold(1u64, |acc, n| {
+        if n == 0 || acc == 0 {
+            return Some(0);
+        }
+        (acc / gcd(acc, n)).checked_mul(n)
+    }).and_then(|ret| i64::try_from(ret).ok())
+        .ok_or_else(|| SchemeError::OverflowError("lcm: overflow.".to_string()))?;
+    Ok(Value::Number(Number::contagion(&nums, Number::Int(ret))))
+}
+
+#[derive(Clone, Copy)]
+enum Rounding {
+    // The quotient is rounded toward zero, the remainder has the dividend's sign.
+    Truncate,
+    // The quotient is rounded down, the remainder has the divisor's sign.
+    Floor,
+    // The remainder is never negative.
+    Euclidean,
+}
+
+fn integer_division(name: &str, args: &[Value], rounding: Rounding) -> Result<(Number, Number), SchemeError> {
+    check_arity!(name, args, 2);
+    let (nums, ints) = integer_operands(name, args)?;
+    let (a, b) = (ints[0], ints[1]);
+    if b == 0 {
+        return Err(SchemeError::DivisionByZero(format!("{}: division by zero.", name)));
+    }
+    let overflow = || SchemeError::OverflowError(format!("{}: overflow on {} / {}.", name, a, b));
+    let (mut q, mut r) = (a.checked_div(b).ok_or_else(overflow)?, a % b);
+    match rounding {
+        Rounding::Truncate => (),
+        Rounding::Floor => if r != 0 && (r < 0) != (b < 0) {
+            q -= 1;
+            r += b;
+        },
+        Rounding::Euclidean => {
+            q = a.checked_div_euclid(b).ok_or_else(overflow)?;
+            r = a.rem_euclid(b);
+        },
+    }
+    Ok((Number::contagion(&nums, Number::Int(q)), Number::contagion(&nums, Number::Int(r))))
+}
+
+fn division_values(interp: &Interp, name: &str, args: &[Value], rounding: Rounding) -> Result<Value, SchemeError> {
+    let (q, r) = integer_division(name, args, rounding)?;
+    interp.heap.borrow_mut().alloc_values(&[Value::Number(q), Value::Number(r)])
+}
+
+fn primitive_truncate_div(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    division_values(interp, "truncate/", args, Rounding::Truncate)
+}
+
+fn primitive_truncate_quotient(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    Ok(Value::Number(integer_division("truncate-quotient", args, Rounding::Truncate)?.0))
+}
+
+fn primitive_truncate_remainder(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    Ok(Value::Number(integer_division("truncate-remainder", args, Rounding::Truncate)?.1))
+}
+
+fn primitive_floor_div(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    division_values(interp, "floor/", args, Rounding::Floor)
+}
+
+fn primitive_floor_quotient(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    Ok(Value::Number(integer_division("floor-quotient", args, Rounding::Floor)?.0))
+}
+
+fn primitive_floor_remainder(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    Ok(Value::Number(integer_division("floor-remainder", args, Rounding::Floor)?.1))
+}
+
+fn primitive_euclidean_div(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    division_values(interp, "euclidean/", args, Rounding::Euclidean)
+}
+
+fn primitive_euclidean_quotient(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    Ok(Value::Number(integer_division("euclidean-quotient", args, Rounding::Euclidean)?.0))
+}
+
+fn primitive_euclidean_remainder(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    Ok(Value::Number(integer_division("euclidean-remainder", args, Rounding::Euclidean)?.1))
+}
+
+fn primitive_exact_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("exact?", args, 1, n: Number);
+    Ok(Value::Boolean(n.is_exact()))
+}
+
+fn primitive_inexact_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("inexact?", args, 1, n: Number);
+    Ok(Value::Boolean(!n.is_exact()))
+}
+
+// Valid indices and lengths for strings, vectors and bytevectors.
+fn primitive_exact_nonnegative_integer_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("exact-nonnegative-integer?", args, 1);
+    Ok(Value::Boolean(matches!(args[0], Value::Number(Number::Int(i)) if i >= 0)))
+}
+
+// (number->string n [radix]) renders floats in radix 10 only, honoring
+// float-print-precision.
+fn primitive_number_to_string(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("number->string", args, 1..=2);
+    let Value::Number(number) = args[0] else {
+        return Err(arg_type_error("number->string", "Number", 1));
+    };
+    let radix = match args.get(1) {
+        Some(radix) => interp.as_integer(*radix).map_err(|_| arg_type_error("number->string", "Int", 2))?,
+        None => 10,
+    };
+    let text = match (number, radix) {
+        (_, 10) => interp.format_number(number),
+        (Number::Int(i), 2 | 8 | 16) => {
+            let sign = if i < 0 { "-" } else { "" };
+            let digits = match radix {
+                2 => format!("{:b}", i.unsigned_abs()),
+                8 => format!("{:o}", i.unsigned_abs()),
+                _ => format!("{:x}", i.unsigned_abs()),
+            };
+            format!("{}{}", sign, digits)
+        },
+        _ => return Err(SchemeError::EvalError(format!(
+            "number->string: unsupported radix {} for {}.", radix, number
+        ))),
+    };
+    interp.heap.borrow_mut().alloc_string(text)
+}
+
+// (string->number s) is #f unless all of s, with no surrounding whitespace,
+// reads as a single number: "12abc" and "1.2.3" are not numbers.
+fn primitive_string_to_number(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("string->number", args, 1);
+    let mut text = String::new();
+    interp.to_string(args[0], &mut text).map_err(|_| arg_type_error("string->number", "String", 1))?;
+    if text.is_empty() || text.starts_with(|ch: char| ch.is_ascii_whitespace()) {
+        return Ok(Value::Boolean(false));
+    }
+    let mut parser = Parser::new(text.as_bytes());
+    match parser.read(interp) {
+        Ok(number @ Value::Number(_)) if parser.position() == text.len() => Ok(number),
+        _ => Ok(Value::Boolean(false)),
+    }
+}
+
+fn primitive_valid_symbol_char_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("valid-symbol-char?", args, 1, ch: Char);
+    Ok(Value::Boolean(is_symbol_char(*ch)))
+}
+
+// (identifier? s) is #t when all of s reads as a single symbol.
+fn primitive_identifier_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("identifier?", args, 1);
+    let mut text = String::new();
+    interp.to_string(args[0], &mut text).map_err(|_| arg_type_error("identifier?", "String", 1))?;
+    Ok(Value::Boolean(is_identifier(&text)))
+}
+
+fn string_args(interp: &Interp, name: &str, args: &[Value]) -> Result<(String, String), SchemeError> {
+    check_arity!(name, args, 2);
+    let (mut needle, mut haystack) = (String::new(), String::new());
+    interp.to_string(args[0], &mut needle).map_err(|_| arg_type_error(name, "String", 1))?;
+    interp.to_string(args[1], &mut haystack).map_err(|_| arg_type_error(name, "String", 2))?;
+    Ok((needle, haystack))
+}
+
+fn primitive_string_prefix_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let (prefix, s) = string_args(interp, "string-prefix?", args)?;
+    Ok(Value::Boolean(s.starts_with(&prefix)))
+}
+
+fn primitive_string_suffix_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let (suffix, s) = string_args(interp, "string-suffix?", args)?;
+    Ok(Value::Boolean(s.ends_with(&suffix)))
+}
+
+fn primitive_substring_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let (needle, s) = string_args(interp, "substring?", args)?;
+    Ok(Value::Boolean(s.contains(&needle)))
+}
+
+// Compares each pair of adjacent strings, ci versions fold the case of each
+// character the way char-downcase does before comparing.
+fn compare_strings(
+    interp: &Interp, name: &str, args: &[Value], fold_case: bool, test: fn(Ordering) -> bool
+) -> Result<Value, SchemeError> {
+    check_arity!(name, args, 2..);
+    let mut texts = Vec::with_capacity(args.len());
+    for (i, arg) in args.iter().enumerate() {
+        let mut text = String::new();
+        interp.to_string(*arg, &mut text).map_err(|_| arg_type_error(name, "String", i + 1))?;
+        if fold_case {
+            text = text.chars().map(|ch| ch.to_ascii_lowercase()).collect();
+        }
+        texts.push(text);
+    }
+    Ok(Value::Boolean(texts.windows(2).all(|pair| test(pair[0].cmp(&pair[1])))))
+}
+
+fn primitive_string_eq(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    compare_strings(interp, "string=?", args, false, |order| Ordering::Equal == order)
+}
+
+fn primitive_string_lt(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    compare_strings(interp, "string<?", args, false, |order| Ordering::Less == order)
+}
+
+fn primitive_string_lte(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    compare_strings(interp, "string<=?", args, false, |order| Ordering::Greater != order)
+}
+
+fn primitive_string_gt(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    compare_strings(interp, "string>?", args, false, |order| Ordering::Greater == order)
+}
+
+fn primitive_string_gte(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    compare_strings(interp, "string>=?", args, false, |order| Ordering::Less != order)
+}
+
+fn primitive_string_ci_eq(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    compare_strings(interp, "string-ci=?", args, true, |order| Ordering::Equal == order)
+}
+
+fn primitive_string_ci_lt(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    compare_strings(interp, "string-ci<?", args, true, |order| Ordering::Less == order)
+}
+
+fn primitive_string_ci_lte(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    compare_strings(interp, "string-ci<=?", args, true, |order| Ordering::Greater != order)
+}
+
+fn primitive_string_ci_gt(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    compare_strings(interp, "string-ci>?", args, true, |order| Ordering::Greater == order)
+}
+
+fn primitive_string_ci_gte(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    compare_strings(interp, "string-ci>=?", args, true, |order| Ordering::Less != order)
+}
+
+// The chars of (name s pred) matching pred, which is either a char or a
+// predicate on chars, with their index.
+fn matching_chars(interp: &Interp, name: &str, args: &[Value]) -> Result<Vec<usize>, SchemeError> {
+    check_arity!(name, args, 2);
+    let mut text = String::new();
+    interp.to_string(args[0], &mut text).map_err(|_| arg_type_error(name, "String", 1))?;
+    let pred = match args[1] {
+        Value::Char(_) => args[1],
+        pred => interp.check_procedure(name, pred, 2)?,
+    };
+    // Positions count characters, as read-string does; non-ASCII characters
+    // have no Char value, so they never match.
+    let mut indices = Vec::new();
+    for (i, ch) in text.chars().enumerate() {
+        let Some(ch) = ch.is_ascii().then_some(ch as u8) else {
+            continue;
+        };
+        let matches = match pred {
+            Value::Char(c) => c == ch,
+            _ => pred.apply(interp, &interp.env, vec![Value::Char(ch)])?.is_truthy(),
+        };
+        if matches {
+            indices.push(i);
+        }
+    }
+    Ok(indices)
+}
+
+fn primitive_string_index(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let indices = matching_chars(interp, "string-index", args)?;
+    Ok(indices.first().map_or(Value::Boolean(false), |i| Value::Number(Number::Int(*i as i64))))
+}
+
+fn primitive_string_index_right(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let indices = matching_chars(interp, "string-index-right", args)?;
+    Ok(indices.last().map_or(Value::Boolean(false), |i| Value::Number(Number::Int(*i as i64))))
+}
+
+fn primitive_string_count(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let indices = matching_chars(interp, "string-count", args)?;
+    Ok(Value::Number(Number::Int(indices.len() as i64)))
+}
+
+fn format_radix(n: i64, radix: u32) -> String {
+    let digits = match radix {
+        2 => format!("{:b}", n.unsigned_abs()),
+        8 => format!("{:o}", n.unsigned_abs()),
+        _ => format!("{:x}", n.unsigned_abs()),
+    };
+    if n < 0 { format!("-{}", digits) } else { digits }
+}
+
+fn format_directive(interp: &Interp, directive: char, params: &[Option<usize>], arg: Value) 
+    -> Result<String, SchemeError> 
+{
+    if let Some(param) = params.iter().flatten().find(|param| **param > MAX_FORMAT_PARAM) {
+        return Err(SchemeError::EvalError(format!(
+            "format: parameter {} of ~{} is over the limit of {}.", param, directive, MAX_FORMAT_PARAM
+        )));
+    }
+    let digits = params.get(1).copied().flatten();
+    let text = match directive {
+        'a' => interp.display(arg),
+        's' => interp.write(arg),
+        'f' | 'e' => {
+            let n = match interp.is_number(arg) {
+                Some(Number::Int(i)) => i as f64,
+                Some(Number::Float(f)) => f,
+                None => return Err(SchemeError::TypeError(format!(
+                    "format: ~{} expects a Number, but got a {}.", directive, arg.type_name()
+                ))),
+            };
+            match (directive, digits) {
+                ('f', Some(d)) => format!("{:.*}", d, n),
+                ('f', None) => Number::Float(n).to_string(),
+                (_, Some(d)) => format!("{:.*e}", d, n),
+                (_, None) => format!("{:e}", n),
+            }
+        },
+        'b' | 'o' | 'x' => {
+            let radix = match directive { 'b' => 2, 'o' => 8, _ => 16 };
+            format_radix(interp.as_integer(arg)?, radix)
+        },
+        _ => return Err(SchemeError::EvalError(format!(
+            "format: unknown directive ~{}.", directive
+        ))),
+    };
+    // The first parameter is a minimum width, padded on the left.
+    match params.first().copied().flatten() {
+        Some(width) => Ok(format!("{:>width$}", text, width = width)),
+        None => Ok(text),
+    }
+}
+
+// (format fmt arg ...) supports ~a ~s ~% ~~ plus the numeric directives
+// ~f ~e ~b ~o ~x, which take optional width and digits params as in ~8,2f.
+fn primitive_format(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("format", args, 1..);
+    let (fmt, mut rest) = (&args[0], &args[1..]);
+    let mut fmt_str = String::new();
+    interp.to_string(*fmt, &mut fmt_str)?;
+    let mut out = String::new();
+    let mut chars = fmt_str.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '~' {
+            out.push(ch);
+            continue;
+        }
+        let mut params = vec![None];
+        while let Some(&next) = chars.peek() {
+            if let Some(digit) = next.to_digit(10) {
+                let param = params.last_mut().unwrap();
+                *param = Some(param.unwrap_or(0usize).saturating_mul(10).saturating_add(digit as usize));
+            } else if next == ',' {
+                params.push(None);
+            } else {
+                break;
+            }
+            chars.next();
+        }
+        match chars.next().map(|c| c.to_ascii_lowercase()) {
+            Some('%') => out.push('\n'),
+            Some('~') => out.push('~'),
+            Some(directive) => {
+                let Some((arg, tail)) = rest.split_first() else {
+                    return Err(SchemeError::ArgCountError(format!(
+                        "format: missing argument for ~{}.", directive
+                    )));
+                };
+                rest = tail;
+                out.push_str(&format_directive(interp, directive, &params, *arg)?);
+            },
+            None => return Err(SchemeError::EvalError(
+                "format: incomplete directive at end of string.".to_string()
+            )),
+        }
+    }
+    interp.heap.borrow_mut().alloc_string(out)
 }
 
 fn primitive_list(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
     if args.is_empty() {
         Ok(Value::Nil)
     } else {
-        Ok(interp.heap.borrow_mut().alloc_list(args))
+        interp.heap.borrow_mut().alloc_list(args)
     }
 }
 
@@ -435,10 +2279,10 @@ fn primitive_append(interp: &Interp, args: &[Value])-> Result<Value, SchemeError
             while let Ok((car, cdr)) = interp.to_pair(p) {
                 let mut heap = interp.heap.borrow_mut();
                 if matches!(retval, Value::Nil) {
-                    retval = heap.alloc_pair(car, Value::Nil);
+                    retval = heap.alloc_pair(car, Value::Nil)?;
                     prev_cdr = retval;
                 } else {
-                    let next = heap.alloc_pair(car, Value::Nil);
+                    let next = heap.alloc_pair(car, Value::Nil)?;
                     heap.setcdr(interp.to_object(prev_cdr)?, next)?;
                     prev_cdr = next;
                 }
@@ -455,148 +2299,1335 @@ fn primitive_append(interp: &Interp, args: &[Value])-> Result<Value, SchemeError
 }
 
 fn primitive_length(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    check_arity!(args, 1);
-    let mut length = 0;
-    if ! matches!(args[0], Value::Nil) {
-        let (_, mut cdr) = interp.to_pair(args[0])?;
-        loop {
-            length += 1;
-            if matches!(cdr, Value::Nil) { break; }
-            (_, cdr) = interp.to_pair(cdr)?;
-        }
-    }
+    check_arity!("length", args, 1);
+    let length = interp.fold_list_strict(args[0], 0, |length, _| Ok(length + 1))?;
     Ok(Value::Number(Number::Int(length)))
 }
 
 fn primitive_list_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    check_arity!(args, 1);
-    Ok(Value::Boolean(interp.is_nil(args[0]) || interp.is_list(args[0])))
+    check_arity!("list?", args, 1);
+    Ok(Value::Boolean(interp.is_list(args[0])))
+}
+
+fn primitive_length_plus(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("length+", args, 1);
+    match interp.proper_length(args[0]) {
+        Some(length) => Ok(Value::Number(Number::Int(length as i64))),
+        None => Ok(Value::Boolean(false)),
+    }
 }
 
 fn primitive_null_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    check_arity!(args, 1);
+    check_arity!("null?", args, 1);
     Ok(Value::Boolean(interp.is_null(args[0])))
 }
 
 fn primitive_list_cons(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    check_arity!(args, 2);
+    check_arity!("cons", args, 2);
     let mut heap = interp.heap.borrow_mut();
-    Ok(heap.alloc_pair(args[0], args[1]))
+    heap.alloc_pair(args[0], args[1])
 }
 
 fn primitive_list_car(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    check_arity!(args, 1);
-    let (car, _) = interp.to_pair(args[0])?;
+    check_arity!("car", args, 1);
+    let (car, _) = interp.to_pair(args[0]).map_err(|_| arg_type_error("car", "Pair", 1))?;
     Ok(car)
 }
 
 fn primitive_list_cdr(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    check_arity!(args, 1);
-    let (_, cdr) = interp.to_pair(args[0])?;
+    check_arity!("cdr", args, 1);
+    let (_, cdr) = interp.to_pair(args[0]).map_err(|_| arg_type_error("cdr", "Pair", 1))?;
     Ok(cdr)
 }
 
-fn primitive_char_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    check_arity!(args, 1);
-    Ok(Value::Boolean(interp.is_char(args[0]).is_some()))
+fn primitive_set_car(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("set-car!", args, 2);
+    let type_error = || arg_type_error("set-car!", "Pair", 1);
+    let id = interp.to_object(args[0]).map_err(|_| type_error())?;
+    interp.heap.borrow_mut().setcar(id, args[1]).map_err(|_| type_error())?;
+    Ok(Value::Unspecified)
 }
 
-fn primitive_char_alphabetic_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 1, ch: Char);
-    Ok(Value::Boolean((*ch as char).is_alphabetic()))
+fn primitive_set_cdr(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("set-cdr!", args, 2);
+    let type_error = || arg_type_error("set-cdr!", "Pair", 1);
+    let id = interp.to_object(args[0]).map_err(|_| type_error())?;
+    interp.heap.borrow_mut().setcdr(id, args[1]).map_err(|_| type_error())?;
+    Ok(Value::Unspecified)
 }
 
-fn primitive_char_numeric_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 1, ch: Char);
-    Ok(Value::Boolean((*ch as char).is_digit(10)))
+fn list_args(interp: &Interp, lists: &[Value]) -> Result<Vec<Vec<Value>>, SchemeError> {
+    lists.iter()
+        .map(|list| interp.list_to_vec(*list))
+        .collect()
 }
 
-fn primitive_char_whitespace_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 1, ch: Char);
-    Ok(Value::Boolean(*ch == 9 || *ch == 10 || *ch == 32))
+// Converts the list passed as argument position (1-based) of name, reporting
+// that position when it isn't a list, or a dotted one.
+fn list_arg(
+    interp: &Interp, name: &str, list: Value, position: usize
+) -> Result<Vec<Value>, SchemeError> {
+    if interp.is_pair(list).is_none() && !interp.is_nil(list) {
+        return Err(arg_type_error(name, "List", position));
+    }
+    interp.list_to_vec(list).map_err(|_| arg_type_error(name, "Proper List", position))
 }
 
-fn primitive_char_upper_case_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 1, ch: Char);
-    Ok(Value::Boolean((*ch as char).is_uppercase()))
+// Checks that the procedure of map, for-each and their vector versions accepts
+// one arg per sequence.
+fn check_map_procedure(interp: &Interp, name: &str, args: &[Value]) -> Result<(), SchemeError> {
+    check_arity!(name, args, 2..);
+    interp.check_procedure(name, args[0], 1)?;
+    // Each call gets one arg per list, which the procedure must accept.
+    let given = args.len() - 1;
+    if let Some((min, max)) = interp.closure_arity(args[0])
+        && (given < min || max.is_some_and(|max| given > max)) {
+        let expected = match max {
+            Some(_) => min.to_string(),
+            None => format!("at least {}", min),
+        };
+        return Err(arg_count_error(&format!("{}: procedure", name), &expected, given));
+    }
+    Ok(())
 }
 
-fn primitive_char_lower_case_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 1, ch: Char);
-    Ok(Value::Boolean((*ch as char).is_lowercase()))
+fn map_args(interp: &Interp, name: &str, args: &[Value]) -> Result<Vec<Vec<Value>>, SchemeError> {
+    check_map_procedure(interp, name, args)?;
+    let lists = args[1..].iter().enumerate()
+        .map(|(i, list)| list_arg(interp, name, *list, i + 2))
+        .collect::<Result<Vec<_>, SchemeError>>()?;
+    let count = lists.iter().map(|list| list.len()).min().unwrap_or(0);
+    Ok((0..count)
+        .map(|i| lists.iter().map(|list| list[i]).collect())
+        .collect())
 }
 
-fn primitive_char_to_integer(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 1, ch: Char);
-    Ok(Value::Number(Number::Int(*ch as i64)))
+fn primitive_map(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let results = map_args(interp, "map", args)?
+        .into_iter()
+        .map(|call_args| args[0].apply(interp, &interp.env, call_args))
+        .collect::<Result<Vec<Value>, SchemeError>>()?;
+    interp.heap.borrow_mut().alloc_list(&results)
 }
 
-fn primitive_integer_to_char(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    check_arity!(args, 1);
-    let byte = interp.as_integer(args[0])?;
-    Ok(Value::Char(byte as u8))
+fn primitive_for_each(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    for call_args in map_args(interp, "for-each", args)? {
+        args[0].apply(interp, &interp.env, call_args)?;
+    }
+    Ok(Value::Unspecified)
 }
 
-fn primitive_char_upcase(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 1, ch: Char);
-    Ok(Value::Char((*ch as char).to_ascii_uppercase() as u8))
+// (memoize proc) calls proc once per distinct list of args, in the equal?
+// sense, and returns the cached result for the args it has seen.
+fn primitive_memoize(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("memoize", args, 1);
+    let proc = interp.check_procedure("memoize", args[0], 1)?;
+    interp.heap.borrow_mut().alloc_memoized(proc)
 }
 
-fn primitive_char_downcase(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 1, ch: Char);
-    Ok(Value::Char((*ch as char).to_ascii_lowercase() as u8))
+// (curry proc arg ...) returns a procedure calling proc with the given args
+// followed by its own.
+fn primitive_curry(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("curry", args, 1..);
+    let proc = interp.check_procedure("curry", args[0], 1)?;
+    interp.heap.borrow_mut().alloc_curried(proc, args[1..].to_vec())
 }
 
-fn primitive_char_eq(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 2, ch1: Char, ch2: Char);
-    Ok(Value::Boolean(ch1 == ch2))
+// (make-object methods) takes an alist of method names to procedures, and
+// returns an object which, called as (obj 'name arg ...), applies the method.
+fn primitive_make_object(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("make-object", args, 1);
+    for entry in interp.list_to_vec(args[0])? {
+        let (name, method) = interp.to_pair(entry).map_err(|_| SchemeError::TypeError(format!(
+            "make-object: expected a method entry, but got {}.", interp.write(entry)
+        )))?;
+        interp.to_symbol(name).map_err(|_| SchemeError::TypeError(format!(
+            "make-object: method name {} is not a symbol.", interp.write(name)
+        )))?;
+        interp.check_procedure("make-object", method, 1)?;
+    }
+    interp.heap.borrow_mut().alloc_curried(interp.object_dispatcher, vec![args[0]])
 }
 
-fn primitive_char_lt(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 2, ch1: Char, ch2: Char);
-    Ok(Value::Boolean(ch1 < ch2))
+fn dispatch_method(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("object", args, 2..);
+    let (methods, name) = (args[0], args[1]);
+    for entry in interp.list_to_vec(methods)? {
+        let (key, method) = interp.to_pair(entry)?;
+        if key == name {
+            return method.apply(interp, &interp.env, args[2..].to_vec());
+        }
+    }
+    Err(SchemeError::EvalError(format!("object: no method named {}.", interp.write(name))))
 }
 
-fn primitive_char_lte(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 2, ch1: Char, ch2: Char);
-    Ok(Value::Boolean(ch1 <= ch2))
+// (send obj 'name arg ...) is the same as (obj 'name arg ...).
+fn primitive_send(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("send", args, 2..);
+    let object = interp.check_procedure("send", args[0], 1)?;
+    object.apply(interp, &interp.env, args[1..].to_vec())
 }
 
-fn primitive_char_gt(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 2, ch1: Char, ch2: Char);
-    Ok(Value::Boolean(ch1 > ch2))
+fn primitive_eq_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("eq?", args, 2);
+    Ok(Value::Boolean(interp.is_eq(args[0], args[1])))
 }
 
-fn primitive_char_gte(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 2, ch1: Char, ch2: Char);
-    Ok(Value::Boolean(ch1 >= ch2))
+fn primitive_eqv_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("eqv?", args, 2);
+    Ok(Value::Boolean(interp.is_eqv(args[0], args[1])))
 }
 
-fn primitive_char_ci_eq(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 2, ch1: Char, ch2: Char);
-    Ok(Value::Boolean(ch1.to_ascii_lowercase() == ch2.to_ascii_lowercase()))
+fn primitive_equal_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("equal?", args, 2);
+    Ok(Value::Boolean(interp.is_equal(args[0], args[1])))
 }
 
-fn primitive_char_ci_lt(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 2, ch1: Char, ch2: Char);
-    Ok(Value::Boolean(ch1.to_ascii_lowercase() < ch2.to_ascii_lowercase()))
+fn alist_entries(interp: &Interp, alist: Value) -> Result<Vec<(Value, Value)>, SchemeError> {
+    interp.fold_list_strict(alist, Vec::new(), |mut acc, entry| {
+        acc.push(interp.to_pair(entry)?);
+        Ok(acc)
+    })
 }
 
-fn primitive_char_ci_lte(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 2, ch1: Char, ch2: Char);
-    Ok(Value::Boolean(ch1.to_ascii_lowercase() <= ch2.to_ascii_lowercase()))
+// Merges two association lists, keeping the order of al1 followed by the
+// keys only found in al2. Keys found in both are combined with resolve.
+fn primitive_alist_merge(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("alist-merge", args, 3);
+    let resolve = interp.check_procedure("alist-merge", args[2], 3)?;
+    let left = alist_entries(interp, args[0])?;
+    let mut right = alist_entries(interp, args[1])?;
+    let mut merged = Vec::new();
+    for (key, value) in left {
+        let value = match right.iter().position(|(k, _)| interp.is_equal(*k, key)) {
+            Some(index) => {
+                let (_, other) = right.remove(index);
+                resolve.apply(interp, &interp.env, vec![key, value, other])?
+            },
+            None => value,
+        };
+        merged.push((key, value));
+    }
+    merged.extend(right);
+    let mut heap = interp.heap.borrow_mut();
+    let entries = merged.into_iter()
+        .map(|(key, value)| heap.alloc_pair(key, value))
+        .collect::<Result<Vec<Value>, SchemeError>>()?;
+    heap.alloc_list(&entries)
+}
+
+fn alloc_alist(interp: &Interp, entries: &[(Value, Value)]) -> Result<Value, SchemeError> {
+    let mut heap = interp.heap.borrow_mut();
+    let pairs = entries.iter()
+        .map(|(key, value)| heap.alloc_pair(*key, *value))
+        .collect::<Result<Vec<Value>, SchemeError>>()?;
+    heap.alloc_list(&pairs)
+}
+
+// (alist-update key value alist) replaces the first binding of key, compared
+// with equal?, or adds the binding in front when there is none.
+fn primitive_alist_update(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("alist-update", args, 3);
+    let mut entries = alist_entries(interp, args[2])?;
+    match entries.iter_mut().find(|(key, _)| interp.is_equal(*key, args[0])) {
+        Some(entry) => entry.1 = args[1],
+        None => entries.insert(0, (args[0], args[1])),
+    }
+    alloc_alist(interp, &entries)
+}
+
+// (del-assq key alist) drops every binding of key, compared with eq?.
+fn primitive_del_assq(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("del-assq", args, 2);
+    let mut entries = alist_entries(interp, args[1])?;
+    entries.retain(|(key, _)| *key != args[0]);
+    alloc_alist(interp, &entries)
+}
+
+// The first pair of list whose car is the same as item, or #f.
+fn member_by(interp: &Interp, name: &str, args: &[Value], same: impl Fn(Value, Value) -> bool) -> Result<Value, SchemeError> {
+    check_arity!(name, args, 2);
+    let mut p = args[1];
+    while let Some((car, cdr)) = interp.is_pair(p) {
+        if same(args[0], car) {
+            return Ok(p);
+        }
+        p = cdr;
+    }
+    Ok(Value::Boolean(false))
+}
+
+fn primitive_memq(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    member_by(interp, "memq", args, |a, b| interp.is_eq(a, b))
+}
+
+fn primitive_memv(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    member_by(interp, "memv", args, |a, b| interp.is_eqv(a, b))
+}
+
+fn primitive_member(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    member_by(interp, "member", args, |a, b| interp.is_equal(a, b))
+}
+
+// The first entry of alist whose key is the same as key, or #f.
+fn assoc_by(interp: &Interp, name: &str, args: &[Value], same: impl Fn(Value, Value) -> bool) -> Result<Value, SchemeError> {
+    check_arity!(name, args, 2);
+    let mut p = args[1];
+    while let Some((entry, cdr)) = interp.is_pair(p) {
+        let (key, _) = interp.is_pair(entry).ok_or_else(|| SchemeError::TypeError(format!(
+            "{}: expected an alist entry, but got {}.", name, interp.write(entry)
+        )))?;
+        if same(args[0], key) {
+            return Ok(entry);
+        }
+        p = cdr;
+    }
+    Ok(Value::Boolean(false))
+}
+
+fn primitive_assq(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    assoc_by(interp, "assq", args, |a, b| interp.is_eq(a, b))
+}
+
+fn primitive_assv(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    assoc_by(interp, "assv", args, |a, b| interp.is_eqv(a, b))
+}
+
+fn primitive_assoc(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    assoc_by(interp, "assoc", args, |a, b| interp.is_equal(a, b))
+}
+
+fn primitive_filter(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("filter", args, 2);
+    let pred = interp.check_procedure("filter", args[0], 1)?;
+    let mut items = Vec::new();
+    for item in list_arg(interp, "filter", args[1], 2)? {
+        if pred.apply(interp, &interp.env, vec![item])?.is_truthy() {
+            items.push(item);
+        }
+    }
+    interp.heap.borrow_mut().alloc_list(&items)
+}
+
+// Returns the first pair of the list whose car satisfies pred, or #f.
+fn find_tail(interp: &Interp, name: &str, args: &[Value]) -> Result<Option<Value>, SchemeError> {
+    check_arity!(name, args, 2);
+    let pred = interp.check_procedure(name, args[0], 1)?;
+    let mut p = args[1];
+    while let Some((car, cdr)) = interp.is_pair(p) {
+        if pred.apply(interp, &interp.env, vec![car])?.is_truthy() {
+            return Ok(Some(p));
+        }
+        p = cdr;
+    }
+    if !interp.is_nil(p) {
+        return Err(arg_type_error(name, "List", 2));
+    }
+    Ok(None)
+}
+
+fn primitive_find(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    match find_tail(interp, "find", args)? {
+        Some(tail) => Ok(interp.to_pair(tail)?.0),
+        None => Ok(Value::Boolean(false)),
+    }
+}
+
+fn primitive_find_tail(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    Ok(find_tail(interp, "find-tail", args)?.unwrap_or(Value::Boolean(false)))
+}
+
+// (list-index pred list ...) applies pred across the lists in parallel,
+// stopping at the shortest one.
+fn primitive_list_index(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    for (index, call_args) in map_args(interp, "list-index", args)?.into_iter().enumerate() {
+        if args[0].apply(interp, &interp.env, call_args)?.is_truthy() {
+            return Ok(Value::Number(Number::Int(index as i64)));
+        }
+    }
+    Ok(Value::Boolean(false))
+}
+
+// (delete-duplicates list [same?]) keeps the first occurrence of each element,
+// comparing with equal? by default. Keys compared with equal? don't all hash,
+// so both cases are quadratic.
+fn primitive_delete_duplicates(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("delete-duplicates", args, 1..=2);
+    let same = match args.get(1) {
+        Some(same) => Some(interp.check_procedure("delete-duplicates", *same, 2)?),
+        None => None,
+    };
+    let mut kept: Vec<Value> = Vec::new();
+    for item in interp.list_to_vec(args[0])? {
+        let mut duplicate = false;
+        for other in kept.iter() {
+            duplicate = match same {
+                Some(same) => same.apply(interp, &interp.env, vec![*other, item])?.is_truthy(),
+                None => interp.is_equal(*other, item),
+            };
+            if duplicate {
+                break;
+            }
+        }
+        if !duplicate {
+            kept.push(item);
+        }
+    }
+    interp.heap.borrow_mut().alloc_list(&kept)
+}
+
+// Turns the lists into a list of the tuples found at each position, up to
+// the shortest list.
+fn transpose(interp: &Interp, lists: &[Value]) -> Result<Value, SchemeError> {
+    let lists = list_args(interp, lists)?;
+    let count = lists.iter().map(|list| list.len()).min().unwrap_or(0);
+    let mut heap = interp.heap.borrow_mut();
+    let tuples = (0..count)
+        .map(|i| heap.alloc_list(&lists.iter().map(|list| list[i]).collect::<Vec<_>>()))
+        .collect::<Result<Vec<_>, SchemeError>>()?;
+    heap.alloc_list(&tuples)
+}
+
+// (zip '(1 2) '(a b)) => ((1 a) (2 b))
+fn primitive_zip(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("zip", args, 1..);
+    transpose(interp, args)
+}
+
+// (unzip '((1 a) (2 b))) => ((1 2) (a b)), the inverse of zip.
+fn primitive_unzip(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("unzip", args, 1);
+    transpose(interp, &interp.list_to_vec(args[0])?)
+}
+
+// (unzip1 '((1 a) (2 b))) => (1 2)
+fn primitive_unzip1(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("unzip1", args, 1);
+    let firsts = interp.list_to_vec(args[0])?.into_iter()
+        .map(|tuple| interp.to_pair(tuple).map(|(car, _)| car))
+        .collect::<Result<Vec<_>, SchemeError>>()?;
+    interp.heap.borrow_mut().alloc_list(&firsts)
+}
+
+fn primitive_fold(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("fold", args, 3);
+    let kons = interp.check_procedure("fold", args[0], 1)?;
+    list_arg(interp, "fold", args[2], 3)?.into_iter().try_fold(args[1], |acc, item| {
+        kons.apply(interp, &interp.env, vec![item, acc])
+    })
+}
+
+// (sort seq less?) returns a new sorted list or vector, the sort is stable.
+fn primitive_sort(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("sort", args, 2);
+    let less = interp.check_procedure("sort", args[1], 2)?;
+    let vector = interp.is_vector(args[0]).map(|items| items.to_vec());
+    let is_vector = vector.is_some();
+    let mut items = match vector {
+        Some(items) => items,
+        None => interp.list_to_vec(args[0])?,
+    };
+    let mut error = None;
+    let mut is_less = |a: Value, b: Value| -> bool {
+        if error.is_some() {
+            return false;
+        }
+        match less.apply(interp, &interp.env, vec![a, b]) {
+            Ok(result) => result.is_truthy(),
+            Err(e) => {
+                error = Some(e);
+                false
+            }
+        }
+    };
+    items.sort_by(|a, b| {
+        if is_less(*a, *b) {
+            Ordering::Less
+        } else if is_less(*b, *a) {
+            Ordering::Greater
+        } else {
+            Ordering::Equal
+        }
+    });
+    if let Some(e) = error {
+        return Err(e);
+    }
+    let mut heap = interp.heap.borrow_mut();
+    if is_vector { heap.alloc_vector(items) } else { heap.alloc_list(&items) }
+}
+
+// Returns the element of the list with the greatest key, or with the least
+// when reversed, keeping the first one on ties.
+fn extreme_by(interp: &Interp, name: &str, args: &[Value], reversed: bool) -> Result<Value, SchemeError> {
+    check_arity!(name, args, 2);
+    let key_fn = interp.check_procedure(name, args[0], 1)?;
+    let best = interp.fold_list_strict(args[1], None, |best: Option<(Value, Number)>, item| {
+        let key = match key_fn.apply(interp, &interp.env, vec![item])? {
+            Value::Number(key) => key,
+            key => return Err(SchemeError::TypeError(format!(
+                "{}: key {} is not a number.", name, interp.write(key)
+            ))),
+        };
+        Ok(match best {
+            Some((_, best_key)) if (reversed && key < best_key) || (!reversed && key > best_key) => Some((item, key)),
+            Some(best) => Some(best),
+            None => Some((item, key)),
+        })
+    })?;
+    best.map(|(item, _)| item).ok_or_else(|| SchemeError::EvalError(format!(
+        "{}: empty list.", name
+    )))
+}
+
+// (max-by key-fn list) returns the element, not the key.
+fn primitive_max_by(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extreme_by(interp, "max-by", args, false)
+}
+
+fn primitive_min_by(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extreme_by(interp, "min-by", args, true)
+}
+
+fn primitive_procedure_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("procedure?", args, 1);
+    Ok(Value::Boolean(interp.is_procedure(args[0])))
+}
+
+fn primitive_apply(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("apply", args, 1..);
+    let (func, rest) = (&args[0], &args[1..]);
+    interp.check_procedure("apply", *func, 1)?;
+    let mut call_args = Vec::new();
+    if let Some((last, spread)) = rest.split_last() {
+        call_args.extend_from_slice(spread);
+        call_args.extend(list_arg(interp, "apply", *last, args.len())?);
+    }
+    func.apply(interp, &interp.env, call_args)
+}
+
+fn primitive_call_cc(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("call/cc", args, 1);
+    interp.call_cc(interp.check_procedure("call/cc", args[0], 1)?)
+}
+
+fn primitive_dynamic_wind(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("dynamic-wind", args, 3);
+    let before = interp.check_procedure("dynamic-wind", args[0], 1)?;
+    let thunk = interp.check_procedure("dynamic-wind", args[1], 2)?;
+    let after = interp.check_procedure("dynamic-wind", args[2], 3)?;
+    interp.dynamic_wind(before, thunk, after)
+}
+
+fn primitive_values(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    match args {
+        [value] => Ok(*value),
+        _ => interp.heap.borrow_mut().alloc_values(args),
+    }
+}
+
+fn primitive_call_with_values(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("call-with-values", args, 2);
+    let producer = interp.check_procedure("call-with-values", args[0], 1)?;
+    let consumer = interp.check_procedure("call-with-values", args[1], 2)?;
+    let result = producer.apply(interp, &interp.env, Vec::new())?;
+    let values = interp.is_values(result).unwrap_or_else(|| vec![result]);
+    consumer.apply(interp, &interp.env, values)
+}
+
+fn primitive_vector(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    interp.heap.borrow_mut().alloc_vector(args.to_vec())
+}
+
+fn primitive_vector_length(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("vector-length", args, 1);
+    let items = interp.to_vector(args[0]).map_err(|_| arg_type_error("vector-length", "Vector", 1))?;
+    Ok(Value::Number(Number::Int(items.len() as i64)))
+}
+
+fn primitive_vector_ref(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("vector-ref", args, 2);
+    let items = interp.to_vector(args[0]).map_err(|_| arg_type_error("vector-ref", "Vector", 1))?;
+    let index = interp.as_integer(args[1]).map_err(|_| arg_type_error("vector-ref", "Int", 2))?;
+    usize::try_from(index).ok()
+        .and_then(|i| items.get(i).copied())
+        .ok_or_else(|| SchemeError::EvalError(format!(
+            "vector-ref: index {} out of range for length {}.", index, items.len()
+        )))
+}
+
+fn primitive_vector_to_list(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("vector->list", args, 1);
+    let items = interp.to_vector(args[0]).map_err(|_| arg_type_error("vector->list", "Vector", 1))?.to_vec();
+    interp.heap.borrow_mut().alloc_list(&items)
+}
+
+// Like map_args but for vectors, which must all have the same length unless
+// the interpreter is set to stop at the shortest one.
+fn vector_map_args(interp: &Interp, name: &str, args: &[Value]) -> Result<Vec<Vec<Value>>, SchemeError> {
+    check_map_procedure(interp, name, args)?;
+    let vectors = args[1..].iter().enumerate()
+        .map(|(i, vector)| interp.to_vector(*vector).map_err(|_| arg_type_error(name, "Vector", i + 2)))
+        .collect::<Result<Vec<_>, SchemeError>>()?;
+    if !interp.vector_map_shortest.get()
+        && let Some((i, vector)) = vectors.iter().enumerate().find(|(_, v)| v.len() != vectors[0].len()) {
+        return Err(SchemeError::EvalError(format!(
+            "{}: vector argument {} has length {}, but argument 2 has length {}.",
+            name, i + 2, vector.len(), vectors[0].len()
+        )));
+    }
+    let count = vectors.iter().map(|vector| vector.len()).min().unwrap_or(0);
+    Ok((0..count)
+        .map(|i| vectors.iter().map(|vector| vector[i]).collect())
+        .collect())
+}
+
+fn primitive_vector_map(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let results = vector_map_args(interp, "vector-map", args)?
+        .into_iter()
+        .map(|call_args| args[0].apply(interp, &interp.env, call_args))
+        .collect::<Result<Vec<Value>, SchemeError>>()?;
+    interp.heap.borrow_mut().alloc_vector(results)
+}
+
+fn primitive_vector_for_each(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    for call_args in vector_map_args(interp, "vector-for-each", args)? {
+        args[0].apply(interp, &interp.env, call_args)?;
+    }
+    Ok(Value::Unspecified)
+}
+
+fn primitive_list_to_vector(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("list->vector", args, 1);
+    let items = interp.list_to_vec(args[0])?;
+    interp.heap.borrow_mut().alloc_vector(items)
+}
+
+// Resolves a Python-style index, negative values counting from the end.
+// Out of range indices are clamped to the sequence bounds rather than errors.
+fn slice_index(index: i64, len: usize) -> usize {
+    let len = len as i64;
+    let index = if index < 0 { len + index } else { index };
+    index.clamp(0, len) as usize
+}
+
+// (slice seq start [end]) copies a list or a vector range into a new
+// sequence of the same kind.
+fn primitive_slice(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("slice", args, 2..=3);
+    let vector = interp.is_vector(args[0]).map(|items| items.to_vec());
+    let is_vector = vector.is_some();
+    let items = match vector {
+        Some(items) => items,
+        None => interp.list_to_vec(args[0])?,
+    };
+    let start = interp.as_integer(args[1]).map_err(|_| arg_type_error("slice", "Int", 2))?;
+    let start = slice_index(start, items.len());
+    let end = match args.get(2) {
+        Some(end) => {
+            let end = interp.as_integer(*end).map_err(|_| arg_type_error("slice", "Int", 3))?;
+            slice_index(end, items.len())
+        },
+        None => items.len(),
+    };
+    let slice = if start < end { &items[start..end] } else { &[] };
+    let mut heap = interp.heap.borrow_mut();
+    if is_vector { heap.alloc_vector(slice.to_vec()) } else { heap.alloc_list(slice) }
+}
+
+fn byte(name: &str, value: Value, position: usize) -> Result<u8, SchemeError> {
+    match value {
+        Value::Number(Number::Int(i)) if (0..=255).contains(&i) => Ok(i as u8),
+        _ => Err(arg_type_error(name, "Byte", position)),
+    }
+}
+
+// Borrows the heap, as is_vector does.
+fn bytevector<'a>(interp: &'a Interp, name: &str, value: Value) -> Result<Ref<'a, [u8]>, SchemeError> {
+    let id = interp.to_object(value).map_err(|_| arg_type_error(name, "Bytevector", 1))?;
+    Ref::filter_map(interp.heap.borrow(), |heap| match heap.get(id) {
+        HeapObject::Bytevector(bytes) => Some(bytes.as_slice()),
+        _ => None,
+    }).map_err(|_| arg_type_error(name, "Bytevector", 1))
+}
+
+fn primitive_bytevector_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("bytevector?", args, 1);
+    Ok(Value::Boolean(bytevector(interp, "bytevector?", args[0]).is_ok()))
+}
+
+// (make-bytevector k [byte]) fills with zeros by default.
+fn primitive_make_bytevector(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("make-bytevector", args, 1..=2);
+    let length = interp.as_integer(args[0]).map_err(|_| arg_type_error("make-bytevector", "Int", 1))?;
+    let length = usize::try_from(length).map_err(|_| {
+        SchemeError::EvalError("make-bytevector: negative length.".to_string())
+    })?;
+    let fill = match args.get(1) {
+        Some(fill) => byte("make-bytevector", *fill, 2)?,
+        None => 0,
+    };
+    let bytes = filled("make-bytevector", length, fill)?;
+    interp.heap.borrow_mut().alloc_bytevector(bytes)
+}
+
+fn primitive_bytevector_length(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("bytevector-length", args, 1);
+    let bytes = bytevector(interp, "bytevector-length", args[0])?;
+    Ok(Value::Number(Number::Int(bytes.len() as i64)))
+}
+
+fn primitive_bytevector_u8_ref(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("bytevector-u8-ref", args, 2);
+    let bytes = bytevector(interp, "bytevector-u8-ref", args[0])?;
+    let index = checked_index("bytevector-u8-ref", bytes.len(), args[1])?;
+    Ok(Value::Number(Number::Int(bytes[index] as i64)))
+}
+
+fn primitive_bytevector_u8_set(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("bytevector-u8-set!", args, 3);
+    let id = interp.to_object(args[0]).map_err(|_| arg_type_error("bytevector-u8-set!", "Bytevector", 1))?;
+    let value = byte("bytevector-u8-set!", args[2], 3)?;
+    let mut heap = interp.heap.borrow_mut();
+    let bytes = heap.bytevector_mut(id)?;
+    let index = checked_index("bytevector-u8-set!", bytes.len(), args[1])?;
+    bytes[index] = value;
+    Ok(Value::Unspecified)
+}
+
+fn primitive_bytevector_to_list(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("bytevector->list", args, 1);
+    let items = bytevector(interp, "bytevector->list", args[0])?.iter()
+        .map(|byte| Value::Number(Number::Int(*byte as i64)))
+        .collect::<Vec<Value>>();
+    interp.heap.borrow_mut().alloc_list(&items)
+}
+
+fn primitive_list_to_bytevector(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("list->bytevector", args, 1);
+    let bytes = interp.list_to_vec(args[0])?.into_iter()
+        .map(|item| byte("list->bytevector", item, 1))
+        .collect::<Result<Vec<u8>, SchemeError>>()?;
+    interp.heap.borrow_mut().alloc_bytevector(bytes)
+}
+
+fn primitive_make_hash_table(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("make-hash-table", args, 0);
+    interp.heap.borrow_mut().alloc_hash_table(false)
+}
+
+// Entries of a weak table whose key is otherwise unreachable go away with
+// the key on the next collection.
+fn primitive_make_weak_hash_table(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("make-weak-hash-table", args, 0);
+    interp.heap.borrow_mut().alloc_hash_table(true)
+}
+
+fn primitive_put(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("put!", args, 3);
+    let symbol = interp.to_symbol(args[0]).map_err(|_| arg_type_error("put!", "Symbol", 1))?;
+    let (key, value) = (args[1], args[2]);
+    let mut plists = interp.plists.borrow_mut();
+    let plist = plists.entry(symbol).or_default();
+    match plist.iter_mut().find(|(other, _)| interp.is_eq(*other, key)) {
+        Some(entry) => entry.1 = value,
+        None => plist.push((key, value)),
+    }
+    Ok(Value::Unspecified)
+}
+
+// (get symbol key) is #f for properties never put.
+fn primitive_get(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("get", args, 2);
+    let symbol = interp.to_symbol(args[0]).map_err(|_| arg_type_error("get", "Symbol", 1))?;
+    let value = interp.plists.borrow().get(&symbol)
+        .and_then(|plist| plist.iter().find(|(key, _)| interp.is_eq(*key, args[1])))
+        .map(|(_, value)| *value);
+    Ok(value.unwrap_or(Value::Boolean(false)))
+}
+
+// (help 'name) is #f for primitives defined without documentation.
+fn primitive_help(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("help", args, 1);
+    let symbol = interp.to_symbol(args[0]).map_err(|_| arg_type_error("help", "Symbol", 1))?;
+    match interp.docs.borrow().get(&symbol) {
+        Some(doc) => interp.heap.borrow_mut().alloc_string(doc.as_str()),
+        None => Ok(Value::Boolean(false)),
+    }
+}
+
+fn primitive_apropos(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("apropos", args, 1);
+    let mut part = String::new();
+    interp.to_string(args[0], &mut part).map_err(|_| arg_type_error("apropos", "String", 1))?;
+    let mut bound = HashSet::new();
+    let mut env = Some(interp.env.clone());
+    while let Some(current) = env {
+        bound.extend(current.borrow().bindings().into_iter().map(|(id, _)| id));
+        env = current.borrow().parent.clone();
+    }
+    let mut heap = interp.heap.borrow_mut();
+    let mut matches = bound.into_iter()
+        .filter_map(|id| match heap.get(id) {
+            HeapObject::Symbol(name) if name.contains(&part) => Some((name.clone(), id)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    matches.sort();
+    let symbols = matches.into_iter().map(|(_, id)| Value::Object(id)).collect::<Vec<_>>();
+    heap.alloc_list(&symbols)
+}
+
+fn primitive_hash_table_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("hash-table?", args, 1);
+    let is_table = interp.is_object(args[0]).is_some_and(|id| {
+        matches!(interp.heap.borrow().get(id), HeapObject::HashTable { .. })
+    });
+    Ok(Value::Boolean(is_table))
+}
+
+fn primitive_hash_set(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("hash-set!", args, 3);
+    interp.with_hash_table("hash-set!", args[0], |table| table.insert(HashKey::from(args[1]), args[2]))?;
+    Ok(args[2])
+}
+
+// (hash-ref table key [default]) errors on a missing key without a default.
+fn primitive_hash_ref(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("hash-ref", args, 2..=3);
+    match interp.with_hash_table("hash-ref", args[0], |table| table.get(&HashKey::from(args[1])).copied())? {
+        Some(value) => Ok(value),
+        None if args.len() == 3 => Ok(args[2]),
+        None => Err(SchemeError::EvalError(format!(
+            "hash-ref: key {} not found.", interp.write(args[1])
+        ))),
+    }
+}
+
+// Unlike hash-table-update!, the default is returned as is, not called.
+fn primitive_hash_table_ref_default(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("hash-table-ref/default", args, 3);
+    let value = interp.with_hash_table("hash-table-ref/default", args[0], |table| table.get(&HashKey::from(args[1])).copied())?;
+    Ok(value.unwrap_or(args[2]))
+}
+
+fn primitive_hash_table_contains_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("hash-table-contains?", args, 2);
+    let found = interp.with_hash_table("hash-table-contains?", args[0], |table| table.contains_key(&HashKey::from(args[1])))?;
+    Ok(Value::Boolean(found))
+}
+
+fn primitive_hash_delete(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("hash-delete!", args, 2);
+    let removed = interp.with_hash_table("hash-delete!", args[0], |table| table.remove(&HashKey::from(args[1])))?;
+    Ok(Value::Boolean(removed.is_some()))
+}
+
+fn primitive_hash_count(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("hash-count", args, 1);
+    let count = interp.with_hash_table("hash-count", args[0], |table| table.len())?;
+    Ok(Value::Number(Number::Int(count as i64)))
+}
+
+// (hash-table-update! table key proc [default]) stores (proc value), where a
+// missing value is taken from calling the default thunk.
+fn primitive_hash_table_update(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("hash-table-update!", args, 3..=4);
+    let proc = interp.check_procedure("hash-table-update!", args[2], 3)?;
+    let key = HashKey::from(args[1]);
+    let value = match interp.with_hash_table("hash-table-update!", args[0], |table| table.get(&key).copied())? {
+        Some(value) => value,
+        None if args.len() == 4 => {
+            let default = interp.check_procedure("hash-table-update!", args[3], 4)?;
+            default.apply(interp, &interp.env, Vec::new())?
+        },
+        None => return Err(SchemeError::EvalError(format!(
+            "hash-table-update!: key {} not found.", interp.write(args[1])
+        ))),
+    };
+    let value = proc.apply(interp, &interp.env, vec![value])?;
+    interp.with_hash_table("hash-table-update!", args[0], |table| table.insert(key, value))?;
+    Ok(value)
+}
+
+// Snapshots the entries so that procedures applied to them may freely
+// mutate the table. Iteration order is unspecified.
+fn hash_table_entries(interp: &Interp, name: &str, table: Value) -> Result<Vec<(Value, Value)>, SchemeError> {
+    interp.with_hash_table(name, table, |table| {
+        table.iter().map(|(key, value)| (Value::from(*key), *value)).collect()
+    })
+}
+
+// (hash-table-walk table proc) calls (proc key value) on each entry.
+fn primitive_hash_table_walk(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("hash-table-walk", args, 2);
+    let proc = interp.check_procedure("hash-table-walk", args[1], 2)?;
+    for (key, value) in hash_table_entries(interp, "hash-table-walk", args[0])? {
+        proc.apply(interp, &interp.env, vec![key, value])?;
+    }
+    Ok(Value::Unspecified)
+}
+
+// (hash-table-fold table proc init) threads (proc key value acc) through the entries.
+fn primitive_hash_table_fold(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("hash-table-fold", args, 3);
+    let proc = interp.check_procedure("hash-table-fold", args[1], 2)?;
+    hash_table_entries(interp, "hash-table-fold", args[0])?.into_iter().try_fold(args[2], |acc, (key, value)| {
+        proc.apply(interp, &interp.env, vec![key, value, acc])
+    })
+}
+
+// (make-parameter value [converter]) converts the initial value too.
+fn primitive_make_parameter(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let (value, converter) = match args {
+        [value] => (*value, None),
+        [value, converter] => (*value, Some(interp.check_procedure("make-parameter", *converter, 2)?)),
+        _ => return Err(arg_count_error("make-parameter", "1 to 2", args.len())),
+    };
+    let value = match converter {
+        Some(converter) => converter.apply(interp, &interp.env, vec![value])?,
+        None => value,
+    };
+    interp.heap.borrow_mut().alloc_parameter(value, converter)
+}
+
+fn convert_float_precision(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("float-print-precision", args, 1);
+    match args[0] {
+        Value::Boolean(false) => Ok(args[0]),
+        Value::Number(Number::Int(digits)) if (0..=64).contains(&digits) => Ok(args[0]),
+        value => Err(SchemeError::TypeError(format!(
+            "float-print-precision: expected #f or a digit count up to 64, but got {}.", value.type_name()
+        ))),
+    }
+}
+
+fn primitive_force(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("force", args, 1);
+    interp.force(args[0])
+}
+
+// (make-promise v) is an already forced promise of v, or v itself if a promise.
+fn primitive_make_promise(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("make-promise", args, 1);
+    let is_promise = interp.is_object(args[0]).is_some_and(|id| {
+        matches!(interp.heap.borrow().get(id), HeapObject::Promise { .. })
+    });
+    if is_promise {
+        Ok(args[0])
+    } else {
+        interp.heap.borrow_mut().alloc_resolved_promise(args[0])
+    }
+}
+
+// Forces a stream, returning its head and tail unless it is empty.
+fn stream_next(interp: &Interp, name: &str, stream: Value) -> Result<Option<(Value, Value)>, SchemeError> {
+    match interp.force(stream)? {
+        Value::Nil => Ok(None),
+        forced => match interp.to_pair(forced) {
+            Ok(pair) => Ok(Some(pair)),
+            Err(_) => Err(arg_type_error(name, "Stream", 1)),
+        },
+    }
+}
+
+// A stream computed by func on args when first forced, func must return a stream.
+fn delay_stream(interp: &Interp, func: heap::PrimitiveFn, args: &[Value]) -> Result<Value, SchemeError> {
+    let mut heap = interp.heap.borrow_mut();
+    let quote = Value::Object(heap::Keyword::Quote as GcId);
+    let args = args.iter()
+        .map(|arg| heap.alloc_list(&[quote, *arg]))
+        .collect::<Result<Vec<Value>, SchemeError>>()?;
+    let func = heap.alloc_primitive(func)?;
+    heap.alloc_delayed_call(func, &args, &interp.env, true)
+}
+
+fn stream_cons(interp: &Interp, head: Value, tail: Value) -> Result<Value, SchemeError> {
+    let mut heap = interp.heap.borrow_mut();
+    let pair = heap.alloc_pair(head, tail)?;
+    heap.alloc_resolved_promise(pair)
+}
+
+fn primitive_stream_null_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("stream-null?", args, 1);
+    Ok(Value::Boolean(stream_next(interp, "stream-null?", args[0])?.is_none()))
+}
+
+fn primitive_stream_car(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("stream-car", args, 1);
+    match stream_next(interp, "stream-car", args[0])? {
+        Some((head, _)) => Ok(head),
+        None => Err(SchemeError::EvalError("stream-car: empty stream".to_string())),
+    }
+}
+
+fn primitive_stream_cdr(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("stream-cdr", args, 1);
+    match stream_next(interp, "stream-cdr", args[0])? {
+        Some((_, tail)) => Ok(tail),
+        None => Err(SchemeError::EvalError("stream-cdr: empty stream".to_string())),
+    }
+}
+
+// (stream-take stream n) is the stream of the first n elements, or fewer
+// when stream runs out.
+fn primitive_stream_take(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("stream-take", args, 2);
+    let count = interp.as_integer(args[1]).map_err(|_| arg_type_error("stream-take", "Int", 2))?;
+    if count <= 0 {
+        return interp.heap.borrow_mut().alloc_resolved_promise(Value::Nil);
+    }
+    delay_stream(interp, stream_take_step, args)
+}
+
+fn stream_take_step(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    match stream_next(interp, "stream-take", args[0])? {
+        Some((head, tail)) => {
+            let count = Value::Number(Number::Int(interp.as_integer(args[1])? - 1));
+            let rest = primitive_stream_take(interp, &[tail, count])?;
+            stream_cons(interp, head, rest)
+        },
+        None => Ok(args[0]),
+    }
+}
+
+fn primitive_stream_map(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("stream-map", args, 2);
+    delay_stream(interp, stream_map_step, args)
+}
+
+fn stream_map_step(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    match stream_next(interp, "stream-map", args[1])? {
+        Some((head, tail)) => {
+            let head = args[0].apply(interp, &interp.env, vec![head])?;
+            let rest = primitive_stream_map(interp, &[args[0], tail])?;
+            stream_cons(interp, head, rest)
+        },
+        None => Ok(args[1]),
+    }
+}
+
+fn primitive_stream_to_list(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("stream->list", args, 1);
+    let mut items = Vec::new();
+    let mut stream = args[0];
+    while let Some((head, tail)) = stream_next(interp, "stream->list", stream)? {
+        items.push(head);
+        stream = tail;
+    }
+    interp.heap.borrow_mut().alloc_list(&items)
+}
+
+fn primitive_promise_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("promise?", args, 1);
+    let is_promise = interp.is_object(args[0]).is_some_and(|id| {
+        matches!(interp.heap.borrow().get(id), HeapObject::Promise { .. })
+    });
+    Ok(Value::Boolean(is_promise))
+}
+
+fn primitive_char_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("char?", args, 1);
+    Ok(Value::Boolean(interp.is_char(args[0]).is_some()))
+}
+
+fn primitive_char_alphabetic_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("char-alphabetic?", args, 1, ch: Char);
+    Ok(Value::Boolean(ch.is_ascii_alphabetic()))
+}
+
+fn primitive_char_numeric_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("char-numeric?", args, 1, ch: Char);
+    Ok(Value::Boolean(ch.is_ascii_digit()))
+}
+
+fn primitive_char_whitespace_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("char-whitespace?", args, 1, ch: Char);
+    Ok(Value::Boolean(ch.is_ascii_whitespace()))
+}
+
+fn primitive_char_upper_case_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("char-upper-case?", args, 1, ch: Char);
+    Ok(Value::Boolean(ch.is_ascii_uppercase()))
+}
+
+fn primitive_char_lower_case_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("char-lower-case?", args, 1, ch: Char);
+    Ok(Value::Boolean(ch.is_ascii_lowercase()))
+}
+
+fn primitive_char_to_integer(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("char->integer", args, 1, ch: Char);
+    Ok(Value::Number(Number::Int(*ch as i64)))
+}
+
+fn primitive_integer_to_char(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("integer->char", args, 1);
+    let byte = interp.as_integer(args[0]).map_err(|_| arg_type_error("integer->char", "Int", 1))?;
+    Ok(Value::Char(byte as u8))
+}
+
+fn primitive_char_upcase(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("char-upcase", args, 1, ch: Char);
+    Ok(Value::Char(ch.to_ascii_uppercase()))
+}
+
+fn primitive_char_downcase(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("char-downcase", args, 1, ch: Char);
+    Ok(Value::Char(ch.to_ascii_lowercase()))
+}
+
+fn primitive_char_eq(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("char=?", args, 2, ch1: Char, ch2: Char);
+    Ok(Value::Boolean(ch1 == ch2))
+}
+
+fn primitive_char_lt(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("char<?", args, 2, ch1: Char, ch2: Char);
+    Ok(Value::Boolean(ch1 < ch2))
+}
+
+fn primitive_char_lte(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("char<=?", args, 2, ch1: Char, ch2: Char);
+    Ok(Value::Boolean(ch1 <= ch2))
+}
+
+fn primitive_char_gt(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("char>?", args, 2, ch1: Char, ch2: Char);
+    Ok(Value::Boolean(ch1 > ch2))
+}
+
+fn primitive_char_gte(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("char>=?", args, 2, ch1: Char, ch2: Char);
+    Ok(Value::Boolean(ch1 >= ch2))
+}
+
+fn primitive_char_ci_eq(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("char-ci=?", args, 2, ch1: Char, ch2: Char);
+    Ok(Value::Boolean(ch1.eq_ignore_ascii_case(ch2)))
+}
+
+fn primitive_char_ci_lt(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("char-ci<?", args, 2, ch1: Char, ch2: Char);
+    Ok(Value::Boolean(ch1.to_ascii_lowercase() < ch2.to_ascii_lowercase()))
+}
+
+fn primitive_char_ci_lte(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("char-ci<=?", args, 2, ch1: Char, ch2: Char);
+    Ok(Value::Boolean(ch1.to_ascii_lowercase() <= ch2.to_ascii_lowercase()))
 }
 
 fn primitive_char_ci_gt(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 2, ch1: Char, ch2: Char);
+    extract_args!("char-ci>?", args, 2, ch1: Char, ch2: Char);
     Ok(Value::Boolean(ch1.to_ascii_lowercase() > ch2.to_ascii_lowercase()))
 }
 
 fn primitive_char_ci_gte(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
-    extract_args!(args, 2, ch1: Char, ch2: Char);
+    extract_args!("char-ci>=?", args, 2, ch1: Char, ch2: Char);
     Ok(Value::Boolean(ch1.to_ascii_lowercase() >= ch2.to_ascii_lowercase()))
 }
 
+fn primitive_error(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("error", args, 1..);
+    let (message, irritants) = (&args[0], &args[1..]);
+    interp.raise(SchemeError::UserError(interp.display(*message), irritants.to_vec()), false)
+}
+
+fn primitive_raise(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("raise", args, 1);
+    interp.raise(SchemeError::Raised(args[0]), false)
+}
+
+fn primitive_raise_continuable(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("raise-continuable", args, 1);
+    interp.raise(SchemeError::Raised(args[0]), true)
+}
+
+// Only raise, raise-continuable and error call the handler. Errors signalled
+// by the primitives themselves unwind the stack, and are only caught by guard.
+fn primitive_with_exception_handler(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("with-exception-handler", args, 2);
+    let handler = interp.check_procedure("with-exception-handler", args[0], 1)?;
+    let thunk = interp.check_procedure("with-exception-handler", args[1], 2)?;
+    interp.with_exception_handler(handler, thunk)
+}
+
+fn primitive_condition_p(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("condition?", args, 1);
+    let is_condition = interp.is_object(args[0]).is_some_and(|id| {
+        matches!(interp.heap.borrow().get(id), HeapObject::Condition(_))
+    });
+    Ok(Value::Boolean(is_condition))
+}
+
+fn primitive_condition_message(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("condition-message", args, 1);
+    let type_error = || arg_type_error("condition-message", "Condition", 1);
+    let id = interp.to_object(args[0]).map_err(|_| type_error())?;
+    let message = match interp.heap.borrow().get(id) {
+        HeapObject::Condition(error) => error.message(),
+        _ => return Err(type_error()),
+    };
+    interp.heap.borrow_mut().alloc_string(message)
+}
+
+fn output_port_arg(name: &str, args: &[Value], count: usize) -> Result<Option<Value>, SchemeError> {
+    match args.len() {
+        n if n == count => Ok(None),
+        n if n == count + 1 => Ok(Some(args[count])),
+        n => Err(arg_count_error(name, &format!("{} to {}", count, count + 1), n)),
+    }
+}
+
+fn primitive_write(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let port = output_port_arg("write", args, 1)?;
+    interp.write_output(port, &interp.write(args[0]))?;
+    Ok(Value::Unspecified)
+}
+
+fn primitive_write_shared(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let port = output_port_arg("write-shared", args, 1)?;
+    interp.write_output(port, &interp.write_shared(args[0]))?;
+    Ok(Value::Unspecified)
+}
+
+fn primitive_write_simple(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let port = output_port_arg("write-simple", args, 1)?;
+    interp.write_output(port, &interp.write_simple(args[0]))?;
+    Ok(Value::Unspecified)
+}
+
+fn primitive_display(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let port = output_port_arg("display", args, 1)?;
+    interp.write_output(port, &interp.display(args[0]))?;
+    Ok(Value::Unspecified)
+}
+
+fn primitive_newline(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let port = output_port_arg("newline", args, 0)?;
+    interp.write_output(port, "\n")?;
+    Ok(Value::Unspecified)
+}
+
+fn primitive_open_output_string(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("open-output-string", args, 0);
+    interp.heap.borrow_mut().alloc_output_string()
+}
+
+fn primitive_get_output_string(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("get-output-string", args, 1);
+    let type_error = || arg_type_error("get-output-string", "OutputString", 1);
+    let id = interp.to_object(args[0]).map_err(|_| type_error())?;
+    let mut heap = interp.heap.borrow_mut();
+    let text = heap.output_string_mut(id).map_err(|_| type_error())?.clone();
+    heap.alloc_string(text)
+}
+
+fn primitive_flush_output_port(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    let port = output_port_arg("flush-output-port", args, 0)?;
+    interp.flush_output(port)?;
+    Ok(Value::Unspecified)
+}
+
+fn primitive_set_profiling(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    extract_args!("set-profiling!", args, 1, enabled: Boolean);
+    interp.set_profiling(*enabled);
+    Ok(Value::Unspecified)
+}
+
+fn primitive_profile_report(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("profile-report", args, 0);
+    interp.profile_report()
+}
+
+// Values in use by the evaluation in progress aren't known to the collector,
+// it only runs once the outermost one returns.
+fn primitive_gc(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("gc", args, 0);
+    interp.gc_requested.set(true);
+    Ok(Value::Unspecified)
+}
+
+// (gc-stats) returns an alist of the total, live and free heap slots, and of
+// the number of interned symbols, as they were right before building the alist.
+fn primitive_gc_stats(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("gc-stats", args, 0);
+    let mut heap = interp.heap.borrow_mut();
+    let names = ["total", "live", "free", "symbols"].map(|name| heap.intern_symbol(name));
+    let (total, free, symbols) = (heap.size(), heap.free_count(), heap.symbol_count());
+    let entries = names.iter().zip([total, total - free, free, symbols])
+        .map(|(name, count)| heap.alloc_pair(*name, Value::Number(Number::Int(count as i64))))
+        .collect::<Result<Vec<Value>, SchemeError>>()?;
+    heap.alloc_list(&entries)
+}
+
+fn primitive_open_input_string(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("open-input-string", args, 1);
+    let mut text = String::new();
+    interp.to_string(args[0], &mut text).map_err(|_| arg_type_error("open-input-string", "String", 1))?;
+    interp.heap.borrow_mut().alloc_input_string(text)
+}
+
+fn primitive_read(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("read", args, 1);
+    interp.read_port(args[0])
+}
+
+// (read-string k port) reads up to k characters, fewer when the port runs
+// out, and the eof object once it has.
+fn primitive_read_string(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("read-string", args, 2);
+    let count = interp.as_integer(args[0]).map_err(|_| arg_type_error("read-string", "Int", 1))?;
+    let count = usize::try_from(count).map_err(|_| {
+        SchemeError::EvalError("read-string: negative count.".to_string())
+    })?;
+    let type_error = || arg_type_error("read-string", "InputString", 2);
+    let id = interp.to_object(args[1]).map_err(|_| type_error())?;
+    let mut heap = interp.heap.borrow_mut();
+    let (text, position) = heap.input_string_mut(id).map_err(|_| type_error())?;
+    let rest = &text[*position..];
+    if rest.is_empty() && count > 0 {
+        return Ok(Value::Eof);
+    }
+    let end = rest.char_indices().nth(count).map_or(rest.len(), |(i, _)| i);
+    let read = rest[..end].to_string();
+    *position += end;
+    heap.alloc_string(read)
+}
+
+fn primitive_open_input_bytevector(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("open-input-bytevector", args, 1);
+    let type_error = || arg_type_error("open-input-bytevector", "Bytevector", 1);
+    let id = interp.to_object(args[0]).map_err(|_| type_error())?;
+    let mut heap = interp.heap.borrow_mut();
+    let bytes = heap.bytevector_mut(id).map_err(|_| type_error())?.clone();
+    heap.alloc_input_bytevector(bytes)
+}
+
+fn primitive_read_u8(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("read-u8", args, 1);
+    let type_error = || arg_type_error("read-u8", "InputBytevector", 1);
+    let id = interp.to_object(args[0]).map_err(|_| type_error())?;
+    let mut heap = interp.heap.borrow_mut();
+    let (bytes, position) = heap.input_bytevector_mut(id).map_err(|_| type_error())?;
+    match bytes.get(*position).copied() {
+        Some(byte) => {
+            *position += 1;
+            Ok(Value::Number(Number::Int(byte as i64)))
+        },
+        None => Ok(Value::Eof),
+    }
+}
+
+fn primitive_eof_object(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("eof-object", args, 0);
+    Ok(Value::Eof)
+}
+
+fn primitive_eof_object_p(_interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
+    check_arity!("eof-object?", args, 1);
+    Ok(Value::Boolean(args[0] == Value::Eof))
+}
+
 fn primitive_debug(interp: &Interp, args: &[Value]) -> Result<Value, SchemeError> {
     for (i, arg) in args.iter().enumerate() {
         if i > 0 {
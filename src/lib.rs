@@ -4,10 +4,12 @@ pub mod interp;
 pub mod env;
 pub mod parser;
 pub mod macros;
+pub mod syntax;
 #[cfg(test)]
 mod tests {
     mod test_heap;
     mod test_eval;
     mod test_interp;
     mod test_parser;
+    mod test_tail_calls;
 }
\ No newline at end of file
@@ -1,30 +1,59 @@
+use crate::types::SchemeError;
 
+// All primitives report argument errors through these two, so that the
+// messages read the same everywhere.
+pub fn arg_count_error(name: &str, expected: &str, got: usize) -> SchemeError {
+    let noun = if matches!(expected, "1" | "at least 1") { "arg" } else { "args" };
+    SchemeError::ArgCountError(format!(
+        "{}: expected {} {}, but got {}", name, expected, noun, got
+    ))
+}
+
+pub fn arg_type_error(name: &str, type_name: &str, position: usize) -> SchemeError {
+    SchemeError::TypeError(format!(
+        "{}: expected {} as argument {}", name, type_name, position
+    ))
+}
+
+// check_arity!(name, args, 2), check_arity!(name, args, 1..=3) for optional
+// args, or check_arity!(name, args, 1..) for rest args.
 #[macro_export]
 macro_rules! check_arity {
-    ($args:expr, $count: expr) => {
+    ($name:expr, $args:expr, $min:literal ..) => {
+        if $args.len() < $min {
+            return Err($crate::macros::arg_count_error(
+                $name, &format!("at least {}", $min), $args.len()
+            ))
+        }
+    };
+    ($name:expr, $args:expr, $min:literal ..= $max:literal) => {
+        if $args.len() < $min || $args.len() > $max {
+            return Err($crate::macros::arg_count_error(
+                $name, &format!("{} to {}", $min, $max), $args.len()
+            ))
+        }
+    };
+    ($name:expr, $args:expr, $count:expr) => {
         if $args.len() != $count {
-            return Err(SchemeError::ArgCountError(format!(
-                "Expected {} args, but got {}.", $count, $args.len()
-            )))
+            return Err($crate::macros::arg_count_error(
+                $name, &$count.to_string(), $args.len()
+            ))
         }
-    }
+    };
 }
 
+// Checks the arg count, and binds each arg to the payload of its Value variant.
 #[macro_export]
 macro_rules! extract_args {
-    ($args:expr, $count:expr, $($name:ident : $variant:ident),*) => {
-        if $args.len() != $count {
-            return Err(SchemeError::ArgCountError(format!(
-                "Invalid arg-count {} expected {}.", 
-                $args.len(), $count))
-            );
-        }
-        let mut iter = $args.into_iter();
+    ($name:expr, $args:expr, $count:expr, $($arg:ident : $variant:ident),*) => {
+        $crate::check_arity!($name, $args, $count);
+        let mut iter = $args.into_iter().enumerate();
         $(
-            let $name = match iter.next().unwrap() {
-                Value::$variant(val) => val,
-                _ => return Err(SchemeError::TypeError(format!(
-                    "Invalid type {}.", stringify!($variant).to_string()))),
+            let $arg = match iter.next().unwrap() {
+                (_, Value::$variant(val)) => val,
+                (i, _) => return Err($crate::macros::arg_type_error(
+                    $name, stringify!($variant), i + 1
+                )),
             };
         )*
     };
@@ -32,10 +61,10 @@ macro_rules! extract_args {
 
 #[macro_export]
 macro_rules! all_of_type {
-    ($args:expr, $variant:path, $type_name:expr) => {
-        $args.into_iter().map(|v| match v {
+    ($name:expr, $args:expr, $variant:path, $type_name:expr) => {
+        $args.into_iter().enumerate().map(|(i, v)| match v {
             $variant(inner) => Ok(*inner),
-            _ => Err(SchemeError::TypeError($type_name.to_string())),
+            _ => Err($crate::macros::arg_type_error($name, $type_name, i + 1)),
         }).collect::<Result<Vec<_>, SchemeError>>()?
     };
-}
\ No newline at end of file
+}
@@ -1,4 +1,5 @@
-use std::{process};
+use std::io::{self, Write};
+use std::{process, thread};
 
 use rustyline::DefaultEditor;
 use rustyline::error::ReadlineError;
@@ -8,13 +9,17 @@ use scheme::types::{Value};
 use scheme::interp::{Interp};
 
 fn eval_expr(interp: &Interp, expr: Value) {
-    let result = interp.eval(expr);
+    let result = interp.repl_eval(expr);
     match result {
+        // Forms such as set! or a one armed if have no value to show.
+        Ok(Value::Unspecified) => {},
         Ok(val) => {
-            println!(" = {}", interp.display(val));
+            println!(" = {}", interp.write(val));
         },
-        Err(e) => eprintln!("Error: {:?}", e),
+        Err(e) => eprintln!("Error: {}", interp.display_error(&e)),
     }
+    // Shows output not ending with a newline before the next prompt.
+    let _ = io::stdout().flush();
 }
 
 const HISTORY_FILENAME: &str = ".scheme.history";
@@ -36,7 +41,7 @@ fn repl(interp: &Interp) {
                 match expr {
                     Ok(Value::Nil) => process::exit(0),
                     Ok(expr) => eval_expr(interp, expr),
-                    Err(e) => eprintln!("Error: {:?}", e),
+                    Err(e) => eprintln!("Error: {}", interp.display_error(&e)),
                 }
             },
             Err(ReadlineError::Interrupted) => {
@@ -52,13 +57,23 @@ fn repl(interp: &Interp) {
             }            
         }
     }
-    rl.save_history(HISTORY_FILENAME).expect(format!(
-        "Failed to save history to {}.", HISTORY_FILENAME).as_str()
-    );
+    rl.save_history(HISTORY_FILENAME).unwrap_or_else(|_| panic!("Failed to save history to {}.", HISTORY_FILENAME));
 }
 
-fn main() {
-    let interp = Interp::new();
+// Evaluation recurses on the Rust stack, this leaves room for MAX_DEPTH
+// nested evaluations even in debug builds.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+const MAX_DEPTH: usize = 10_000;
 
-    repl(&interp);
+fn main() {
+    let repl_thread = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(|| {
+            let interp = Interp::new();
+            interp.set_max_depth(MAX_DEPTH);
+            interp.set_warning_handler(|message| eprintln!("Warning: {}", message));
+            repl(&interp);
+        })
+        .expect("Failed to start the REPL thread.");
+    let _ = repl_thread.join();
 }
@@ -1,28 +1,83 @@
-use std::io::{BufReader, Bytes, Read};
+use std::io::{BufRead, Bytes};
 use std::iter::Peekable;
 
 use crate::heap::{Keyword};
 use crate::interp::Interp;
 use crate::types::{Number, SchemeError, Value};
 
-pub struct Parser<R: Read> {
-    reader: Peekable<Bytes<BufReader<R>>>,
+// Default limit on how deeply lists, vectors and quotes may nest. The reader
+// recurses on each level and would otherwise overflow the native stack, which
+// in debug builds holds about 800 levels on a 2MB thread.
+pub const DEFAULT_MAX_DEPTH: usize = 512;
+
+// Whether ch may appear in a symbol after its first character.
+pub fn is_symbol_char(ch: u8) -> bool {
+    ch.is_ascii_alphanumeric() || b"!$%&*/:<=>?^_~+-".contains(&ch)
+}
+
+// Whether ch may start a symbol.
+fn is_symbol_start(ch: u8) -> bool {
+    matches!(ch, b'a'..=b'z' | b'A'..=b'Z' 
+        | b'+' | b'-' | b'*' | b'/'| b'>' | b'<'| b'=' | b'%'
+        | b'!' | b'?' | b'_')
+}
+
+// Whether text would read as a single symbol, checked by the reader's rules
+// without interning it.
+pub fn is_identifier(text: &str) -> bool {
+    match text.as_bytes() {
+        b"..." => true,
+        [b'+' | b'-', next, ..] if next.is_ascii_digit() => false,
+        [first, rest @ ..] => is_symbol_start(*first) && rest.iter().all(|ch| is_symbol_char(*ch)),
+        [] => false,
+    }
+}
+
+// Reads its input a byte at a time, so a file must come buffered, while a
+// byte slice is read in place.
+pub struct Parser<R: BufRead> {
+    reader: Peekable<Bytes<R>>,
+    position: usize,
+    depth: usize,
+    max_depth: usize,
 }
 
-impl<R: Read> Parser<R> {
+impl<R: BufRead> Parser<R> {
     
     pub fn new(reader: R) -> Self {
         Self {
-            reader: BufReader::new(reader).bytes().peekable(),
+            reader: reader.bytes().peekable(),
+            position: 0,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
         }
     }
 
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    // Number of bytes consumed from the reader so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    // Skips whitespace and comments, and checks for the end of the input.
+    pub fn at_eof(&mut self) -> bool {
+        self.skip_whitespace();
+        self.peek().is_none()
+    }
+
     fn peek(&mut self) -> Option<u8> {
         self.reader.peek()?.as_ref().ok().cloned()
     }
 
     fn next(&mut self) -> Option<u8> {
-        self.reader.next()?.ok()
+        let byte = self.reader.next()?.ok();
+        if byte.is_some() {
+            self.position += 1;
+        }
+        byte
     }
 
     fn check_for(&mut self, expected: u8) -> Result<(), SchemeError> {
@@ -41,10 +96,9 @@ impl<R: Read> Parser<R> {
         ch.is_ascii_whitespace()
     }
 
-    fn is_symbol(&self, ch: u8) -> bool {
-        matches!(ch, b'a'..=b'z' | b'A'..=b'Z' 
-            | b'+' | b'-' | b'*' | b'/'| b'>' | b'<'| b'=' | b'%'
-            | b'!' | b'?')
+    // Identifiers must be followed by one of these, so that (a,b) is an error.
+    fn is_delimiter(&self, ch: u8) -> bool {
+        self.is_whitespace(ch) || b"()\";".contains(&ch)
     }
 
     fn skip_whitespace(&mut self) {
@@ -62,6 +116,24 @@ impl<R: Read> Parser<R> {
         }
     }
 
+    // Underscores as digit separators (1_000_000, #xFF_FF) are a non-standard
+    // extension: each one must sit between two digits of the given radix.
+    fn strip_digit_separators(&self, token: &str, radix: u32) -> Result<String, SchemeError> {
+        let chars: Vec<char> = token.chars().collect();
+        for (i, ch) in chars.iter().enumerate() {
+            if *ch == '_' {
+                let before = i > 0 && chars[i - 1].is_digit(radix);
+                let after = chars.get(i + 1).is_some_and(|next| next.is_digit(radix));
+                if !before || !after {
+                    return Err(SchemeError::SyntaxError(format!(
+                        "Misplaced digit separator in number: {}", token
+                    )));
+                }
+            }
+        }
+        Ok(token.replace('_', ""))
+    }
+
     fn parse_number_with_sign(&mut self, sign: Option<u8>) -> Result<Value, SchemeError> {
         let mut token = String::new();
         if let Some(ch) = sign {
@@ -76,7 +148,7 @@ impl<R: Read> Parser<R> {
             self.next();
         }
         while let Some(ch) = self.peek() {
-            if ch.is_ascii_digit() {
+            if ch.is_ascii_digit() || ch == b'_' {
                 token.push(ch as char);
                 self.next();
             } else if ch == b'.' && !has_dot && ! has_exponent {
@@ -96,6 +168,7 @@ impl<R: Read> Parser<R> {
                 break;
             }
         }
+        let token = self.strip_digit_separators(&token, 10)?;
         if has_dot || has_exponent {
             match token.parse::<f64>() {
                 Ok(num) => Ok(Value::Number(Number::Float(num))),
@@ -119,31 +192,49 @@ impl<R: Read> Parser<R> {
             token.push(ch as char)
         }
         while let Some(ch) = self.peek() {
-            if ch.is_ascii_alphanumeric() || b"!$%&*/:<=>?^_~+-".contains(&ch) {
+            if is_symbol_char(ch) {
                 token.push(ch as char);
                 self.next();
-            } else {
+            } else if self.is_delimiter(ch) {
                 break;
+            } else {
+                return Err(SchemeError::SyntaxError(format!(
+                    "Unexpected character {} in identifier {}", ch as char, token
+                )));
             }
         }
-        return Ok(interp.lookup(&token))
+        Ok(interp.lookup(&token))
     }
 
     fn parse_symbol(&mut self, interp: &Interp) -> Result<Value, SchemeError> {
-        return self.parse_symbol_with_lead(interp, None)
+        self.parse_symbol_with_lead(interp, None)
+    }
+
+    // Reads the datum following 'x `x ,x or ,@x into (keyword x).
+    fn parse_abbreviation(&mut self, interp: &Interp, keyword: Value) -> Result<Value, SchemeError> {
+        let datum = self.read(interp)?;
+        interp.heap.borrow_mut().alloc_list(&[keyword, datum])
+    }
+
+    // Reads the rest of the ... identifier, whose first dot was consumed.
+    fn parse_ellipsis(&mut self, interp: &Interp) -> Result<Value, SchemeError> {
+        self.check_for(b'.')?;
+        self.check_for(b'.')?;
+        Ok(interp.lookup("..."))
     }
 
     fn parse_hash_number(&mut self, radix: u32) -> Result<Value, SchemeError> {
         let mut token = String::new();
         while let Some(byte) = self.peek() {
             let ch = byte as char;
-            if ch.is_digit(radix) {
+            if ch.is_digit(radix) || ch == '_' {
                 self.next();
                 token.push(ch);
             } else {
                 break;
             }
         }
+        let token = self.strip_digit_separators(&token, radix)?;
         match i64::from_str_radix(&token, radix) {
             Ok(num) => Ok(Value::Number(Number::Int(num))),
             Err(_) => Err(SchemeError::SyntaxError(format!(
@@ -154,9 +245,19 @@ impl<R: Read> Parser<R> {
 
     fn parse_hash_character(&mut self) -> Result<Value, SchemeError> {
         let mut token = String::new();
+        // The first character is taken as is, so #\( or #\3 read fine.
+        match self.next() {
+            Some(ch) => token.push(ch as char),
+            None => return Err(SchemeError::SyntaxError(
+                "Unexpected end of file while parsing a #\\ character.".to_string()
+            )),
+        }
+        if !token.as_bytes()[0].is_ascii_alphabetic() {
+            return Ok(Value::Char(token.as_bytes()[0]));
+        }
         while let Some(ch) = self.peek() {
             let ch = ch as char;
-            if ch.is_ascii_alphabetic() {
+            if ch.is_ascii_alphanumeric() {
                 self.next();
                 token.push(ch);
             } else {
@@ -172,6 +273,17 @@ impl<R: Read> Parser<R> {
                 "tab" => Ok(Value::Char(9)),
                 "newline" => Ok(Value::Char(10)),
                 "return" => Ok(Value::Char(13)),
+                "null" => Ok(Value::Char(0)),
+                "alarm" => Ok(Value::Char(7)),
+                "escape" => Ok(Value::Char(27)),
+                "delete" => Ok(Value::Char(127)),
+                _ if token.starts_with('x') && token.len() > 1 => {
+                    u8::from_str_radix(&token[1..], 16)
+                        .map(Value::Char)
+                        .map_err(|_| SchemeError::SyntaxError(format!(
+                            "Invalid #\\ token {}.", token
+                        )))
+                },
                 _ => Err(SchemeError::SyntaxError(format!(
                     "Invalid #\\ token {}.", token
                 )))
@@ -179,16 +291,22 @@ impl<R: Read> Parser<R> {
         }
     }
 
+    #[cfg(test)]
     fn parse_hash(&mut self) -> Result<Value, SchemeError> {
         self.check_for(b'#')?;
+        self.parse_hash_body()
+    }
+
+    // Parses what follows a '#', once it has been consumed.
+    fn parse_hash_body(&mut self) -> Result<Value, SchemeError> {
         match self.next() {
-            Some(ch) if ch.to_ascii_lowercase() == b't' => Ok(Value::Boolean(true)),
-            Some(ch) if ch.to_ascii_lowercase() == b'f' => Ok(Value::Boolean(false)),
-            Some(ch) if ch == b'b' => self.parse_hash_number(2),
-            Some(ch) if ch == b'o' => self.parse_hash_number(8),
-            Some(ch) if ch == b'd' => self.parse_hash_number(10),
-            Some(ch) if ch == b'x' => self.parse_hash_number(16),
-            Some(ch) if ch == b'\\' => self.parse_hash_character(),
+            Some(ch) if ch.eq_ignore_ascii_case(&b't') => Ok(Value::Boolean(true)),
+            Some(ch) if ch.eq_ignore_ascii_case(&b'f') => Ok(Value::Boolean(false)),
+            Some(b'b') => self.parse_hash_number(2),
+            Some(b'o') => self.parse_hash_number(8),
+            Some(b'd') => self.parse_hash_number(10),
+            Some(b'x') => self.parse_hash_number(16),
+            Some(b'\\') => self.parse_hash_character(),
             Some(ch) => Err(SchemeError::SyntaxError(format!(
                 "Invalid char in # sequence {}", ch as char
             ))),
@@ -198,6 +316,45 @@ impl<R: Read> Parser<R> {
         }
     }
 
+    // Parses a #!name object literal, or applies a #!name reader directive in
+    // which case there is no value. The '#' is already consumed.
+    fn parse_directive(&mut self, interp: &Interp) -> Result<Option<Value>, SchemeError> {
+        // Only the very first line of the input can be a shebang line, such
+        // as #!/usr/bin/env scheme, which is skipped.
+        let at_start = self.position == 1;
+        self.check_for(b'!')?;
+        if at_start && let Some(ch) = self.peek() && (ch == b'/' || self.is_whitespace(ch)) {
+            while let Some(ch) = self.next() {
+                if ch == b'\n' { break; }
+            }
+            return Ok(None);
+        }
+        let mut token = String::new();
+        while let Some(ch) = self.peek() {
+            if ch.is_ascii_alphanumeric() || ch == b'-' {
+                token.push(ch as char);
+                self.next();
+            } else {
+                break;
+            }
+        }
+        match token.as_str() {
+            "eof" => Ok(Some(Value::Eof)),
+            "default" | "unspecified" => Ok(Some(Value::Unspecified)),
+            "fold-case" => {
+                interp.set_fold_case(true);
+                Ok(None)
+            },
+            "no-fold-case" => {
+                interp.set_fold_case(false);
+                Ok(None)
+            },
+            _ => Err(SchemeError::SyntaxError(format!(
+                "Unknown reader directive #!{}.", token
+            ))),
+        }
+    }
+
     fn parse_string(&mut self, interp: &Interp) -> Result<Value, SchemeError> {
         let mut token = String::new();
         self.check_for(b'"')?;
@@ -205,21 +362,24 @@ impl<R: Read> Parser<R> {
             self.next();
             if ch == b'"' {
                 let mut heap = interp.heap.borrow_mut();
-                return Ok(heap.alloc_string(token));
+                return heap.alloc_string(token);
             } else if ch == b'\\' {
                 match self.next() {
+                    Some(b'n') => token.push('\n'),
+                    Some(b't') => token.push('\t'),
+                    Some(b'r') => token.push('\r'),
                     Some(ch) => token.push(ch as char),
-                    None => return Err(SchemeError::SyntaxError(format!(
-                        "Unexpected enf of file while parsing string."                    
-                    )))
+                    None => return Err(SchemeError::SyntaxError(
+                        "Unexpected enf of file while parsing string.".to_string()
+                    ))
                 }
             } else {
                 token.push(ch as char);
             }
         }
-        return Err(SchemeError::SyntaxError(format!(
-            "Unexpected enf of file while parsing string."
-        )))
+        Err(SchemeError::SyntaxError(
+            "Unexpected enf of file while parsing string.".to_string()
+        ))
     }
 
     fn parse_list(&mut self, interp: &Interp) -> Result<Value, SchemeError> {
@@ -229,21 +389,27 @@ impl<R: Read> Parser<R> {
             match c {
                 b')' => {
                     self.check_for(b')')?;
-                    return Ok(interp.heap.borrow_mut().alloc_list(&items));
+                    return interp.heap.borrow_mut().alloc_list(&items);
                 },
                 b'.' => {
                     self.next();
+                    // Two more dots make the ... identifier, not a dotted tail.
+                    if self.peek() == Some(b'.') {
+                        items.push(self.parse_ellipsis(interp)?);
+                        self.skip_whitespace();
+                        continue;
+                    }
                     let cdr = self.read(interp)?;
                     self.skip_whitespace();
                     self.check_for(b')')?;
                     let mut heap = interp.heap.borrow_mut();
-                    let car = heap.alloc_list(&items);
+                    let car = heap.alloc_list(&items)?;
                     let tail = heap.last(car)?;
                     heap.setcdr(interp.to_object(tail)?, cdr)?;
                     return Ok(car);
                 },
                 _ => {
-                    items.push(self.read(interp)?);
+                    items.extend(self.read_item(interp)?);
                     self.skip_whitespace();
                 }
             }
@@ -253,22 +419,77 @@ impl<R: Read> Parser<R> {
         ))
     }
 
-    // fn parse_vector(&mut self, interp: &Interp) -> Result<Value, SchemeError> {
-    //     let mut list = Vec::new();
-    //     self.skip_whitespace();
-    //     while let Some(c) = self.peek() {
-    //         if c == b')' { break; }
-    //         list.push(self.read(interp)?);
-    //         self.skip_whitespace();
-    //     }
-    //     self.check_for(b')')?;
-    //     return Ok(interp.heap.borrow_mut().alloc_list(list));
-    // }
+    fn parse_vector(&mut self, interp: &Interp) -> Result<Value, SchemeError> {
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        while let Some(c) = self.peek() {
+            if c == b')' {
+                self.next();
+                return interp.heap.borrow_mut().alloc_vector(items);
+            }
+            items.extend(self.read_item(interp)?);
+            self.skip_whitespace();
+        }
+        Err(SchemeError::SyntaxError(
+            "Unexpected end of file while parsing vector.".to_string()
+        ))
+    }
 
+    fn parse_bytevector(&mut self, interp: &Interp) -> Result<Value, SchemeError> {
+        let mut bytes = Vec::new();
+        self.skip_whitespace();
+        while let Some(c) = self.peek() {
+            if c == b')' {
+                self.next();
+                return interp.heap.borrow_mut().alloc_bytevector(bytes);
+            }
+            match self.read_item(interp)? {
+                Some(Value::Number(Number::Int(i))) if (0..=255).contains(&i) => bytes.push(i as u8),
+                None => {},
+                _ => return Err(SchemeError::SyntaxError(
+                    "Bytevector elements must be integers from 0 to 255.".to_string()
+                )),
+            }
+            self.skip_whitespace();
+        }
+        Err(SchemeError::SyntaxError(
+            "Unexpected end of file while parsing bytevector.".to_string()
+        ))
+    }
+
+    // Reads the next datum, skipping any reader directive before it. Input
+    // ending right after a directive has no datum to read, and gives an
+    // unspecified value rather than the nil marking the end of the input.
     pub fn read(&mut self, interp: &Interp) -> Result<Value, SchemeError> {
+        loop {
+            if let Some(value) = self.read_item(interp)? {
+                return Ok(value);
+            }
+            self.skip_whitespace();
+            if self.peek().is_none() {
+                return Ok(Value::Unspecified);
+            }
+        }
+    }
+
+    // Reads a datum, or applies a reader directive in which case there is
+    // none, so that lists and vectors go on with their next item.
+    fn read_item(&mut self, interp: &Interp) -> Result<Option<Value>, SchemeError> {
+        if self.depth >= self.max_depth {
+            return Err(SchemeError::SyntaxError(format!(
+                "Input nested deeper than {} levels.", self.max_depth
+            )));
+        }
+        self.depth += 1;
+        let result = self.read_datum(interp);
+        self.depth -= 1;
+        result
+    }
+
+    fn read_datum(&mut self, interp: &Interp) -> Result<Option<Value>, SchemeError> {
         self.skip_whitespace();
         let current = self.peek();
-        return match current {
+        match current {
             Some(b'(') => {
                 self.next(); // consume '('
                 self.parse_list(interp)
@@ -285,23 +506,48 @@ impl<R: Read> Parser<R> {
             Some(ch) if ch.is_ascii_digit() || ch == b'-' || ch == b'+' => {
                 self.parse_number()
             },
-            Some(ch) if self.is_symbol(ch) => {
+            Some(ch) if is_symbol_start(ch) => {
                 self.parse_symbol(interp)
             },
-            Some(ch) if ch == b'#' => {
-                self.parse_hash()
+            Some(b'.') => {
+                self.next();
+                self.parse_ellipsis(interp)
+            },
+            Some(b'#') => {
+                self.next();
+                if self.peek() == Some(b'!') {
+                    return self.parse_directive(interp);
+                } else if self.peek() == Some(b'(') {
+                    self.next();
+                    self.parse_vector(interp)
+                } else if self.peek() == Some(b'u') {
+                    self.next();
+                    self.check_for(b'8')?;
+                    self.check_for(b'(')?;
+                    self.parse_bytevector(interp)
+                } else {
+                    self.parse_hash_body()
+                }
             },
             Some(b'"') => {
-                return self.parse_string(interp)
+                self.parse_string(interp)
+            },
+            Some(b'\'') => {
+                self.next();
+                self.parse_abbreviation(interp, Value::Object(Keyword::Quote as usize))
             },
-            Some(ch) if ch == b'\'' => {
+            Some(b'`') => {
                 self.next();
-                let quoted = self.read(interp)?;
-                let value = &[
-                    Value::Object(Keyword::Quote as usize), 
-                    quoted,
-                ];
-                Ok(interp.heap.borrow_mut().alloc_list(value))
+                self.parse_abbreviation(interp, Value::Object(Keyword::Quasiquote as usize))
+            },
+            Some(b',') => {
+                self.next();
+                if self.peek() == Some(b'@') {
+                    self.next();
+                    self.parse_abbreviation(interp, interp.lookup("unquote-splicing"))
+                } else {
+                    self.parse_abbreviation(interp, interp.lookup("unquote"))
+                }
             },
             Some(ch) => {
                 self.next();
@@ -310,7 +556,7 @@ impl<R: Read> Parser<R> {
                 ))
             },
             None => Ok(Value::Nil),
-        };
+        }.map(Some)
     }
 }
 
@@ -319,9 +565,10 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_parse_number() {
-        let inputs = vec!["42", "-3", "0", "3.14", "-0.001", "2e10", "-1.5E-3"];
-        let expected = vec![
+        let inputs = ["42", "-3", "0", "3.14", "-0.001", "2e10", "-1.5E-3"];
+        let expected = [
             Value::Number(Number::Int(42)),
             Value::Number(Number::Int(-3)),
             Value::Number(Number::Int(0)),              
@@ -337,6 +584,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_digit_separators() {
+        let ok_inputs = vec![
+            ("1_000", Value::Number(Number::Int(1000))),
+            ("-1_000_000", Value::Number(Number::Int(-1_000_000))),
+            ("0", Value::Number(Number::Int(0))),
+            ("1_0.2_5", Value::Number(Number::Float(10.25))),
+        ];
+        for (text, value) in ok_inputs {
+            let mut parser = Parser::new(text.as_bytes());
+            assert_eq!(Ok(value), parser.parse_number());
+        }
+        let mut parser = Parser::new("#xFF_FF".as_bytes());
+        assert_eq!(Ok(Value::Number(Number::Int(0xFFFF))), parser.parse_hash());
+
+        for text in ["1__0", "1_", "1_.5", "1._5"] {
+            let mut parser = Parser::new(text.as_bytes());
+            assert!(matches!(parser.parse_number(), Err(SchemeError::SyntaxError(_))), "{}", text);
+        }
+        for text in ["#x_FF", "#b1__0"] {
+            let mut parser = Parser::new(text.as_bytes());
+            assert!(matches!(parser.parse_hash(), Err(SchemeError::SyntaxError(_))), "{}", text);
+        }
+    }
+
     #[test]
     fn test_parse_hash() {
         let ok_inputs = vec![
@@ -354,6 +626,11 @@ mod tests {
             ("#\\return", Value::Char(13)),
             ("#\\space", Value::Char(32)),
             ("#\\A", Value::Char(65)),
+            ("#\\3", Value::Char(b'3')),
+            ("#\\(", Value::Char(b'(')),
+            ("#\\null", Value::Char(0)),
+            ("#\\delete", Value::Char(127)),
+            ("#\\x41", Value::Char(65)),
 
         ];
         for (text, value) in ok_inputs {
@@ -362,6 +639,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_object_literals() {
+        let interp = Interp::new();
+        for (text, value) in [
+            ("#!eof", Value::Eof),
+            ("#!default", Value::Unspecified),
+            ("#!unspecified", Value::Unspecified),
+        ] {
+            let mut parser = Parser::new(text.as_bytes());
+            assert_eq!(Ok(value), parser.read(&interp));
+            assert_eq!(interp.write(value), if value == Value::Eof { "#!eof" } else { "#!unspecified" });
+        }
+        let mut parser = Parser::new("#!whatever".as_bytes());
+        assert!(matches!(parser.read(&interp), Err(SchemeError::SyntaxError(_))));
+    }
+
     #[test]
     fn test_parse_symbol() {
         let interp = Interp::new();
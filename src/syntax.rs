@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+
+use crate::{heap::{HashKey, SyntaxRules}, interp::Interp, types::{GcId, SchemeError, Value}};
+
+// Expansion of syntax-rules macros. Expansions are not hygienic: the symbols
+// of a template are inserted as is, and refer to whatever they are bound to
+// where the macro is used, as the README warns.
+
+// What a pattern variable matched: a single form, or the bindings of each
+// form matched by the pattern it appears in under an ellipsis.
+#[derive(Clone)]
+enum Binding {
+    One(Value),
+    // The number of ellipses the variable is under, known even without items.
+    Many(usize, Vec<Binding>),
+}
+
+struct Expander<'a> {
+    interp: &'a Interp,
+    literals: &'a [GcId],
+    ellipsis: Value,
+    underscore: Value,
+}
+
+// Expands form, a use of the macro rules, once: later evaluations of the
+// same form reuse its expansion, even if the form was changed since.
+pub fn expand_use(interp: &Interp, rules: &SyntaxRules, form: Value) -> Result<Value, SchemeError> {
+    let key = HashKey::from(form);
+    if let Some(expansion) = interp.with_hash_table("syntax-rules", rules.expansions, |table| table.get(&key).copied())? {
+        return Ok(expansion);
+    }
+    let expansion = expand(interp, rules, form)?;
+    interp.with_hash_table("syntax-rules", rules.expansions, |table| table.insert(key, expansion))?;
+    Ok(expansion)
+}
+
+// Expands form, a use of the macro rules, with the first rule that matches.
+pub fn expand(interp: &Interp, rules: &SyntaxRules, form: Value) -> Result<Value, SchemeError> {
+    let expander = Expander {
+        interp,
+        literals: &rules.literals,
+        ellipsis: interp.lookup("..."),
+        underscore: interp.lookup("_"),
+    };
+    let (_, args) = interp.to_pair(form)?;
+    for (pattern, template) in rules.rules.iter() {
+        // The keyword position of the pattern is ignored.
+        let (_, pattern) = interp.to_pair(*pattern)?;
+        let mut bindings = HashMap::new();
+        if expander.matches(pattern, args, &mut bindings)? {
+            return expander.instantiate(*template, &bindings);
+        }
+    }
+    Err(SchemeError::SyntaxError(format!(
+        "No syntax-rules pattern matches {}.", interp.write(form)
+    )))
+}
+
+impl Expander<'_> {
+
+    fn is_literal(&self, value: Value) -> bool {
+        matches!(value, Value::Object(id) if self.literals.contains(&id))
+    }
+
+    fn is_variable(&self, value: Value) -> bool {
+        self.interp.to_symbol(value).is_ok()
+            && value != self.ellipsis && value != self.underscore && !self.is_literal(value)
+    }
+
+    // The pattern of a (pattern ... rest) list, and its rest.
+    fn split_ellipsis(&self, value: Value) -> Option<(Value, Value)> {
+        let (car, cdr) = self.interp.is_pair(value)?;
+        let (cadr, cddr) = self.interp.is_pair(cdr)?;
+        (cadr == self.ellipsis).then_some((car, cddr))
+    }
+
+    // The pattern of a (pattern ... ... rest) list, the number of ellipses
+    // following it and its rest.
+    fn split_ellipses(&self, value: Value) -> Option<(Value, usize, Value)> {
+        let (repeated, mut rest) = self.split_ellipsis(value)?;
+        let mut depth = 1;
+        while let Some((car, cdr)) = self.interp.is_pair(rest) && car == self.ellipsis {
+            depth += 1;
+            rest = cdr;
+        }
+        Some((repeated, depth, rest))
+    }
+
+    // The variables of pattern, with the number of ellipses each is under.
+    fn variables(&self, pattern: Value, depth: usize, vars: &mut Vec<(GcId, usize)>) -> Result<(), SchemeError> {
+        if self.is_variable(pattern) {
+            vars.push((self.interp.to_symbol(pattern).unwrap(), depth));
+        } else if let Some(items) = self.interp.is_vector(pattern).map(|items| items.to_vec()) {
+            let list = self.interp.heap.borrow_mut().alloc_list(&items)?;
+            self.variables(list, depth, vars)?;
+        } else if let Some((repeated, ellipses, rest)) = self.split_ellipses(pattern) {
+            self.variables(repeated, depth + ellipses, vars)?;
+            self.variables(rest, depth, vars)?;
+        } else if let Some((car, cdr)) = self.interp.is_pair(pattern) {
+            self.variables(car, depth, vars)?;
+            self.variables(cdr, depth, vars)?;
+        }
+        Ok(())
+    }
+
+    fn matches(&self, pattern: Value, form: Value, bindings: &mut HashMap<GcId, Binding>) -> Result<bool, SchemeError> {
+        let interp = self.interp;
+        if pattern == self.underscore {
+            return Ok(true);
+        }
+        if self.is_literal(pattern) {
+            return Ok(pattern == form);
+        }
+        if self.is_variable(pattern) {
+            bindings.insert(interp.to_symbol(pattern)?, Binding::One(form));
+            return Ok(true);
+        }
+        if let Some((repeated, rest)) = self.split_ellipsis(pattern) {
+            // The forms left over by the rest of the pattern match the repeated one.
+            let mut rest_len = 0;
+            let mut p = rest;
+            while let Some((_, cdr)) = interp.is_pair(p) {
+                rest_len += 1;
+                p = cdr;
+            }
+            let mut items = Vec::new();
+            let mut f = form;
+            while let Some((car, cdr)) = interp.is_pair(f) {
+                items.push(car);
+                f = cdr;
+            }
+            if items.len() < rest_len {
+                return Ok(false);
+            }
+            let mut vars = Vec::new();
+            self.variables(repeated, 0, &mut vars)?;
+            let mut matched = vars.iter()
+                .map(|(var, depth)| (*var, depth + 1, Vec::new()))
+                .collect::<Vec<_>>();
+            let mut f = form;
+            for _ in 0..items.len() - rest_len {
+                let (car, cdr) = interp.to_pair(f)?;
+                let mut item_bindings = HashMap::new();
+                if !self.matches(repeated, car, &mut item_bindings)? {
+                    return Ok(false);
+                }
+                for (var, _, items) in matched.iter_mut() {
+                    if let Some(binding) = item_bindings.remove(var) {
+                        items.push(binding);
+                    }
+                }
+                f = cdr;
+            }
+            bindings.extend(matched.into_iter().map(|(var, depth, items)| (var, Binding::Many(depth, items))));
+            return self.matches(rest, f, bindings);
+        }
+        if let Some((pattern_car, pattern_cdr)) = interp.is_pair(pattern) {
+            return match interp.is_pair(form) {
+                Some((car, cdr)) => Ok(self.matches(pattern_car, car, bindings)?
+                    && self.matches(pattern_cdr, cdr, bindings)?),
+                None => Ok(false),
+            };
+        }
+        if let Some(items) = interp.is_vector(pattern).map(|items| items.to_vec()) {
+            return match interp.is_vector(form).map(|forms| forms.to_vec()) {
+                Some(forms) => {
+                    let mut heap = interp.heap.borrow_mut();
+                    let (pattern, form) = (heap.alloc_list(&items)?, heap.alloc_list(&forms)?);
+                    drop(heap);
+                    self.matches(pattern, form, bindings)
+                },
+                None => Ok(false),
+            };
+        }
+        Ok(interp.is_equal(pattern, form))
+    }
+
+    fn instantiate(&self, template: Value, bindings: &HashMap<GcId, Binding>) -> Result<Value, SchemeError> {
+        let interp = self.interp;
+        if let Value::Object(id) = template
+            && let Some(binding) = bindings.get(&id) {
+            return match binding {
+                Binding::One(value) => Ok(*value),
+                Binding::Many(..) => Err(SchemeError::SyntaxError(format!(
+                    "syntax-rules: {} must be followed by an ellipsis.", interp.write(template)
+                ))),
+            };
+        }
+        if let Some((repeated, depth, rest)) = self.split_ellipses(template) {
+            let mut expansions = Vec::new();
+            self.instantiate_repeated(repeated, depth, bindings, &mut expansions)?;
+            let rest = self.instantiate(rest, bindings)?;
+            let mut heap = interp.heap.borrow_mut();
+            return expansions.iter().try_rfold(rest, |acc, item| heap.alloc_pair(*item, acc));
+        }
+        if let Some((car, cdr)) = interp.is_pair(template) {
+            let car = self.instantiate(car, bindings)?;
+            let cdr = self.instantiate(cdr, bindings)?;
+            return interp.heap.borrow_mut().alloc_pair(car, cdr);
+        }
+        if let Some(items) = interp.is_vector(template).map(|items| items.to_vec()) {
+            let list = interp.heap.borrow_mut().alloc_list(&items)?;
+            let items = interp.list_to_vec(self.instantiate(list, bindings)?)?;
+            return interp.heap.borrow_mut().alloc_vector(items);
+        }
+        Ok(template)
+    }
+
+    // Instantiates template once for each form matched under depth ellipses.
+    // Only the variables under more ellipses in the pattern than they are in
+    // template are iterated over, the others are the same for every item.
+    fn instantiate_repeated(&self, template: Value, depth: usize, bindings: &HashMap<GcId, Binding>, expansions: &mut Vec<Value>)
+        -> Result<(), SchemeError>
+    {
+        if depth == 0 {
+            expansions.push(self.instantiate(template, bindings)?);
+            return Ok(());
+        }
+        let mut vars = Vec::new();
+        self.variables(template, depth - 1, &mut vars)?;
+        let sequences = vars.iter()
+            .filter_map(|(var, var_depth)| match bindings.get(var) {
+                Some(Binding::Many(bound_depth, items)) if bound_depth > var_depth => Some((*var, items)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let Some(count) = sequences.first().map(|(_, items)| items.len()) else {
+            return Err(SchemeError::SyntaxError(format!(
+                "syntax-rules: no pattern variable to repeat in {}.", self.interp.write(template)
+            )));
+        };
+        if sequences.iter().any(|(_, items)| items.len() != count) {
+            return Err(SchemeError::SyntaxError(format!(
+                "syntax-rules: pattern variables of unequal lengths in {}.", self.interp.write(template)
+            )));
+        }
+        for i in 0..count {
+            let mut item_bindings = bindings.clone();
+            for (var, items) in sequences.iter() {
+                item_bindings.insert(*var, items[i].clone());
+            }
+            self.instantiate_repeated(template, depth - 1, &item_bindings, expansions)?;
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use crate::types::Number;
 
 #[test]
+#[allow(clippy::approx_constant)]
 fn test_eval_self_types() {
     use crate::interp::Interp;
     use crate::types::Value;
@@ -17,3 +18,41 @@ fn test_eval_self_types() {
     assert_eq!(interp.eval(bool_val).unwrap(), bool_val);
     assert_eq!(interp.eval(nil_val).unwrap(), nil_val);
 }
+
+#[test]
+fn test_truthiness() {
+    use crate::types::{SchemeObject, Value};
+
+    assert!(Value::Number(Number::Int(0)).is_truthy());
+    assert!(Value::Nil.is_truthy());
+    assert!(Value::Boolean(true).is_truthy());
+    assert!(Value::Boolean(false).is_false());
+    assert!(!Value::Boolean(false).is_truthy());
+    assert!(!Value::Nil.is_false());
+    assert!(!Value::Number(Number::Int(0)).is_false());
+}
+
+#[test]
+fn test_global_and_local_bindings() {
+    use std::rc::Rc;
+    use crate::env::Env;
+    use crate::types::Value;
+
+    let global = Rc::new(std::cell::RefCell::new(Env::new()));
+    global.borrow_mut().define(1000, Value::Number(Number::Int(1)));
+    assert_eq!(global.borrow().lookup(1000), Some(Value::Number(Number::Int(1))));
+    assert_eq!(global.borrow().lookup(999), None);
+    assert_eq!(global.borrow().lookup(5000), None);
+    assert!(global.borrow_mut().set_bang(999, Value::Nil).is_err());
+
+    let local = Env::extend(Rc::clone(&global));
+    local.borrow_mut().define(1000, Value::Number(Number::Int(2)));
+    assert_eq!(local.borrow().lookup(1000), Some(Value::Number(Number::Int(2))));
+    assert_eq!(global.borrow().lookup(1000), Some(Value::Number(Number::Int(1))));
+
+    // set! reaches through the local scope to the global table.
+    global.borrow_mut().define(7, Value::Nil);
+    local.borrow_mut().set_bang(7, Value::Boolean(true)).unwrap();
+    assert_eq!(global.borrow().lookup(7), Some(Value::Boolean(true)));
+    assert_eq!(global.borrow().bindings().len(), 2);
+}
@@ -1,5 +1,6 @@
 use crate::heap::Heap;
 use crate::interp::Interp;
+use crate::parser::Parser;
 use crate::types::SchemeError::UnboundVariable;
 use crate::types::{Number, Value};
 
@@ -18,10 +19,8 @@ fn test_intern_symbol() {
 #[test]
 fn test_eval_symbol() {
     let interp = Interp::new();
-    let mut heap = interp.heap.borrow_mut();
     // Creates an unbound symbol, and attempt to evaluate it.
-    let symbol = heap.intern_symbol("test-symbol");
-    drop(heap);
+    let symbol = interp.symbol("test-symbol");
 
     let result = interp.eval(symbol);
     assert!(matches!(result, Err(UnboundVariable(_))), "Evaluated result should be an UnboundVariable error");
@@ -35,9 +34,7 @@ fn test_eval_symbol() {
 #[test]
 fn test_eval_string() {
     let interp = Interp::new();
-    let mut heap = interp.heap.borrow_mut();
-    let string = heap.alloc_string("Hello, World!");
-    drop(heap);
+    let string = interp.string("Hello, World!").unwrap();
     let Value::Object(string_id) = string else {
         panic!("Expected Value::Object");
     };
@@ -48,12 +45,53 @@ fn test_eval_string() {
 #[test]
 fn test_true_and_false_symbols() {
     let interp = Interp::new();
-    let mut heap = interp.heap.borrow_mut();
 
-    let true_sym = heap.intern_symbol("#t");
-    let false_sym = heap.intern_symbol("#f");
-    drop(heap);
+    let true_sym = interp.symbol("#t");
+    let false_sym = interp.symbol("#f");
     
     assert!(matches!(interp.eval(true_sym), Ok(Value::Boolean(true))), "#t should evaluate to Boolean(true)");
     assert!(matches!(interp.eval(false_sym), Ok(Value::Boolean(false))), "#f should evaluate to Boolean(false)");  
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_quoted_constant_is_shared() {
+    let interp = Interp::new();
+    let text = "(define table (lambda () '((a . 1) (b . 2) (c . 3) (d . 4) (e . 5))))";
+    let expr = Parser::new(text.as_bytes()).read(&interp).unwrap();
+    interp.eval(expr).unwrap();
+    let call = Parser::new("(table)".as_bytes()).read(&interp).unwrap();
+
+    let first = interp.eval(call).unwrap();
+    let allocations = interp.heap.borrow().allocation_count();
+    for _ in 0..100 {
+        assert_eq!(interp.eval(call), Ok(first), "Quoted constant should be the same object");
+    }
+    assert_eq!(interp.heap.borrow().allocation_count(), allocations, "Calls should not allocate");
+}
+
+#[test]
+fn test_fold_list_allocates() {
+    let interp = Interp::new();
+    let list = interp.list(vec![
+        Value::Number(Number::Int(1)),
+        Value::Number(Number::Int(2)),
+        Value::Number(Number::Int(3)),
+    ]).unwrap();
+    // The callback allocates and mutates the heap while the list is walked.
+    let reversed = interp.fold_list_strict(list, Value::Nil, |acc, item| {
+        let pair = interp.heap.borrow_mut().alloc_pair(item, acc).unwrap();
+        Ok(pair)
+    }).unwrap();
+    let total = interp.fold_list(reversed, 0, |acc, item| {
+        interp.heap.borrow_mut().alloc_string("scratch").unwrap();
+        Ok(acc + interp.as_integer(item)?)
+    }).unwrap();
+    assert_eq!(total, 6);
+    assert_eq!(interp.to_pair(reversed).map(|(car, _)| car), Ok(Value::Number(Number::Int(3))));
+
+    // Same from Scheme, through fold calling an allocating procedure.
+    let text = "(fold (lambda (x acc) (cons (list x x) acc)) '() '(1 2 3))";
+    let expr = Parser::new(text.as_bytes()).read(&interp).unwrap();
+    let result = interp.eval(expr).unwrap();
+    assert_eq!(interp.list_to_vec(result).map(|items| items.len()), Ok(3));
+}
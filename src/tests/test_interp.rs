@@ -1,4 +1,6 @@
-use crate::{interp::Interp, parser::Parser, types::{Number, Value}};
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{interp::Interp, parser::Parser, types::{Number, SchemeError, Value}};
 
 
 fn eval_expr(interp: &Interp, expr: Value) {
@@ -13,7 +15,7 @@ fn eval_expr(interp: &Interp, expr: Value) {
 fn check_exprs(interp: &Interp, inputs: &Vec<(&str, Value)>) {
     for (text, expected) in inputs {
         let mut parser = Parser::new(text.as_bytes());
-        let expr = parser.read(&interp);
+        let expr = parser.read(interp);
         match expr {
             Ok(expr) => {
                 match interp.eval(expr) {
@@ -29,26 +31,23 @@ fn check_exprs(interp: &Interp, inputs: &Vec<(&str, Value)>) {
 #[test]
 fn test_cond() {
     let interp = Interp::new();
-    let cond = interp.lookup("if");
-    let tru = interp.lookup("#t");
-    let fls = interp.lookup("#f");
-
-    let mut heap = interp.heap.borrow_mut();
+    let cond = interp.symbol("if");
+    let tru = interp.symbol("#t");
+    let fls = interp.symbol("#f");
 
-    let cond_expr_true = heap.alloc_list(&[
+    let cond_expr_true = interp.list(vec![
         cond,
         tru,
         Value::Number(Number::Int(42)),
         Value::Number(Number::Int(0)),
-    ]);
+    ]).unwrap();
 
-    let cond_expr_false = heap.alloc_list(&[
+    let cond_expr_false = interp.list(vec![
         cond,
         fls,
         Value::Number(Number::Int(42)),
         Value::Number(Number::Int(0)),
-    ]);
-    drop(heap);
+    ]).unwrap();
 
     eval_expr(&interp, cond_expr_true);
     eval_expr(&interp, cond_expr_false);
@@ -58,48 +57,88 @@ fn test_cond() {
 fn test_nested_expr() {
     let interp = Interp::new();
     
-    let add = interp.lookup("+");
-    let mul = interp.lookup("*");
-    let mut heap = interp.heap.borrow_mut();
+    let add = interp.symbol("+");
+    let mul = interp.symbol("*");
 
-    let expr= heap.alloc_list(&[
+    let expr = interp.list(vec![
         mul,
         Value::Number(Number::Int(2)),
         Value::Number(Number::Int(3)),
-    ]);
+    ]).unwrap();
 
-    let list: Value = heap.alloc_list(&[
+    let list = interp.list(vec![
         add,
         expr,
         Value::Number(Number::Int(1)),
         Value::Number(Number::Int(2)),
-    ]);
-    drop(heap);
+    ]).unwrap();
 
     eval_expr(&interp, list);
 }
 
+#[test]
+fn test_builder_nested_expr() {
+    let interp = Interp::new();
+
+    // (car (cons (+ 1 2) "tail"))
+    let sum = interp.list(vec![
+        interp.symbol("+"),
+        Value::Number(Number::Int(1)),
+        Value::Number(Number::Int(2)),
+    ]).unwrap();
+    let cons = interp.list(vec![interp.symbol("cons"), sum, interp.string("tail").unwrap()]).unwrap();
+    let expr = interp.list(vec![interp.symbol("car"), cons]).unwrap();
+    assert_eq!(interp.eval(expr), Ok(Value::Number(Number::Int(3))));
+
+    // An improper pair built directly is quoted back as-is.
+    let pair = interp.pair(Value::Number(Number::Int(1)), Value::Number(Number::Int(2))).unwrap();
+    let quoted = interp.list(vec![interp.symbol("quote"), pair]).unwrap();
+    assert_eq!(interp.eval(quoted), Ok(pair));
+    assert_eq!(interp.display(pair), "(1 . 2)");
+}
+
 
 #[test]
 fn test_setbang_special_form() {
     let interp = Interp::new();
     
-    let define = interp.lookup("define");
-    let x = interp.lookup("x");
+    let define = interp.symbol("define");
+    let x = interp.symbol("x");
 
-    let mut heap = interp.heap.borrow_mut();
-
-    let expr= heap.alloc_list(&[
+    let expr = interp.list(vec![
         define,
         x,
         Value::Number(Number::Int(1))
-    ]);
-    drop(heap);
+    ]).unwrap();
     
     eval_expr(&interp, expr);
     eval_expr(&interp, x);
 }
 
+#[test]
+fn test_define_constant() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define-constant pi 2.5)");
+    eval_str(&interp, "(define area (lambda (r) (* pi r r)))");
+    eval_str(&interp, "(define bump-pi (lambda () (set! pi 3)))");
+    let inputs = vec![
+        ("pi", Value::Number(Number::Float(2.5))),
+        ("(area 1)", Value::Number(Number::Float(2.5))),
+        // Local bindings may still shadow a constant.
+        ("((lambda (pi) (set! pi 3) pi) 1)", Value::Number(Number::Int(3))),
+    ];
+    check_exprs(&interp, &inputs);
+
+    for text in ["(set! pi 3)", "(define pi 3)", "(bump-pi)", "(define-values (e pi) (values 1 2))", "(define-constant pi 3)"] {
+        assert_eq!(eval_err(&interp, text), SchemeError::EvalError("cannot reassign constant".to_string()), "{}", text);
+    }
+    assert_eq!(eval_str(&interp, "pi"), Value::Number(Number::Float(2.5)));
+    // The failed define-values bound none of its formals.
+    assert!(matches!(eval_err(&interp, "e"), SchemeError::UnboundVariable(_)));
+
+    assert!(matches!(eval_err(&interp, "(define-constant (f) 1)"), SchemeError::TypeError(_)));
+}
+
 #[test]
 fn test_read_eval_number() {
     let inputs = vec![
@@ -107,7 +146,7 @@ fn test_read_eval_number() {
         ("(- 1)",  Value::Number(Number::Int(-1))),
         ("(- 2 1)",  Value::Number(Number::Int(1))),
         ("(/ 2)",  Value::Number(Number::Float(0.5))),
-        ("(/ 4 2)",  Value::Number(Number::Float(2.0))),
+        ("(/ 4 2)",  Value::Number(Number::Int(2))),
         ("(% 10 3)",  Value::Number(Number::Int(1))),
         ("(= 10. 10.0)",  Value::Boolean(true)),
         ("(> 10 3)",  Value::Boolean(true)),
@@ -120,7 +159,7 @@ fn test_read_eval_number() {
         ("(integer? 1.0)",  Value::Boolean(false)),
         ("(float? 1.0)",  Value::Boolean(true)),
         ("(float? 1)",  Value::Boolean(false)),
-        ("(max 4 2.0 1)",  Value::Number(Number::Int(4))),
+        ("(max 4 2.0 1)",  Value::Number(Number::Float(4.0))),
         ("(min 4 2.0 7)",  Value::Number(Number::Float(2.0))),
     ];
     let interp = Interp::new();
@@ -135,6 +174,7 @@ fn test_read_eval_closure() {
         ("((lambda (x) (+ x 1)) 2)", Value::Number(Number::Int(3))),
         ("((lambda (x) (+ x 1)) 2)", Value::Number(Number::Int(3))),
         ("((lambda (x y) (+ x y)) 1 2)", Value::Number(Number::Int(3))),
+        ("(((lambda (x) (lambda (y) (+ x y))) 1) 2)", Value::Number(Number::Int(3))),
     ];
     let interp = Interp::new();
     check_exprs(&interp, &inputs);
@@ -160,6 +200,766 @@ fn test_read_eval_list() {
     check_exprs(&interp, &inputs);
 }
 
+#[test]
+fn test_read_eval_append_shares_tail() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define head (list 1 2))");
+    eval_str(&interp, "(define tail (list 3 4))");
+    eval_str(&interp, "(define joined (append head tail))");
+    let inputs = vec![
+        ("(eq? (cdr (cdr joined)) tail)", Value::Boolean(true)),
+        ("(eq? (append tail) tail)", Value::Boolean(true)),
+        ("(eq? (append '() '() tail) tail)", Value::Boolean(true)),
+        // The earlier lists are copied, mutating the result leaves them alone.
+        ("(eq? joined head)", Value::Boolean(false)),
+        ("(car (begin (set-car! joined 10) head))", Value::Number(Number::Int(1))),
+        // The last argument needs not be a list.
+        ("(cdr (append '(1) 2))", Value::Number(Number::Int(2))),
+    ];
+    check_exprs(&interp, &inputs);
+}
+
+
+#[test]
+fn test_expt() {
+    let interp = Interp::new();
+    let inputs = vec![
+        ("(expt 2 10)", Value::Number(Number::Int(1024))),
+        ("(expt -3 3)", Value::Number(Number::Int(-27))),
+        ("(expt 7 0)", Value::Number(Number::Int(1))),
+        ("(expt 0 0)", Value::Number(Number::Int(1))),
+        ("(expt 2 62)", Value::Number(Number::Int(1 << 62))),
+        ("(expt 2 -1)", Value::Number(Number::Float(0.5))),
+        ("(expt 4 -2)", Value::Number(Number::Float(0.0625))),
+        ("(expt 2.5 2)", Value::Number(Number::Float(6.25))),
+        ("(expt 2.0 0)", Value::Number(Number::Float(1.0))),
+        ("(expt 9 0.5)", Value::Number(Number::Float(3.0))),
+        ("(exact? (expt 3 4))", Value::Boolean(true)),
+        ("(exact? (expt 2 -1))", Value::Boolean(false)),
+    ];
+    check_exprs(&interp, &inputs);
+
+    assert!(matches!(eval_err(&interp, "(expt 2 64)"), SchemeError::OverflowError(_)));
+    assert!(matches!(eval_err(&interp, "(expt 0 -1)"), SchemeError::DivisionByZero(_)));
+}
+
+#[test]
+fn test_numeric_exactness() {
+    // Each primitive with exact-only operands, then with an inexact operand.
+    let inputs = vec![
+        ("(+ 1 2)", "(+ 1 2.0)", 3.0),
+        ("(- 5 2)", "(- 5.0 2)", 3.0),
+        ("(- 5)", "(- 5.0)", -5.0),
+        ("(* 2 3)", "(* 2 3.0)", 6.0),
+        ("(/ 6 3)", "(/ 6 3.0)", 2.0),
+        ("(/ 1)", "(/ 1.0)", 1.0),
+        ("(% 7 4)", "(% 7 4.0)", 3.0),
+        ("(max 1 3 2)", "(max 1 3 2.0)", 3.0),
+        ("(min 1 3 2)", "(min 1 3.0 2)", 1.0),
+        ("(abs -4)", "(abs -4.0)", 4.0),
+        ("(gcd 12 18)", "(gcd 12.0 18)", 6.0),
+        ("(lcm 4 6)", "(lcm 4 6.0)", 12.0),
+        ("(floor 2)", "(floor 2.5)", 2.0),
+        ("(ceiling 2)", "(ceiling 1.5)", 2.0),
+        ("(truncate -2)", "(truncate -2.7)", -2.0),
+        ("(round 2)", "(round 2.5)", 2.0),
+    ];
+    let interp = Interp::new();
+    for (exact, inexact, expected) in inputs {
+        let value = eval_str(&interp, exact);
+        assert!(matches!(value, Value::Number(Number::Int(_))), "{} is inexact", exact);
+        assert_eq!(value, Value::Number(Number::Float(expected)), "{}", exact);
+        let value = eval_str(&interp, inexact);
+        assert!(matches!(value, Value::Number(Number::Float(_))), "{} is exact", inexact);
+        assert_eq!(value, Value::Number(Number::Float(expected)), "{}", inexact);
+    }
+    // Dividing exact numbers is only exact when the division is.
+    assert_eq!(eval_str(&interp, "(/ 1 2)"), Value::Number(Number::Float(0.5)));
+    // Only an exact zero divisor is an error.
+    assert_eq!(eval_str(&interp, "(/ 1 0.0)"), Value::Number(Number::Float(f64::INFINITY)));
+    assert_eq!(eval_str(&interp, "(/ 0.0)"), Value::Number(Number::Float(f64::INFINITY)));
+    assert!(matches!(eval_str(&interp, "(/ 0.0 0.0)"), Value::Number(Number::Float(f)) if f.is_nan()));
+    for text in ["(/ 1 0)", "(/ 1.0 0)", "(/ 0)"] {
+        assert!(matches!(eval_err(&interp, text), SchemeError::DivisionByZero(_)), "{}", text);
+    }
+    let inputs = vec![
+        ("(exact? 1)", Value::Boolean(true)),
+        ("(exact? 1.0)", Value::Boolean(false)),
+        ("(inexact? 1.0)", Value::Boolean(true)),
+        ("(gcd)", Value::Number(Number::Int(0))),
+        ("(lcm)", Value::Number(Number::Int(1))),
+        ("(gcd -12 18)", Value::Number(Number::Int(6))),
+        ("(lcm 0 5)", Value::Number(Number::Int(0))),
+        ("(gcd -9223372036854775808 -1)", Value::Number(Number::Int(1))),
+        ("(gcd -9223372036854775808 6)", Value::Number(Number::Int(2))),
+    ];
+    check_exprs(&interp, &inputs);
+
+    assert!(matches!(eval_err(&interp, "(gcd 1.5 3)"), SchemeError::TypeError(_)));
+    // The magnitude of i64::MIN doesn't fit back in an i64.
+    for text in ["(gcd -9223372036854775808)", "(lcm -9223372036854775808 1)"] {
+        assert!(matches!(eval_err(&interp, text), SchemeError::OverflowError(_)), "{}", text);
+    }
+}
+
+#[test]
+fn test_rounding() {
+    let inputs = vec![
+        ("(floor -2.5)", Value::Number(Number::Float(-3.0))),
+        ("(ceiling -2.5)", Value::Number(Number::Float(-2.0))),
+        ("(truncate 2.7)", Value::Number(Number::Float(2.0))),
+        ("(round 3.5)", Value::Number(Number::Float(4.0))),
+        ("(round -2.5)", Value::Number(Number::Float(-2.0))),
+        ("(round 2.6)", Value::Number(Number::Float(3.0))),
+        ("(exact? (floor 2.5))", Value::Boolean(false)),
+        ("(exact? (round 7))", Value::Boolean(true)),
+        ("(exact-nonnegative-integer? 0)", Value::Boolean(true)),
+        ("(exact-nonnegative-integer? 5)", Value::Boolean(true)),
+        ("(exact-nonnegative-integer? -1)", Value::Boolean(false)),
+        ("(exact-nonnegative-integer? 5.0)", Value::Boolean(false)),
+        ("(exact-nonnegative-integer? 'a)", Value::Boolean(false)),
+    ];
+    let interp = Interp::new();
+    check_exprs(&interp, &inputs);
+
+    assert!(matches!(eval_err(&interp, "(round 'a)"), SchemeError::TypeError(_)));
+}
+
+#[test]
+fn test_mutable_strings() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define s (make-string 3 #\\a))");
+    eval_str(&interp, "(string-set! s 1 #\\b)");
+    eval_str(&interp, "(define t (string-copy s))");
+    eval_str(&interp, "(string-set! t 0 #\\z)");
+    let inputs = vec![
+        ("(string-length s)", Value::Number(Number::Int(3))),
+        ("(string-ref s 0)", Value::Char(b'a')),
+        ("(string-ref s 1)", Value::Char(b'b')),
+        ("(equal? s \"aba\")", Value::Boolean(true)),
+        // The copy is independent of the original.
+        ("(equal? t \"zba\")", Value::Boolean(true)),
+        ("(string-ref s 0)", Value::Char(b'a')),
+        ("(equal? (string-copy \"hello\" 1 3) \"el\")", Value::Boolean(true)),
+        ("(equal? (string-copy \"hello\" 2) \"llo\")", Value::Boolean(true)),
+        ("(equal? (make-string 2) \"  \")", Value::Boolean(true)),
+        ("(string-length (make-string 0 #\\x))", Value::Number(Number::Int(0))),
+    ];
+    check_exprs(&interp, &inputs);
+
+    eval_str(&interp, "(string-fill! s #\\c)");
+    assert_eq!(eval_str(&interp, "(equal? s \"ccc\")"), Value::Boolean(true));
+
+    for text in ["(string-set! s 3 #\\a)", "(string-set! s -1 #\\a)", "(string-ref s 3)", "(string-copy s 2 4)"] {
+        assert!(matches!(eval_err(&interp, text), SchemeError::EvalError(_)), "{}", text);
+    }
+    assert!(matches!(eval_err(&interp, "(string-set! s 0 1)"), SchemeError::TypeError(_)));
+    assert_eq!(eval_err(&interp, "(make-string 100000000000 #\\a)"), SchemeError::EvalError(
+        "make-string: length 100000000000 is too long.".to_string()
+    ));
+}
+
+#[test]
+fn test_parameters() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define width (make-parameter 10))");
+    eval_str(&interp, "(define doubled (make-parameter 2 (lambda (x) (* x 2))))");
+    eval_str(&interp, "(define show-width (lambda () (width)))");
+    let inputs = vec![
+        ("(width)", Value::Number(Number::Int(10))),
+        ("(doubled)", Value::Number(Number::Int(4))),
+        ("(parameterize ((width 20)) (show-width))", Value::Number(Number::Int(20))),
+        ("(parameterize ((doubled 5)) (doubled))", Value::Number(Number::Int(10))),
+        ("(parameterize ((width 1)) (parameterize ((width 2)) (width)))", Value::Number(Number::Int(2))),
+        ("(width)", Value::Number(Number::Int(10))),
+        ("(procedure? width)", Value::Boolean(true)),
+    ];
+    check_exprs(&interp, &inputs);
+
+    // The old value is restored when the body raises an error.
+    eval_err(&interp, "(parameterize ((width 30)) (car 1))");
+    assert_eq!(eval_str(&interp, "(width)"), Value::Number(Number::Int(10)));
+}
+
+#[test]
+fn test_fluid_let() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define depth 1)");
+    eval_str(&interp, "(define name 'outer)");
+    eval_str(&interp, "(define show (lambda () (list depth name)))");
+    let inputs = vec![
+        // Unlike a new binding, the assignment is seen by the procedures called.
+        ("(equal? (fluid-let ((depth 2) (name 'inner)) (show)) '(2 inner))", Value::Boolean(true)),
+        ("(equal? (show) '(1 outer))", Value::Boolean(true)),
+        ("(fluid-let ((depth (+ depth 1))) (fluid-let ((depth (* depth 10))) depth))", Value::Number(Number::Int(20))),
+        ("depth", Value::Number(Number::Int(1))),
+        ("(fluid-let ((depth 5)) (set! depth 6) depth)", Value::Number(Number::Int(6))),
+        ("depth", Value::Number(Number::Int(1))),
+        // Escaping with a continuation restores the value too.
+        ("(call/cc (lambda (k) (fluid-let ((depth 7)) (k depth))))", Value::Number(Number::Int(7))),
+        ("depth", Value::Number(Number::Int(1))),
+        ("(guard (e (#t depth)) (fluid-let ((depth 8)) (raise 'oops)))", Value::Number(Number::Int(1))),
+    ];
+    check_exprs(&interp, &inputs);
+
+    // The old value is restored when the body raises an error.
+    eval_err(&interp, "(fluid-let ((depth 30)) (car 1))");
+    assert_eq!(eval_str(&interp, "depth"), Value::Number(Number::Int(1)));
+
+    // Only existing variables can be assigned, constants included.
+    assert!(matches!(eval_err(&interp, "(fluid-let ((unknown 1)) unknown)"), SchemeError::UnboundVariable(_)));
+    eval_str(&interp, "(define-constant limit 10)");
+    assert_eq!(eval_err(&interp, "(fluid-let ((depth 2) (limit 20)) limit)"), SchemeError::EvalError("cannot reassign constant".to_string()));
+    assert_eq!(eval_str(&interp, "depth"), Value::Number(Number::Int(1)));
+}
+
+#[test]
+fn test_float_print_precision() {
+    let interp = Interp::new();
+    let text = |expr: &str| {
+        let mut buf = String::new();
+        assert!(interp.is_string(eval_str(&interp, expr), &mut buf));
+        buf
+    };
+    assert_eq!(text("(number->string 3.14159)"), "3.14159");
+    assert_eq!(text("(parameterize ((float-print-precision 2)) (number->string 3.14159))"), "3.14");
+    assert_eq!(text("(parameterize ((float-print-precision 0)) (number->string 2.5))"), "2");
+    // Integers are unaffected, and the default is restored on exit.
+    assert_eq!(text("(parameterize ((float-print-precision 2)) (number->string 42))"), "42");
+    assert_eq!(text("(number->string 3.14159)"), "3.14159");
+    assert_eq!(text("(number->string 255 16)"), "ff");
+    assert_eq!(text("(number->string -5 2)"), "-101");
+
+    eval_str(&interp, "(define port (open-output-string))");
+    eval_str(&interp, "(parameterize ((float-print-precision 3)) (display 1.5 port))");
+    eval_str(&interp, "(display 1.5 port)");
+    assert_eq!(text("(get-output-string port)"), "1.5001.5");
+
+    assert!(matches!(eval_err(&interp, "(parameterize ((float-print-precision 'x)) 1)"), SchemeError::TypeError(_)));
+}
+
+#[test]
+fn test_improper_lists() {
+    let interp = Interp::new();
+    let inputs = vec![
+        ("(length '(1 2 3))", Value::Number(Number::Int(3))),
+        ("(length '())", Value::Number(Number::Int(0))),
+        ("(cdr (cdr '(1 2 . 3)))", Value::Number(Number::Int(3))),
+    ];
+    check_exprs(&interp, &inputs);
+
+    for text in [
+        "(length '(1 2 . 3))",
+        "(length 5)",
+        "(map (lambda (x) x) '(1 2 . 3))",
+        "(for-each (lambda (x) x) '(1 . 2))",
+        "(apply + '(1 2 . 3))",
+        "(fold + 0 '(1 2 . 3))",
+        "(filter (lambda (x) #t) '(1 . 2))",
+        "(list->vector '(1 . 2))",
+        "(+ 1 . 2)",
+    ] {
+        assert!(matches!(eval_err(&interp, text), SchemeError::TypeError(_)), "{}", text);
+    }
+}
+
+#[test]
+fn test_length_plus() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define odd (list 1 2 3))");
+    eval_str(&interp, "(set-cdr! (cdr (cdr odd)) odd)");
+    eval_str(&interp, "(define even (list 1 2))");
+    eval_str(&interp, "(set-cdr! (cdr even) even)");
+    let inputs = vec![
+        ("(length+ '())", Value::Number(Number::Int(0))),
+        ("(length+ '(1))", Value::Number(Number::Int(1))),
+        ("(length+ '(1 2 3))", Value::Number(Number::Int(3))),
+        ("(length+ '(1 2 . 3))", Value::Boolean(false)),
+        ("(length+ 5)", Value::Boolean(false)),
+        ("(length+ odd)", Value::Boolean(false)),
+        ("(length+ even)", Value::Boolean(false)),
+        ("(list? odd)", Value::Boolean(false)),
+    ];
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_max_min_by() {
+    let interp = Interp::new();
+    let inputs = vec![
+        ("(max-by (lambda (x) (- x)) '(3 1 2))", Value::Number(Number::Int(1))),
+        ("(min-by (lambda (x) (- x)) '(3 1 2))", Value::Number(Number::Int(3))),
+        ("(car (max-by cdr '((a . 1) (b . 5) (c . 5))))", interp.symbol("b")),
+        ("(car (min-by cdr '((a . 2) (b . 1) (c . 1))))", interp.symbol("b")),
+        ("(equal? (max-by string-length '(\"a\" \"abc\" \"ab\")) \"abc\")", Value::Boolean(true)),
+        ("(equal? (min-by string-length '(\"ab\" \"abc\" \"a\")) \"a\")", Value::Boolean(true)),
+    ];
+    check_exprs(&interp, &inputs);
+
+    assert!(matches!(eval_err(&interp, "(max-by string-length '())"), SchemeError::EvalError(_)));
+    assert!(matches!(eval_err(&interp, "(min-by car '((a) (b)))"), SchemeError::TypeError(_)));
+}
+
+#[test]
+fn test_list_p() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define cycle (list 1 2 3))");
+    eval_str(&interp, "(set-cdr! (cdr (cdr cycle)) cycle)");
+    eval_str(&interp, "(define loop (list 1))");
+    eval_str(&interp, "(set-cdr! loop loop)");
+    let inputs = vec![
+        ("(list? '())", Value::Boolean(true)),
+        ("(list? '(1))", Value::Boolean(true)),
+        ("(list? '(1 2 3 4))", Value::Boolean(true)),
+        ("(list? (cons 1 2))", Value::Boolean(false)),
+        ("(list? '(1 2 . 3))", Value::Boolean(false)),
+        ("(list? 1)", Value::Boolean(false)),
+        ("(list? cycle)", Value::Boolean(false)),
+        ("(list? loop)", Value::Boolean(false)),
+        ("(car (cdr (cdr (cdr cycle))))", Value::Number(Number::Int(1))),
+    ];
+    check_exprs(&interp, &inputs);
+
+    eval_str(&interp, "(define pair (cons 1 2))");
+    eval_str(&interp, "(set-car! pair 'a)");
+    assert_eq!(eval_str(&interp, "(car pair)"), interp.symbol("a"));
+}
+
+#[test]
+fn test_write_cycles() {
+    let interp = Interp::new();
+    let display = |text: &str| interp.display(eval_str(&interp, text));
+    eval_str(&interp, "(define p (cons 1 2))");
+    eval_str(&interp, "(set-cdr! p p)");
+    assert_eq!(display("p"), "#0=(1 . #0#)");
+
+    eval_str(&interp, "(define l (list 1 2 3))");
+    eval_str(&interp, "(set-cdr! (cdr (cdr l)) (cdr l))");
+    assert_eq!(display("l"), "(1 . #0=(2 3 . #0#))");
+
+    eval_str(&interp, "(define c (list 1 2))");
+    eval_str(&interp, "(set-car! c c)");
+    assert_eq!(display("c"), "#0=(#0# 2)");
+
+    eval_str(&interp, "(define v (vector 1 (list 2)))");
+    eval_str(&interp, "(set-car! (vector-ref v 1) v)");
+    assert_eq!(display("v"), "#0=#(1 (#0#))");
+
+    // Shared structure without a cycle is written out in full.
+    eval_str(&interp, "(define x (list 1))");
+    assert_eq!(display("(list x x)"), "((1) (1))");
+    assert_eq!(display("'(1 (2 #(3)) . 4)"), "(1 (2 #(3)) . 4)");
+}
+
+#[test]
+fn test_equal_cycles() {
+    let interp = Interp::new();
+    let cycle = |name: &str, items: &str| {
+        eval_str(&interp, &format!("(define {} (list {}))", name, items));
+        eval_str(&interp, &format!("(set-cdr! (list-tail-of {}) {})", name, name));
+    };
+    eval_str(&interp, "(define list-tail-of (lambda (l) (if (null? (cdr l)) l (list-tail-of (cdr l)))))");
+    cycle("a", "1 2");
+    cycle("b", "1 2");
+    cycle("c", "1 2 1 2");
+    cycle("d", "1 3");
+    eval_str(&interp, "(define v (vector 1 (list 0)))");
+    eval_str(&interp, "(set-car! (vector-ref v 1) v)");
+    eval_str(&interp, "(define w (vector 1 (list 0)))");
+    eval_str(&interp, "(set-car! (vector-ref w 1) w)");
+    eval_str(&interp, "(define self (list 1))");
+    eval_str(&interp, "(set-car! self self)");
+    eval_str(&interp, "(define other (list 1))");
+    eval_str(&interp, "(set-car! other other)");
+    let inputs = vec![
+        ("(equal? a b)", Value::Boolean(true)),
+        ("(equal? a c)", Value::Boolean(true)),
+        ("(equal? a d)", Value::Boolean(false)),
+        ("(equal? a '(1 2 1 2))", Value::Boolean(false)),
+        ("(equal? self other)", Value::Boolean(true)),
+        ("(equal? self a)", Value::Boolean(false)),
+        ("(equal? v w)", Value::Boolean(true)),
+        ("(equal? v (vector 1 (list w)))", Value::Boolean(true)),
+        ("(equal? v (vector 2 (list w)))", Value::Boolean(false)),
+    ];
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_write_shared_and_simple() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define x (list 1 2))");
+    eval_str(&interp, "(define dag (list x x))");
+    assert_eq!(interp.write_shared(eval_str(&interp, "dag")), "(#0=(1 2) #0#)");
+    assert_eq!(interp.write(eval_str(&interp, "dag")), "((1 2) (1 2))");
+    assert_eq!(interp.write_simple(eval_str(&interp, "dag")), "((1 2) (1 2))");
+    // A shared tail is labelled where it starts.
+    assert_eq!(interp.write_shared(eval_str(&interp, "(list x (cons 0 x))")), "(#0=(1 2) (0 . #0#))");
+    assert_eq!(interp.write_shared(eval_str(&interp, "(vector x \"s\" x)")), "#(#0=(1 2) \"s\" #0#)");
+    assert_eq!(interp.write_shared(eval_str(&interp, "'(1 (2 3))")), "(1 (2 3))");
+
+    eval_str(&interp, "(define c (list 1 2))");
+    eval_str(&interp, "(set-cdr! (cdr c) c)");
+    assert_eq!(interp.write_shared(eval_str(&interp, "c")), "#0=(1 2 . #0#)");
+
+    eval_str(&interp, "(define port (open-output-string))");
+    eval_str(&interp, "(write-shared dag port)");
+    eval_str(&interp, "(write-simple dag port)");
+    let mut buf = String::new();
+    assert!(interp.is_string(eval_str(&interp, "(get-output-string port)"), &mut buf));
+    assert_eq!(buf, "(#0=(1 2) #0#)((1 2) (1 2))");
+}
+
+#[test]
+fn test_primitive_error_messages() {
+    let interp = Interp::new();
+    let error = |text: &str| {
+        eval_err(&interp, text)
+    };
+    let type_errors = vec![
+        ("(char-upcase 1)", "char-upcase: expected Char as argument 1"),
+        ("(char<? #\\a 2)", "char<?: expected Char as argument 2"),
+        ("(< 1 'a)", "<: expected Number as argument 2"),
+        ("(+ 1 2 \"x\")", "+: expected Number as argument 3"),
+        ("(max #\\a)", "max: expected Number as argument 1"),
+        ("(string-prefix? 1 \"a\")", "string-prefix?: expected String as argument 1"),
+        ("(string-set! (make-string 1) 0 1)", "string-set!: expected ASCII Char as argument 3"),
+        ("(set-profiling! 1)", "set-profiling!: expected Boolean as argument 1"),
+        ("(car 1)", "car: expected Pair as argument 1"),
+        ("(set-cdr! (vector 1) 2)", "set-cdr!: expected Pair as argument 1"),
+        ("(string-length 1)", "string-length: expected String as argument 1"),
+        ("(string-fill! 'a #\\b)", "string-fill!: expected String as argument 1"),
+        ("(string-copy \"abc\" 0 'x)", "string-copy: expected Int as argument 3"),
+        ("(vector-ref (vector 1) 1.0)", "vector-ref: expected Int as argument 2"),
+        ("(vector-length '(1))", "vector-length: expected Vector as argument 1"),
+        ("(number->string \"a\")", "number->string: expected Number as argument 1"),
+        ("(condition-message 1)", "condition-message: expected Condition as argument 1"),
+        ("(read-u8 (open-input-string \"a\"))", "read-u8: expected InputBytevector as argument 1"),
+    ];
+    for (text, message) in type_errors {
+        assert_eq!(error(text), SchemeError::TypeError(message.to_string()), "{}", text);
+    }
+    let count_errors = vec![
+        ("(car '(1) '(2))", "car: expected 1 arg, but got 2"),
+        ("(cons 1)", "cons: expected 2 args, but got 1"),
+        ("(char-upcase)", "char-upcase: expected 1 arg, but got 0"),
+        ("(hash-ref)", "hash-ref: expected 2 to 3 args, but got 0"),
+        ("(-)", "-: expected at least 1 arg, but got 0"),
+        ("(map car)", "map: expected at least 2 args, but got 1"),
+        ("(make-string)", "make-string: expected 1 to 2 args, but got 0"),
+    ];
+    for (text, message) in count_errors {
+        assert_eq!(error(text), SchemeError::ArgCountError(message.to_string()), "{}", text);
+    }
+}
+
+#[test]
+fn test_nested_evaluation_borrows() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define table (make-hash-table))");
+    eval_str(&interp, "(define port (open-output-string))");
+    let inputs = vec![
+        ("(car (list (+ 1 2)))", Value::Number(Number::Int(3))),
+        ("(car (cdr (list 1 (car (list (+ 1 2))))))", Value::Number(Number::Int(3))),
+        ("(vector-ref (vector (car (list (* 2 3)))) 0)", Value::Number(Number::Int(6))),
+        ("(length (map (lambda (x) (list x (hash-set! table x (list x)))) '(1 2 3)))", Value::Number(Number::Int(3))),
+        ("(hash-table-fold table (lambda (k v acc) (+ (car v) acc)) 0)", Value::Number(Number::Int(6))),
+        ("(car (sort (list 3 1 2) (lambda (a b) (< (car (list a)) (car (list b))))))", Value::Number(Number::Int(1))),
+        ("(force (delay (car (list (+ 1 2)))))", Value::Number(Number::Int(3))),
+        ("((make-parameter 1 (lambda (x) (car (list x)))))", Value::Number(Number::Int(1))),
+    ];
+    check_exprs(&interp, &inputs);
+
+    eval_str(&interp, "(for-each (lambda (x) (write (list x (vector x)) port)) '(1 2))");
+    let mut buf = String::new();
+    assert!(interp.is_string(eval_str(&interp, "(get-output-string port)"), &mut buf));
+    assert_eq!(buf, "(1 #(1))(2 #(2))");
+}
+
+#[test]
+fn test_integer_division() {
+    let interp = Interp::new();
+    let inputs = vec![
+        ("(truncate-quotient 7 2)", Value::Number(Number::Int(3))),
+        ("(truncate-remainder 7 2)", Value::Number(Number::Int(1))),
+        ("(floor-quotient 7 2)", Value::Number(Number::Int(3))),
+        ("(floor-remainder 7 2)", Value::Number(Number::Int(1))),
+        ("(euclidean-quotient 7 2)", Value::Number(Number::Int(3))),
+        ("(euclidean-remainder 7 2)", Value::Number(Number::Int(1))),
+        ("(truncate-quotient -7 2)", Value::Number(Number::Int(-3))),
+        ("(truncate-remainder -7 2)", Value::Number(Number::Int(-1))),
+        ("(floor-quotient -7 2)", Value::Number(Number::Int(-4))),
+        ("(floor-remainder -7 2)", Value::Number(Number::Int(1))),
+        ("(euclidean-quotient -7 2)", Value::Number(Number::Int(-4))),
+        ("(euclidean-remainder -7 2)", Value::Number(Number::Int(1))),
+        ("(truncate-quotient 7 -2)", Value::Number(Number::Int(-3))),
+        ("(truncate-remainder 7 -2)", Value::Number(Number::Int(1))),
+        ("(floor-quotient 7 -2)", Value::Number(Number::Int(-4))),
+        ("(floor-remainder 7 -2)", Value::Number(Number::Int(-1))),
+        ("(euclidean-quotient 7 -2)", Value::Number(Number::Int(-3))),
+        ("(euclidean-remainder 7 -2)", Value::Number(Number::Int(1))),
+        ("(truncate-quotient -7 -2)", Value::Number(Number::Int(3))),
+        ("(truncate-remainder -7 -2)", Value::Number(Number::Int(-1))),
+        ("(floor-quotient -7 -2)", Value::Number(Number::Int(3))),
+        ("(floor-remainder -7 -2)", Value::Number(Number::Int(-1))),
+        ("(euclidean-quotient -7 -2)", Value::Number(Number::Int(4))),
+        ("(euclidean-remainder -7 -2)", Value::Number(Number::Int(1))),
+        ("(truncate-quotient 6 3)", Value::Number(Number::Int(2))),
+        ("(truncate-remainder 6 3)", Value::Number(Number::Int(0))),
+        ("(floor-quotient 6 3)", Value::Number(Number::Int(2))),
+        ("(floor-remainder 6 3)", Value::Number(Number::Int(0))),
+        ("(euclidean-quotient 6 3)", Value::Number(Number::Int(2))),
+        ("(euclidean-remainder 6 3)", Value::Number(Number::Int(0))),
+        ("(truncate-quotient -6 3)", Value::Number(Number::Int(-2))),
+        ("(truncate-remainder -6 3)", Value::Number(Number::Int(0))),
+        ("(floor-quotient -6 3)", Value::Number(Number::Int(-2))),
+        ("(floor-remainder -6 3)", Value::Number(Number::Int(0))),
+        ("(euclidean-quotient -6 3)", Value::Number(Number::Int(-2))),
+        ("(euclidean-remainder -6 3)", Value::Number(Number::Int(0))),
+        ("(quotient -7 2)", Value::Number(Number::Int(-3))),
+        ("(remainder -7 2)", Value::Number(Number::Int(-1))),
+        ("(modulo -7 2)", Value::Number(Number::Int(1))),
+        ("(equal? (call-with-values (lambda () (floor/ -7 2)) list) '(-4 1))", Value::Boolean(true)),
+        ("(equal? (call-with-values (lambda () (truncate/ -7 2)) list) '(-3 -1))", Value::Boolean(true)),
+        ("(equal? (call-with-values (lambda () (euclidean/ -7 -2)) list) '(4 1))", Value::Boolean(true)),
+        ("(floor-remainder -7.0 2)", Value::Number(Number::Float(1.0))),
+    ];
+    check_exprs(&interp, &inputs);
+    assert!(matches!(eval_str(&interp, "(floor-remainder -7.0 2)"), Value::Number(Number::Float(_))));
+
+    for text in ["(modulo 1 0)", "(euclidean/ 1 0)"] {
+        assert!(matches!(eval_err(&interp, text), SchemeError::DivisionByZero(_)), "{}", text);
+    }
+    assert!(matches!(eval_err(&interp, "(quotient 1.5 1)"), SchemeError::TypeError(_)));
+}
+
+#[test]
+fn test_approx_eq() {
+    let inputs = vec![
+        ("(approx=? 1.0 1.05 0.1)", Value::Boolean(true)),
+        ("(approx=? 1.0 1.2 0.1)", Value::Boolean(false)),
+        ("(approx=? 2 2.5 0.5)", Value::Boolean(true)),
+        ("(approx=? (+ 0.1 0.2) 0.3)", Value::Boolean(true)),
+        ("(= (+ 0.1 0.2) 0.3)", Value::Boolean(false)),
+        ("(approx=? 0.3 0.3001)", Value::Boolean(false)),
+    ];
+    let interp = Interp::new();
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_list_index_find_tail() {
+    let inputs = vec![
+        ("(list-index even? '(1 3 5 6))", Value::Number(Number::Int(3))),
+        ("(list-index even? '(1 3 5))", Value::Boolean(false)),
+        ("(list-index < '(3 2 1) '(1 2 3))", Value::Number(Number::Int(2))),
+        ("(list-index = '(1 2) '(3 4 2))", Value::Boolean(false)),
+        ("(equal? (find-tail odd? '(2 4 5 6)) '(5 6))", Value::Boolean(true)),
+        ("(find-tail odd? '(2 4 6))", Value::Boolean(false)),
+        ("(find odd? '(2 4 5 7))", Value::Number(Number::Int(5))),
+        ("(find odd? '())", Value::Boolean(false)),
+        ("(even? 0)", Value::Boolean(true)),
+        ("(odd? -3)", Value::Boolean(true)),
+        ("(even? 4.0)", Value::Boolean(true)),
+    ];
+    let interp = Interp::new();
+    check_exprs(&interp, &inputs);
+
+    // find-tail shares structure with its argument.
+    eval_str(&interp, "(define l (list 1 2 3))");
+    assert_eq!(eval_str(&interp, "(eq? (find-tail even? l) (cdr l))"), Value::Boolean(true));
+}
+
+#[test]
+fn test_delete_duplicates() {
+    let inputs = vec![
+        ("(equal? (delete-duplicates '(1 2 1 3 2)) '(1 2 3))", Value::Boolean(true)),
+        ("(equal? (delete-duplicates '()) '())", Value::Boolean(true)),
+        ("(equal? (delete-duplicates '((a 1) (b 2) (a 1))) '((a 1) (b 2)))", Value::Boolean(true)),
+        ("(equal? (delete-duplicates '(\"x\" \"y\" \"x\")) '(\"x\" \"y\"))", Value::Boolean(true)),
+        // The first of the elements considered the same is kept.
+        ("(equal? (delete-duplicates '((a . 1) (b . 2) (a . 3)) (lambda (x y) (eq? (car x) (car y)))) '((a . 1) (b . 2)))", Value::Boolean(true)),
+        ("(equal? (delete-duplicates '(1 2 3 4 5) (lambda (x y) (= (% x 2) (% y 2)))) '(1 2))", Value::Boolean(true)),
+    ];
+    let interp = Interp::new();
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_zip_unzip() {
+    let inputs = vec![
+        ("(equal? (zip '(1 2) '(a b)) '((1 a) (2 b)))", Value::Boolean(true)),
+        ("(equal? (zip '(1 2 3) '(a b) '(x y z)) '((1 a x) (2 b y)))", Value::Boolean(true)),
+        ("(equal? (zip '(1 2)) '((1) (2)))", Value::Boolean(true)),
+        ("(equal? (zip '() '(a)) '())", Value::Boolean(true)),
+        ("(equal? (unzip1 '((1 a) (2 b))) '(1 2))", Value::Boolean(true)),
+        ("(equal? (unzip '((1 a) (2 b))) '((1 2) (a b)))", Value::Boolean(true)),
+        ("(equal? (unzip (zip '(1 2 3) '(a b c))) '((1 2 3) (a b c)))", Value::Boolean(true)),
+        ("(equal? (apply zip (unzip '((1 a) (2 b)))) '((1 a) (2 b)))", Value::Boolean(true)),
+        ("(equal? (unzip '()) '())", Value::Boolean(true)),
+    ];
+    let interp = Interp::new();
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_alist_update_del_assq() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define al '((a . 1) (b . 2) (c . 3)))");
+    let inputs = vec![
+        ("(equal? (alist-update 'b 20 al) '((a . 1) (b . 20) (c . 3)))", Value::Boolean(true)),
+        ("(equal? (alist-update 'd 4 al) '((d . 4) (a . 1) (b . 2) (c . 3)))", Value::Boolean(true)),
+        ("(equal? (alist-update \"k\" 2 '((\"k\" . 1))) '((\"k\" . 2)))", Value::Boolean(true)),
+        ("(equal? (del-assq 'b al) '((a . 1) (c . 3)))", Value::Boolean(true)),
+        ("(equal? (del-assq 'a '((a . 1) (b . 2) (a . 3))) '((b . 2)))", Value::Boolean(true)),
+        ("(equal? (del-assq 'z al) al)", Value::Boolean(true)),
+        // The original alist is left untouched.
+        ("(equal? al '((a . 1) (b . 2) (c . 3)))", Value::Boolean(true)),
+    ];
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_member_assoc() {
+    let interp = Interp::new();
+    let inputs = vec![
+        // = compares numerically, eqv? and the procedures using it also compare exactness.
+        ("(= 2 2.0)", "#t"),
+        ("(eqv? 2 2.0)", "#f"),
+        ("(eqv? 2 2)", "#t"),
+        ("(eqv? 2.5 2.5)", "#t"),
+        ("(eqv? 0.0 -0.0)", "#f"),
+        ("(eq? 2 2.0)", "#f"),
+        ("(eq? 2 2)", "#t"),
+        ("(equal? '(1 2) '(1 2.0))", "#f"),
+        ("(memv 2.0 '(1 2 3))", "#f"),
+        ("(memv 2 '(1 2 3))", "(2 3)"),
+        ("(memv 2.0 '(1 2 2.0 3))", "(2.0 3)"),
+        ("(assv 2.0 '((2 . exact) (2.0 . inexact)))", "(2.0 . inexact)"),
+        ("(assv 2 '((2.0 . inexact) (2 . exact)))", "(2 . exact)"),
+        ("(assv 5 '((1 . a)))", "#f"),
+        ("(memq 'c '(a b c d))", "(c d)"),
+        ("(memq 'e '(a b c d))", "#f"),
+        ("(member (list 'b) '(a (b) c))", "((b) c)"),
+        ("(memq (list 'b) '(a (b) c))", "#f"),
+        ("(assq 'b '((a 1) (b 2)))", "(b 2)"),
+        ("(memq 2.0 '(1 2))", "#f"),
+        ("(assq 2.0 '((2 . exact)))", "#f"),
+        ("(assoc \"b\" '((\"a\" . 1) (\"b\" . 2)))", "(\"b\" . 2)"),
+        ("(assoc 2.0 '((2 . exact)))", "#f"),
+        ("(member 1 '())", "#f"),
+    ];
+    for (text, expected) in inputs {
+        assert_eq!(interp.write(eval_str(&interp, text)), expected, "{}", text);
+    }
+    assert!(matches!(eval_err(&interp, "(assv 1 '(1 2))"), SchemeError::TypeError(_)));
+}
+
+#[test]
+fn test_string_to_number() {
+    let interp = Interp::new();
+    let inputs = vec![
+        ("(string->number \"12\")", Value::Number(Number::Int(12))),
+        ("(string->number \"-1.5\")", Value::Number(Number::Float(-1.5))),
+        ("(string->number \"#xff\")", Value::Number(Number::Int(255))),
+        ("(string->number \"12abc\")", Value::Boolean(false)),
+        ("(string->number \"1.2.3\")", Value::Boolean(false)),
+        ("(string->number \"  12\")", Value::Boolean(false)),
+        ("(string->number \"12 \")", Value::Boolean(false)),
+        ("(string->number \"\")", Value::Boolean(false)),
+        ("(string->number \"abc\")", Value::Boolean(false)),
+        ("(string->number \"(1)\")", Value::Boolean(false)),
+    ];
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_identifiers() {
+    let interp = Interp::new();
+    for (text, expected) in [
+        ("foo", true), ("list->vector", true), ("set!", true), ("+", true), ("...", true),
+        ("x2", true), ("a.b", false), ("foo bar", false), (" foo", false), ("", false),
+        ("12", false), ("-1", false), ("#t", false), ("\"s\"", false), ("(a)", false),
+    ] {
+        interp.define("text", interp.string(text).unwrap());
+        assert_eq!(eval_str(&interp, "(identifier? text)"), Value::Boolean(expected), "{:?}", text);
+    }
+    // Checking a name doesn't intern it.
+    let symbols = interp.heap.borrow().symbol_count();
+    assert_eq!(eval_str(&interp, "(identifier? \"never-interned-name\")"), Value::Boolean(true));
+    assert_eq!(interp.heap.borrow().symbol_count(), symbols);
+    let inputs = vec![
+        ("(valid-symbol-char? #\\a)", Value::Boolean(true)),
+        ("(valid-symbol-char? #\\?)", Value::Boolean(true)),
+        ("(valid-symbol-char? #\\7)", Value::Boolean(true)),
+        ("(valid-symbol-char? #\\space)", Value::Boolean(false)),
+        ("(valid-symbol-char? #\\()", Value::Boolean(false)),
+        ("(valid-symbol-char? #\\\")", Value::Boolean(false)),
+    ];
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_reset() {
+    let interp = Interp::new();
+    let size = interp.heap.borrow().size();
+    eval_str(&interp, "(define x (list 1 2 3))");
+    eval_str(&interp, "(define car (lambda (l) 'shadowed))");
+    assert!(interp.heap.borrow().size() > size);
+
+    interp.reset();
+    assert_eq!(interp.heap.borrow().size(), size);
+    assert!(matches!(eval_err(&interp, "x"), SchemeError::UnboundVariable(_)));
+    assert_eq!(eval_str(&interp, "(car '(1 2))"), Value::Number(Number::Int(1)));
+    assert_eq!(eval_str(&interp, "(if #t 'yes 'no)"), interp.symbol("yes"));
+
+    // The interpreter is fully usable after a reset.
+    eval_str(&interp, "(define x 5)");
+    assert_eq!(eval_str(&interp, "(+ x 1)"), Value::Number(Number::Int(6)));
+}
+
+#[test]
+fn test_max_heap_size() {
+    let interp = Interp::new();
+    let size = interp.heap.borrow().size();
+    interp.set_max_heap_size(Some(size + 1000));
+    eval_str(&interp, "(define grow (lambda (l) (grow (cons 1 l))))");
+    assert_eq!(eval_err(&interp, "(grow '())"), SchemeError::EvalError("heap exhausted".to_string()));
+    assert_eq!(interp.heap.borrow().size(), size + 1000);
+    // The garbage was collected before reporting, no reset is needed.
+    assert!(!interp.heap.borrow().is_exhausted());
+    assert_eq!(eval_str(&interp, "(length (list 1 2 3))"), Value::Number(Number::Int(3)));
+    eval_err(&interp, "(grow '())");
+    assert_eq!(eval_str(&interp, "(length (list 1 2 3))"), Value::Number(Number::Int(3)));
+
+    // A single primitive can't take the heap past the cap either.
+    interp.reset();
+    interp.set_max_heap_size(None);
+    eval_str(&interp, "(define build (lambda (l n) (if (= n 0) l (build (cons n l) (- n 1)))))");
+    eval_str(&interp, "(define long (build '() 5000))");
+    let size = interp.heap.borrow().size();
+    interp.set_max_heap_size(Some(size + 100));
+    assert_eq!(eval_err(&interp, "(append long '())"), SchemeError::EvalError("heap exhausted".to_string()));
+    assert!(interp.heap.borrow().size() <= size + 100);
+
+    interp.reset();
+    assert_eq!(eval_str(&interp, "(length (list 1 2 3))"), Value::Number(Number::Int(3)));
+    interp.set_max_heap_size(None);
+    eval_str(&interp, "(define grow (lambda (l n) (if (= n 0) (length l) (grow (cons n l) (- n 1)))))");
+    assert_eq!(eval_str(&interp, "(grow '() 2000)"), Value::Number(Number::Int(2000)));
+    // Between the tail calls of a form, the garbage is collected before the
+    // cap is reached, a loop with a constant live set allocates past it.
+    interp.reset();
+    let size = interp.heap.borrow().size();
+    interp.set_max_heap_size(Some(size + 1000));
+    eval_str(&interp, "(define churn (lambda (n l) (if (= n 0) (length l) (churn (- n 1) (list n n n)))))");
+    let allocations = interp.heap.borrow().allocation_count();
+    assert_eq!(eval_str(&interp, "(churn 10000 '())"), Value::Number(Number::Int(3)));
+    assert!(interp.heap.borrow().allocation_count() - allocations > 10_000);
+    assert!(interp.heap.borrow().size() <= size + 1000);
+    eval_str(&interp, "(define spin (lambda (i v) (if (= i 5000) (vector-length v) (spin (+ i 1) (vector i i i)))))");
+    assert_eq!(eval_str(&interp, "(spin 0 #f)"), Value::Number(Number::Int(3)));
+}
 
 #[test]
 fn test_read_eval_char() {
@@ -180,8 +980,1609 @@ fn test_read_eval_char() {
         ("(char-ci<? #\\A #\\b)", Value::Boolean(true)),
         ("(char-ci>=? #\\A #\\a)", Value::Boolean(true)),
         ("(char-ci<=? #\\A #\\a)", Value::Boolean(true)),
+        ("(char-alphabetic? #\\a)", Value::Boolean(true)),
+        ("(char-alphabetic? #\\Z)", Value::Boolean(true)),
+        ("(char-alphabetic? #\\7)", Value::Boolean(false)),
+        ("(char-numeric? #\\7)", Value::Boolean(true)),
+        ("(char-numeric? #\\a)", Value::Boolean(false)),
+        ("(char-whitespace? #\\space)", Value::Boolean(true)),
+        ("(char-whitespace? #\\tab)", Value::Boolean(true)),
+        ("(char-whitespace? #\\newline)", Value::Boolean(true)),
+        ("(char-whitespace? #\\return)", Value::Boolean(true)),
+        ("(char-whitespace? #\\a)", Value::Boolean(false)),
+        ("(char-upper-case? #\\A)", Value::Boolean(true)),
+        ("(char-upper-case? #\\a)", Value::Boolean(false)),
+        ("(char-upper-case? #\\1)", Value::Boolean(false)),
+        ("(char-lower-case? #\\a)", Value::Boolean(true)),
+        ("(char-lower-case? #\\A)", Value::Boolean(false)),
+        ("(char-upcase #\\a)", Value::Char(b'A')),
+        ("(char-upcase #\\A)", Value::Char(b'A')),
+        ("(char-upcase #\\1)", Value::Char(b'1')),
+        ("(char-downcase #\\A)", Value::Char(b'a')),
+        ("(char-downcase #\\space)", Value::Char(b' ')),
+    ];
+    let interp = Interp::new();
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_read_eval_string_compare() {
+    let inputs = vec![
+        ("(string=? \"abc\" \"abc\")", Value::Boolean(true)),
+        ("(string=? \"ABC\" \"abc\")", Value::Boolean(false)),
+        ("(string<? \"abc\" \"abd\")", Value::Boolean(true)),
+        ("(string<? \"abc\" \"ab\")", Value::Boolean(false)),
+        ("(string<=? \"ab\" \"abc\" \"abc\")", Value::Boolean(true)),
+        ("(string>? \"b\" \"a\")", Value::Boolean(true)),
+        ("(string>=? \"a\" \"b\")", Value::Boolean(false)),
+        ("(string<? \"B\" \"a\")", Value::Boolean(true)),
+        ("(string-ci=? \"ABC\" \"abc\")", Value::Boolean(true)),
+        ("(string-ci=? \"ABC\" \"abd\")", Value::Boolean(false)),
+        ("(string-ci<? \"abc\" \"ABD\")", Value::Boolean(true)),
+        ("(string-ci<? \"a\" \"B\")", Value::Boolean(true)),
+        ("(string-ci>? \"abc\" \"ABD\")", Value::Boolean(false)),
+        ("(string-ci>=? \"Abc\" \"aBC\")", Value::Boolean(true)),
+        ("(string-ci<=? \"a\" \"B\" \"c\")", Value::Boolean(true)),
+        ("(string-ci=? \"a\" \"A\" \"b\")", Value::Boolean(false)),
     ];
     let interp = Interp::new();
     check_exprs(&interp, &inputs);
+    assert!(matches!(eval_err(&interp, "(string-ci=? \"a\" 'a)"), SchemeError::TypeError(_)));
+}
+
+fn eval_str(interp: &Interp, text: &str) -> Value {
+    let mut parser = Parser::new(text.as_bytes());
+    match parser.read(interp) {
+        Ok(expr) => match interp.eval(expr) {
+            Ok(value) => value,
+            Err(e) => panic!("Eval {} failed with error: {:?}", text, e)
+        },
+        Err(e) => panic!("Parse {} failed, error: {:?}.", text, e)
+    }
 }
 
+fn eval_err(interp: &Interp, text: &str) -> SchemeError {
+    let mut parser = Parser::new(text.as_bytes());
+    match parser.read(interp) {
+        Ok(expr) => match interp.eval(expr) {
+            Ok(value) => panic!("Eval {} should have failed, but gave {}.", text, interp.write(value)),
+            Err(e) => e
+        },
+        Err(e) => panic!("Parse {} failed, error: {:?}.", text, e)
+    }
+}
+
+#[test]
+fn test_read_eval_promise() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define count 0)");
+    eval_str(&interp, "(define p (delay ((lambda () (set! count (+ count 1)) count))))");
+    let inputs = vec![
+        ("(promise? p)", Value::Boolean(true)),
+        ("(promise? 1)", Value::Boolean(false)),
+        ("count", Value::Number(Number::Int(0))),
+        ("(force p)", Value::Number(Number::Int(1))),
+        ("(force p)", Value::Number(Number::Int(1))),
+        ("count", Value::Number(Number::Int(1))),
+        ("(force 42)", Value::Number(Number::Int(42))),
+    ];
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_read_eval_delay_force() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define integers (lambda (n) (delay (cons n (integers (+ n 1))))))");
+    eval_str(&interp, "(define take (lambda (s k) (if (= k 0) '() (cons (car (force s)) (take (cdr (force s)) (- k 1))))))");
+    // Each step hands back a fresh promise, forcing the chain must not grow the stack.
+    eval_str(&interp, "(define drop (lambda (s k) (delay-force (if (= k 0) s (drop (cdr (force s)) (- k 1))))))");
+    eval_str(&interp, "(define p (make-promise 7))");
+    let inputs = vec![
+        ("(equal? (take (integers 0) 5) '(0 1 2 3 4))", Value::Boolean(true)),
+        ("(car (force (drop (integers 0) 10000)))", Value::Number(Number::Int(10000))),
+        ("(force (delay-force (delay 3)))", Value::Number(Number::Int(3))),
+        ("(promise? p)", Value::Boolean(true)),
+        ("(force p)", Value::Number(Number::Int(7))),
+        ("(eq? (make-promise p) p)", Value::Boolean(true)),
+    ];
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_read_eval_streams() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define count 0)");
+    eval_str(&interp, "(define from (lambda (n) (stream-cons (begin-count n) (from (+ n 1)))))");
+    eval_str(&interp, "(define begin-count (lambda (n) (set! count (+ count 1)) n))");
+    eval_str(&interp, "(define naturals (from 0))");
+    let inputs = vec![
+        ("count", Value::Number(Number::Int(0))),
+        ("(equal? (stream->list (stream-take naturals 5)) '(0 1 2 3 4))", Value::Boolean(true)),
+        ("count", Value::Number(Number::Int(5))),
+        ("(stream-car (stream-cdr (stream-cdr naturals)))", Value::Number(Number::Int(2))),
+        ("(equal? (stream->list (stream-take (stream-map (lambda (x) (* x x)) naturals) 4)) '(0 1 4 9))",
+            Value::Boolean(true)),
+        ("(equal? (stream->list (stream-take (stream-cons 1 stream-null) 3)) '(1))", Value::Boolean(true)),
+        ("(stream-null? stream-null)", Value::Boolean(true)),
+        ("(stream-null? naturals)", Value::Boolean(false)),
+        ("(stream->list (stream-take naturals 0))", Value::Nil),
+    ];
+    check_exprs(&interp, &inputs);
+
+    // A stream-cons is its promise and the expressions it delays, and those
+    // survive a collection until forced.
+    let expr = Parser::new("(from 100)".as_bytes()).read(&interp).unwrap();
+    let allocations = interp.heap.borrow().allocation_count();
+    let stream = interp.eval(expr).unwrap();
+    assert_eq!(interp.heap.borrow().allocation_count() - allocations, 2);
+    interp.define("later", stream);
+    eval_str(&interp, "(gc)");
+    assert_eq!(eval_str(&interp, "(stream-car (stream-cdr later))"), Value::Number(Number::Int(101)));
+}
+
+#[test]
+fn test_read_eval_map() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define total 0)");
+    eval_str(&interp, "(for-each (lambda (x) (set! total (+ total x))) '(1 2 3))");
+    let inputs = vec![
+        ("total", Value::Number(Number::Int(6))),
+        ("(map (lambda (x) (* x x)) ())", Value::Nil),
+        ("(car (cdr (map (lambda (x) (* x x)) '(1 2 3))))", Value::Number(Number::Int(4))),
+        ("(length (map + '(1 2 3) '(10 20)))", Value::Number(Number::Int(2))),
+        ("(car (map + '(1 2 3) '(10 20)))", Value::Number(Number::Int(11))),
+    ];
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_read_eval_call_cc() {
+    let interp = Interp::new();
+    let inputs = vec![
+        ("(call/cc (lambda (k) 42))", Value::Number(Number::Int(42))),
+        ("(+ 1 (call/cc (lambda (k) (+ 10 (k 2)))))", Value::Number(Number::Int(3))),
+        // Early return out of a map.
+        ("(call/cc (lambda (return) (map (lambda (x) (if (< x 0) (return x) x)) '(1 -2 3))))",
+            Value::Number(Number::Int(-2))),
+        // Search for the first element greater than 2.
+        ("(call-with-current-continuation (lambda (found)
+            (for-each (lambda (x) (if (> x 2) (found x) #f)) '(1 2 3 4))
+            #f))", Value::Number(Number::Int(3))),
+        ("(call/cc (lambda (found)
+            (for-each (lambda (x) (if (> x 9) (found x) #f)) '(1 2 3 4))
+            #f))", Value::Boolean(false)),
+    ];
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_read_eval_higher_order() {
+    let interp = Interp::new();
+    let inputs = vec![
+        ("(procedure? car)", Value::Boolean(true)),
+        ("(procedure? (lambda (x) x))", Value::Boolean(true)),
+        ("(procedure? 'car)", Value::Boolean(false)),
+        ("(apply + '(1 2 3))", Value::Number(Number::Int(6))),
+        ("(apply + 1 2 '(3 4))", Value::Number(Number::Int(10))),
+        ("(length (filter (lambda (x) (> x 1)) '(1 2 3)))", Value::Number(Number::Int(2))),
+        ("(fold + 0 '(1 2 3))", Value::Number(Number::Int(6))),
+        ("(car (fold cons () '(1 2 3)))", Value::Number(Number::Int(3))),
+    ];
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_higher_order_not_a_procedure() {
+    let interp = Interp::new();
+    for (text, message) in [
+        ("(map 42 '(1 2))", "map: expected Procedure as argument 1"),
+        ("(apply 42 '(1 2))", "apply: expected Procedure as argument 1"),
+        ("(for-each \"f\" '(1 2))", "for-each: expected Procedure as argument 1"),
+        ("(filter 1 '(1 2))", "filter: expected Procedure as argument 1"),
+        ("(sort '(2 1) 'less)", "sort: expected Procedure as argument 2"),
+        ("(apply + 1 2)", "apply: expected List as argument 3"),
+        ("(apply + '(1 . 2))", "apply: expected Proper List as argument 2"),
+        ("(vector-map + (vector 1) '(2))", "vector-map: expected Vector as argument 3"),
+        ("(hash-ref '() 1)", "hash-ref: expected HashTable as argument 1"),
+        ("(hash-table-walk 1 car)", "hash-table-walk: expected HashTable as argument 1"),
+        ("(gcd 4 1.5)", "gcd: expected Integer as argument 2"),
+    ] {
+        assert_eq!(eval_err(&interp, text), SchemeError::TypeError(message.to_string()));
+    }
+}
+
+#[test]
+fn test_map_arity_mismatch() {
+    let interp = Interp::new();
+    let inputs = vec![
+        ("(map (lambda (x) x) '())", Value::Nil),
+        ("(map (lambda (x y) x) '() '())", Value::Nil),
+        ("(equal? (map (lambda (x . rest) (cons x rest)) '(1 2) '(3 4)) '((1 3) (2 4)))", Value::Boolean(true)),
+        ("(equal? (map + '(1 2) '(3 4) '(5 6)) '(9 12))", Value::Boolean(true)),
+    ];
+    check_exprs(&interp, &inputs);
+
+    for (text, message) in [
+        ("(map (lambda (x y) x) '(1 2))", "map: procedure: expected 2 args, but got 1"),
+        ("(map (lambda (x) x) '(1) '(2))", "map: procedure: expected 1 arg, but got 2"),
+        ("(for-each (lambda (x y . z) x) '(1))", "for-each: procedure: expected at least 2 args, but got 1"),
+        ("(map (lambda (x) x))", "map: expected at least 2 args, but got 1"),
+        ("(for-each car)", "for-each: expected at least 2 args, but got 1"),
+    ] {
+        assert_eq!(eval_err(&interp, text), SchemeError::ArgCountError(message.to_string()), "{}", text);
+    }
+    // Too few args to an n-ary closure is an error rather than a panic.
+    eval_err(&interp, "((lambda (x y . z) x) 1)");
+}
+
+#[test]
+fn test_list_arg_positions() {
+    let interp = Interp::new();
+    for (text, message) in [
+        ("(map car 42)", "map: expected List as argument 2"),
+        ("(map + '(1 2) 42)", "map: expected List as argument 3"),
+        ("(for-each car #t)", "for-each: expected List as argument 2"),
+        ("(filter odd? 42)", "filter: expected List as argument 2"),
+        ("(fold + 0 42)", "fold: expected List as argument 3"),
+        ("(fold + 0 '(1 2 . 3))", "fold: expected Proper List as argument 3"),
+    ] {
+        assert_eq!(eval_err(&interp, text), SchemeError::TypeError(message.to_string()), "{}", text);
+    }
+}
+
+#[test]
+fn test_vector_map() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define total 0)");
+    eval_str(&interp, "(vector-for-each (lambda (x y) (set! total (+ total (* x y)))) #(1 2 3) #(4 5 6))");
+    assert_eq!(eval_str(&interp, "total"), Value::Number(Number::Int(32)));
+    for (text, expected) in [
+        ("(vector-map (lambda (x) (* x x)) #(1 2 3))", "#(1 4 9)"),
+        ("(vector-map + #(1 2) #(10 20) #(100 200))", "#(111 222)"),
+        ("(vector-map car #())", "#()"),
+    ] {
+        assert_eq!(interp.write(eval_str(&interp, text)), expected, "{}", text);
+    }
+
+    // Vectors of different lengths are an error, unless set to use the shortest.
+    for (text, message) in [
+        ("(vector-map + #(1 2) #(1 2 3))", "vector-map: vector argument 3 has length 3, but argument 2 has length 2."),
+        ("(vector-for-each + #(1 2 3) #(1))", "vector-for-each: vector argument 3 has length 1, but argument 2 has length 3."),
+    ] {
+        assert_eq!(eval_err(&interp, text), SchemeError::EvalError(message.to_string()), "{}", text);
+    }
+    assert_eq!(eval_err(&interp, "(vector-map (lambda (x y) x) #(1))"), SchemeError::ArgCountError(
+        "vector-map: procedure: expected 2 args, but got 1".to_string()
+    ));
+    interp.set_vector_map_shortest(true);
+    assert_eq!(interp.write(eval_str(&interp, "(vector-map + #(1 2) #(1 2 3))")), "#(2 4)");
+}
+
+#[test]
+fn test_memoize() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define calls 0)");
+    eval_str(&interp, "(define slow-add (lambda (a b) (set! calls (+ calls 1)) (+ a b)))");
+    eval_str(&interp, "(define fast-add (memoize slow-add))");
+    let inputs = vec![
+        ("(procedure? fast-add)", Value::Boolean(true)),
+        ("(fast-add 1 2)", Value::Number(Number::Int(3))),
+        ("(fast-add 1 2)", Value::Number(Number::Int(3))),
+        ("calls", Value::Number(Number::Int(1))),
+        ("(fast-add 2 1)", Value::Number(Number::Int(3))),
+        ("calls", Value::Number(Number::Int(2))),
+        // Args are equal? rather than eq?, 2 and 2.0 are told apart.
+        ("(fast-add 1.0 2)", Value::Number(Number::Float(3.0))),
+        ("calls", Value::Number(Number::Int(3))),
+        ("(fast-add 1.0 2)", Value::Number(Number::Float(3.0))),
+        ("calls", Value::Number(Number::Int(3))),
+    ];
+    check_exprs(&interp, &inputs);
+
+    eval_str(&interp, "(define lengths 0)");
+    eval_str(&interp, "(define count (memoize (lambda (l) (set! lengths (+ lengths 1)) (length l))))");
+    eval_str(&interp, "(count (list 1 2 3))");
+    eval_str(&interp, "(count (list 1 2 3))");
+    eval_str(&interp, "(count '(\"1\" 2 3))");
+    assert_eq!(eval_str(&interp, "lengths"), Value::Number(Number::Int(2)));
+    // Equal args hash the same, however they're built, cycles included.
+    eval_str(&interp, "(count (list (vector (list 1 2) \"a\" #u8(1 2))))");
+    eval_str(&interp, "(count (list (vector '(1 2) (make-string 1 #\\a) (list->bytevector '(1 2)))))");
+    eval_str(&interp, "(define cycle (list 1 1))");
+    eval_str(&interp, "(set-cdr! (cdr cycle) cycle)");
+    eval_str(&interp, "(define other (list 1))");
+    eval_str(&interp, "(set-cdr! other other)");
+    assert_eq!(interp.equal_hash(eval_str(&interp, "cycle")), interp.equal_hash(eval_str(&interp, "other")));
+    assert_eq!(eval_str(&interp, "lengths"), Value::Number(Number::Int(3)));
+
+    // Floats printing the same are still different args.
+    eval_str(&interp, "(define id (memoize (lambda (x) (set! calls (+ calls 1)) x)))");
+    let text = "(parameterize ((float-print-precision 2)) (list (id 1.001) (id 1.004)))";
+    assert_eq!(interp.write(eval_str(&interp, text)), "(1.001 1.004)");
+    assert_eq!(interp.write(eval_str(&interp, "(list (id 1.001) (id 1.004) calls)")), "(1.001 1.004 5)");
+
+    // Cached args survive a collection, so a new object can't reuse their slot
+    // and be taken for them.
+    eval_str(&interp, "(define run (memoize (lambda (thunk) (thunk))))");
+    eval_str(&interp, "(run (lambda () 1))");
+    eval_str(&interp, "(gc)");
+    assert_eq!(eval_str(&interp, "(run (lambda () 2))"), Value::Number(Number::Int(2)));
+
+    assert!(matches!(eval_err(&interp, "(memoize 42)"), SchemeError::TypeError(_)));
+}
+
+#[test]
+fn test_curry() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define add10 (curry + 10))");
+    eval_str(&interp, "(define between? (lambda (low high x) (if (< x low) #f (< x high))))");
+    eval_str(&interp, "(define digit? (curry between? 0 10))");
+    let inputs = vec![
+        ("((curry + 10) 5)", Value::Number(Number::Int(15))),
+        ("(add10 1 2 3)", Value::Number(Number::Int(16))),
+        ("(add10)", Value::Number(Number::Int(10))),
+        ("(procedure? add10)", Value::Boolean(true)),
+        ("(digit? 7)", Value::Boolean(true)),
+        ("(digit? 12)", Value::Boolean(false)),
+        ("((curry (curry between? 0) 10) 3)", Value::Boolean(true)),
+        ("((curry between? 0 10 5))", Value::Boolean(true)),
+        ("(equal? (map (curry * 2) '(1 2 3)) '(2 4 6))", Value::Boolean(true)),
+        ("(equal? (apply (curry list 1) '(2 3)) '(1 2 3))", Value::Boolean(true)),
+    ];
+    check_exprs(&interp, &inputs);
+
+    // The curried procedure still checks the number of args it gets.
+    eval_err(&interp, "(digit? 1 2)");
+    assert!(matches!(eval_err(&interp, "(curry 42 1)"), SchemeError::TypeError(_)));
+}
+
+#[test]
+fn test_make_object_send() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define make-counter (lambda ()
+        (define n 0)
+        (make-object (list
+            (cons 'inc (lambda () (set! n (+ n 1)) n))
+            (cons 'add (lambda (k) (set! n (+ n k)) n))
+            (cons 'get (lambda () n))))))");
+    eval_str(&interp, "(define a (make-counter))");
+    eval_str(&interp, "(define b (make-counter))");
+    let inputs = vec![
+        ("(send a 'inc)", Value::Number(Number::Int(1))),
+        ("(send a 'inc)", Value::Number(Number::Int(2))),
+        ("(send a 'add 10)", Value::Number(Number::Int(12))),
+        ("(send a 'get)", Value::Number(Number::Int(12))),
+        // Each object has its own state, and can also be called directly.
+        ("(b 'get)", Value::Number(Number::Int(0))),
+        ("(b 'inc)", Value::Number(Number::Int(1))),
+        ("(procedure? a)", Value::Boolean(true)),
+    ];
+    check_exprs(&interp, &inputs);
+
+    for (text, expected) in [
+        ("(send a 'reset)", SchemeError::EvalError("object: no method named reset.".to_string())),
+        ("(make-object (list (cons \"inc\" car)))", SchemeError::TypeError("make-object: method name \"inc\" is not a symbol.".to_string())),
+        ("(make-object (list 'inc))", SchemeError::TypeError("make-object: expected a method entry, but got inc.".to_string())),
+        ("(send 42 'inc)", SchemeError::TypeError("send: expected Procedure as argument 1".to_string())),
+    ] {
+        assert_eq!(eval_err(&interp, text), expected, "{}", text);
+    }
+    eval_err(&interp, "(send a 'add)");
+
+    // Objects share their dispatcher, each is a single object holding its
+    // methods, which a collection keeps.
+    eval_str(&interp, "(define methods (list (cons 'get (lambda () 7))))");
+    let expr = Parser::new("(make-object methods)".as_bytes()).read(&interp).unwrap();
+    let allocations = interp.heap.borrow().allocation_count();
+    let object = interp.eval(expr).unwrap();
+    assert_eq!(interp.heap.borrow().allocation_count() - allocations, 1);
+    interp.define("c", object);
+    eval_str(&interp, "(gc)");
+    assert_eq!(eval_str(&interp, "(send c 'get)"), Value::Number(Number::Int(7)));
+}
+
+#[test]
+fn test_read_eval_alist_merge() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define merged (alist-merge '((a . 1) (b . 2)) '((b . 10) (c . 3))
+        (lambda (key v1 v2) (+ v1 v2))))");
+    eval_str(&interp, "(define disjoint (alist-merge '((a . 1)) '((\"b\" . 2)) +))");
+    let inputs = vec![
+        ("(equal? '(1 (2 \"x\")) '(1 (2 \"x\")))", Value::Boolean(true)),
+        ("(equal? '(1 2) '(1 3))", Value::Boolean(false)),
+        ("(length merged)", Value::Number(Number::Int(3))),
+        ("(equal? merged '((a . 1) (b . 12) (c . 3)))", Value::Boolean(true)),
+        ("(equal? disjoint '((a . 1) (\"b\" . 2)))", Value::Boolean(true)),
+        ("(alist-merge () () +)", Value::Nil),
+    ];
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_read_eval_dynamic_wind() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define trace ())");
+    eval_str(&interp, "(define before (lambda () (set! trace (cons 'before trace))))");
+    eval_str(&interp, "(define after (lambda () (set! trace (cons 'after trace))))");
+    let inputs = vec![
+        ("(dynamic-wind before (lambda () 42) after)", Value::Number(Number::Int(42))),
+        ("(equal? trace '(after before))", Value::Boolean(true)),
+        ("(set! trace ())", Value::Unspecified),
+        ("(call/cc (lambda (k) (dynamic-wind before (lambda () (k 7) 0) after)))",
+            Value::Number(Number::Int(7))),
+        ("(equal? trace '(after before))", Value::Boolean(true)),
+    ];
+    check_exprs(&interp, &inputs);
+
+    // The after thunk also runs when the body fails.
+    eval_str(&interp, "(set! trace ())");
+    eval_err(&interp, "(dynamic-wind before (lambda () (car 1)) after)");
+    assert_eq!(eval_str(&interp, "(equal? trace '(after before))"), Value::Boolean(true));
+}
+
+#[test]
+fn test_read_eval_dynamic_wind_escapes() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define trace '())");
+    eval_str(&interp, "(define note (lambda (x) (set! trace (cons x trace)) x))");
+    eval_str(&interp, "(define wind (lambda (thunk)
+        (dynamic-wind (lambda () (note 'before)) thunk (lambda () (note 'after)))))");
+    // The trace lists the most recent event first.
+    let inputs = vec![
+        // guard clauses run once the body has unwound, after the after thunk.
+        ("(guard (e (#t (note (list 'guard e)))) (wind (lambda () (raise 'oops) (note 'unreached))))",
+            "(guard oops)"),
+        ("trace", "((guard oops) after before)"),
+        ("(set! trace '())", ""),
+        // Handlers run where raise is, still inside the dynamic extent.
+        ("(with-exception-handler (lambda (e) (note (list 'handler e)) e)
+            (lambda () (wind (lambda () (+ 1 (raise-continuable 1))))))", "2"),
+        ("trace", "(after (handler 1) before)"),
+        ("(set! trace '())", ""),
+        // Nested extents unwind innermost first, up to the escaping continuation.
+        ("(call/cc (lambda (k)
+            (wind (lambda () (wind (lambda () (k 'escaped) (note 'unreached)))))))", "escaped"),
+        ("trace", "(after after before before)"),
+        ("(set! trace '())", ""),
+        // An escape from a guard clause runs no after thunk twice.
+        ("(call/cc (lambda (k) (guard (e (#t (k (list 'escaped e)))) (wind (lambda () (error \"bad\"))))))",
+            "(escaped <condition bad>)"),
+        ("trace", "(after before)"),
+    ];
+    for (text, expected) in inputs {
+        let value = eval_str(&interp, text);
+        if !expected.is_empty() {
+            assert_eq!(interp.write(value), expected, "{}", text);
+        }
+    }
+
+    // Continuations only escape: re-entering a finished extent is an error,
+    // and before does not run again.
+    eval_str(&interp, "(set! trace '())");
+    eval_str(&interp, "(define saved #f)");
+    eval_str(&interp, "(wind (lambda () (call/cc (lambda (k) (set! saved k)))))");
+    assert!(matches!(eval_err(&interp, "(saved 1)"), SchemeError::Continuation(..)));
+    assert_eq!(interp.write(eval_str(&interp, "trace")), "(after before)");
+}
+
+#[test]
+fn test_error_irritants() {
+    let interp = Interp::new();
+    let error = eval_err(&interp, "(error \"bad\" 1 2)");
+    assert_eq!(error, SchemeError::UserError(
+        "bad".to_string(), 
+        vec![Value::Number(Number::Int(1)), Value::Number(Number::Int(2))]
+    ));
+    assert_eq!(interp.display_error(&error), "bad 1 2");
+
+    let error = eval_err(&interp, "(error \"not a list:\" '(a b))");
+    assert_eq!(interp.display_error(&error), "not a list: (a b)");
+}
+
+#[test]
+fn test_format_directives() {
+    let interp = Interp::new();
+    for (text, expected) in [
+        ("(format \"~,2f\" 3.14159)", "3.14"),
+        ("(format \"~f\" 2)", "2.0"),
+        ("(format \"~6,1f|\" 2.25)", "   2.2|"),
+        ("(format \"~,2e\" 1234.5)", "1.23e3"),
+        ("(format \"~x\" 255)", "ff"),
+        ("(format \"~b ~o\" 5 8)", "101 10"),
+        ("(format \"~x\" -255)", "-ff"),
+        ("(format \"~a and ~s~%\" \"a\" \"s\")", "a and \"s\"\n"),
+        ("(format \"100~~\")", "100~"),
+    ] {
+        let mut buf = String::new();
+        let value = eval_str(&interp, text);
+        assert!(interp.is_string(value, &mut buf), "{}", text);
+        assert_eq!(buf, expected, "{}", text);
+    }
+    assert!(matches!(eval_err(&interp, "(format \"~x\" 1.5)"), SchemeError::TypeError(_)));
+    // Huge widths and digits are errors rather than huge strings.
+    for text in ["(format \"~10000000000a\" 1)", "(format \"~1,5000f\" 1)", "(format \"~99999999999999999999999a\" 1)"] {
+        assert!(matches!(eval_err(&interp, text), SchemeError::EvalError(_)), "{}", text);
+    }
+    assert_eq!(interp.write(eval_str(&interp, "(string-length (format \"~4096a\" 1))")), "4096");
+}
+
+#[test]
+fn test_read_eval_guard() {
+    let interp = Interp::new();
+    let inputs = vec![
+        ("(guard (e (#t 0)) (/ 1 0))", Value::Number(Number::Int(0))),
+        ("(guard (e ((condition? e) -1)) (% 1 0))", Value::Number(Number::Int(-1))),
+        ("(guard (e (else 42)) (error \"bad\" 1))", Value::Number(Number::Int(42))),
+        ("(guard (e (else 42)) 1 2)", Value::Number(Number::Int(2))),
+        ("(guard (e ((condition? e))) (car 1))", Value::Boolean(true)),
+        ("(equal? (guard (e (else (condition-message e))) (error \"bad thing\" 1)) \"bad thing\")",
+            Value::Boolean(true)),
+        // Escaping continuations are not caught by guard.
+        ("(call/cc (lambda (k) (guard (e (else 0)) (k 5))))", Value::Number(Number::Int(5))),
+    ];
+    check_exprs(&interp, &inputs);
+
+    // Without a matching clause, the error is raised again.
+    assert_eq!(eval_err(&interp, "(guard (e (#f 0)) (error \"again\"))"), SchemeError::UserError("again".to_string(), vec![]));
+}
+
+#[test]
+fn test_read_eval_raise() {
+    let interp = Interp::new();
+    let inputs = vec![
+        // Any object can be raised, guard receives it unchanged.
+        ("(guard (e ((symbol? e) (list 'caught e))) (raise 'oops))", "(caught oops)"),
+        ("(guard (e ((string? e) e) ((number? e) (* e 2))) (+ 1 (raise 21)))", "42"),
+        ("(guard (e ((condition? e) 'condition) (else e)) (raise (list 1 2)))", "(1 2)"),
+        ("(guard (e (#t (+ e 1))) (raise-continuable 41))", "42"),
+        ("(guard (outer (#t (list 'outer outer))) (guard (inner ((string? inner) 'inner)) (raise 'up)))", "(outer up)"),
+    ];
+    for (text, expected) in inputs {
+        assert_eq!(interp.write(eval_str(&interp, text)), expected, "{}", text);
+    }
+    let sym = interp.lookup("nobody-catches");
+    let error = eval_err(&interp, "(raise 'nobody-catches)");
+    assert_eq!(error, SchemeError::Raised(sym));
+    assert_eq!(interp.display_error(&error), "Uncaught raise of nobody-catches");
+}
+
+#[test]
+fn test_read_eval_with_exception_handler() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define seen '())");
+    eval_str(&interp, "(define note (lambda (x) (set! seen (cons x seen)) x))");
+    let inputs = vec![
+        // The handler sees the raised object, and its value flows back to raise-continuable.
+        ("(with-exception-handler (lambda (e) (* e 2)) (lambda () (+ 1 (raise-continuable 20))))", "41"),
+        ("(with-exception-handler (lambda (e) 0) (lambda () 'no-raise))", "no-raise"),
+        // Nested handlers, the inner one runs with the outer one installed.
+        ("(with-exception-handler
+            (lambda (e) (note (list 'outer e)))
+            (lambda ()
+              (with-exception-handler
+                (lambda (e) (raise-continuable (list 'inner e)))
+                (lambda () (raise-continuable 'x)))))", "(outer (inner x))"),
+        // Escaping from the handler with a continuation.
+        ("(call/cc (lambda (k)
+            (with-exception-handler (lambda (e) (k (list 'escaped e))) (lambda () (raise 'boom)))))", "(escaped boom)"),
+        ("(call/cc (lambda (k)
+            (with-exception-handler (lambda (e) (k (condition-message e))) (lambda () (error \"bad\")))))", "\"bad\""),
+        // A guard inside the handler's extent catches first, one outside catches what the handler returns from.
+        ("(with-exception-handler (lambda (e) (note 'handler))
+            (lambda () (guard (e (#t (list 'guard e))) (raise 'inner))))", "(guard inner)"),
+        ("(guard (e ((condition? e) 'secondary))
+            (with-exception-handler (lambda (e) (note e)) (lambda () (raise 'first))))", "secondary"),
+        ("seen", "(first (outer (inner x)))"),
+    ];
+    for (text, expected) in inputs {
+        assert_eq!(interp.write(eval_str(&interp, text)), expected, "{}", text);
+    }
+    // Without a handler left, returning from a non-continuable raise is an error.
+    assert!(matches!(eval_err(&interp, "(with-exception-handler (lambda (e) 0) (lambda () (raise 'oops)))"), SchemeError::EvalError(_)));
+    // The handlers are uninstalled once the thunk returns, or escapes.
+    assert_eq!(eval_err(&interp, "(raise 'after)"), SchemeError::Raised(interp.lookup("after")));
+}
+
+#[test]
+fn test_read_eval_values() {
+    let interp = Interp::new();
+    let inputs = vec![
+        ("(call-with-values (lambda () (values 1 2)) +)", Value::Number(Number::Int(3))),
+        ("(call-with-values (lambda () (values)) list)", Value::Nil),
+        ("(call-with-values (lambda () 5) (lambda (x) (* x 2)))", Value::Number(Number::Int(10))),
+        ("(+ 1 (values 2))", Value::Number(Number::Int(3))),
+        ("(call-with-values (lambda () (values 1 2 3)) (lambda (a . rest) (length rest)))",
+            Value::Number(Number::Int(2))),
+    ];
+    check_exprs(&interp, &inputs);
+
+    assert!(matches!(eval_err(&interp, "(+ 1 (values 2 3))"), SchemeError::EvalError(_)));
+}
+
+#[test]
+fn test_apply_values() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define lst '(1 2 3))");
+    eval_str(&interp, "(define split (lambda (l) (apply values l)))");
+    let inputs = vec![
+        ("(equal? (call-with-values (lambda () (apply values lst)) list) lst)", Value::Boolean(true)),
+        ("(call-with-values (lambda () (apply values '())) list)", Value::Nil),
+        ("(apply values '(7))", Value::Number(Number::Int(7))),
+        ("(call-with-values (lambda () (apply values 1 '(2 3))) +)", Value::Number(Number::Int(6))),
+        // Values pass unchanged through closure returns and tail positions.
+        ("(equal? (call-with-values (lambda () (split lst)) list) lst)", Value::Boolean(true)),
+        ("(call-with-values (lambda () (if #t (split '(4 5)) 0)) *)", Value::Number(Number::Int(20))),
+        ("(call-with-values (lambda () (apply split (list lst))) (lambda (a b c) c))", Value::Number(Number::Int(3))),
+    ];
+    check_exprs(&interp, &inputs);
+
+    let values = eval_str(&interp, "(apply values lst)");
+    assert_eq!(interp.is_values(values).map(|values| values.len()), Some(3));
+    assert!(matches!(eval_err(&interp, "(car (apply values lst))"), SchemeError::EvalError(_)));
+}
+
+#[test]
+fn test_unbound_suggestions() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define counter 0)");
+    let repl_eval = |text: &str| {
+        interp.repl_eval(Parser::new(text.as_bytes()).read(&interp).unwrap())
+    };
+    assert_eq!(repl_eval("(cbr '(1 2))"), Err(SchemeError::UnboundVariable(
+        "Unbound symbol: cbr, did you mean `car` or `cdr`?".to_string()
+    )));
+    assert_eq!(repl_eval("countr"), Err(SchemeError::UnboundVariable(
+        "Unbound symbol: countr, did you mean `counter`?".to_string()
+    )));
+    // Names ending in ? stay readable.
+    assert_eq!(repl_eval("substring"), Err(SchemeError::UnboundVariable(
+        "Unbound symbol: substring, did you mean `substring?`?".to_string()
+    )));
+    // Nothing is close enough to suggest.
+    assert_eq!(repl_eval("zzzzzzzz"), Err(SchemeError::UnboundVariable(
+        "Unbound symbol: zzzzzzzz".to_string()
+    )));
+    // Plain eval leaves the message alone.
+    assert_eq!(eval_err(&interp, "cbr"), SchemeError::UnboundVariable("Unbound symbol: cbr".to_string()));
+}
+
+#[test]
+fn test_repl_result_history() {
+    let interp = Interp::new();
+    for (text, expected) in [
+        ("(+ 1 2)", "3"),
+        ("*1", "3"),
+        ("(* *1 10)", "30"),
+        ("(list *1 *2 *3)", "(30 3 3)"),
+        ("(define x 6)", "6"),
+        // Values not shown, such as that of a set!, leave the history alone.
+        ("(set! x 7)", ""),
+        ("(+ x 0)", "7"),
+        ("(list *1 *2 *3)", "(7 6 (30 3 3))"),
+    ] {
+        let result = interp.repl_eval(Parser::new(text.as_bytes()).read(&interp).unwrap()).unwrap();
+        if result != Value::Unspecified {
+            assert_eq!(interp.write(result), expected, "{}", text);
+        }
+    }
+    // Plain eval doesn't record its results.
+    eval_str(&interp, "42");
+    assert_eq!(interp.write(eval_str(&interp, "*1")), "(7 6 (30 3 3))");
+}
+
+#[test]
+fn test_at_exit() {
+    let interp = Interp::new();
+    let codes = Rc::new(RefCell::new(Vec::new()));
+    let exited = codes.clone();
+    interp.set_exit_handler(move |code| exited.borrow_mut().push(code));
+    eval_str(&interp, "(define order '())");
+    eval_str(&interp, "(at-exit (lambda () (set! order (cons 'first order))))");
+    eval_str(&interp, "(at-exit (lambda () (set! order (cons 'second order))))");
+    eval_str(&interp, "(exit 3)");
+    // The hooks ran most recent first, so first was consed last.
+    assert_eq!(interp.write(eval_str(&interp, "order")), "(first second)");
+    assert_eq!(*codes.borrow(), vec![3]);
+
+    // Hooks run once, a failing hook doesn't keep the others from running.
+    eval_str(&interp, "(at-exit (lambda () (set! order '())))");
+    eval_str(&interp, "(at-exit (lambda () (car '())))");
+    eval_str(&interp, "(quit)");
+    assert_eq!(eval_str(&interp, "order"), Value::Nil);
+    assert_eq!(*codes.borrow(), vec![3, 0]);
+    eval_str(&interp, "(set! order 'kept)");
+    eval_str(&interp, "(exit 1)");
+    assert_eq!(interp.write(eval_str(&interp, "order")), "kept");
+
+    assert!(matches!(eval_err(&interp, "(at-exit 42)"), SchemeError::TypeError(_)));
+}
+
+#[test]
+fn test_bytevectors() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define bv #u8(1 2 3))");
+    let inputs = vec![
+        ("(bytevector? bv)", Value::Boolean(true)),
+        ("(bytevector? #(1 2 3))", Value::Boolean(false)),
+        ("(bytevector-length bv)", Value::Number(Number::Int(3))),
+        ("(bytevector-u8-ref bv 1)", Value::Number(Number::Int(2))),
+        ("(bytevector-u8-set! bv 1 255)", Value::Unspecified),
+        ("(bytevector-u8-ref bv 1)", Value::Number(Number::Int(255))),
+        ("(equal? (bytevector->list bv) '(1 255 3))", Value::Boolean(true)),
+        ("(equal? (list->bytevector '(1 255 3)) bv)", Value::Boolean(true)),
+        ("(equal? (make-bytevector 2 7) #u8(7 7))", Value::Boolean(true)),
+        ("(bytevector-length (make-bytevector 4))", Value::Number(Number::Int(4))),
+    ];
+    check_exprs(&interp, &inputs);
+    assert_eq!(interp.write(eval_str(&interp, "bv")), "#u8(1 255 3)");
+    assert_eq!(interp.write(eval_str(&interp, "#u8()")), "#u8()");
+
+    for text in [
+        "(bytevector-u8-set! bv 0 256)",
+        "(bytevector-u8-set! bv 0 -1)",
+        "(list->bytevector '(1 300))",
+        "(make-bytevector 2 1.5)",
+        "(bytevector-u8-ref #(1) 0)",
+    ] {
+        assert!(matches!(eval_err(&interp, text), SchemeError::TypeError(_)), "{}", text);
+    }
+    assert!(matches!(eval_err(&interp, "(bytevector-u8-ref bv 3)"), SchemeError::EvalError(_)));
+    assert_eq!(eval_err(&interp, "(make-bytevector 100000000000)"), SchemeError::EvalError(
+        "make-bytevector: length 100000000000 is too long.".to_string()
+    ));
+    assert!(Parser::new("#u8(1 256)".as_bytes()).read(&interp).is_err());
+}
+
+#[test]
+fn test_define_values() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define-values (a b c) (values 1 2 3))");
+    eval_str(&interp, "(define-values (x . rest) (values 4 5 6))");
+    eval_str(&interp, "(define-values all (values 7 8))");
+    eval_str(&interp, "(define-values (single) 9)");
+    let inputs = vec![
+        ("a", Value::Number(Number::Int(1))),
+        ("b", Value::Number(Number::Int(2))),
+        ("c", Value::Number(Number::Int(3))),
+        ("x", Value::Number(Number::Int(4))),
+        ("(equal? rest '(5 6))", Value::Boolean(true)),
+        ("(equal? all '(7 8))", Value::Boolean(true)),
+        ("single", Value::Number(Number::Int(9))),
+        // Inside a body the names are local to it.
+        ("((lambda () (define-values (a b) (values 10 20)) (+ a b)))", Value::Number(Number::Int(30))),
+        ("a", Value::Number(Number::Int(1))),
+    ];
+    check_exprs(&interp, &inputs);
+
+    for text in ["(define-values (p q) (values 1))", "(define-values (p) (values 1 2))"] {
+        assert!(matches!(eval_err(&interp, text), SchemeError::ArgCountError(_)), "{}", text);
+    }
+}
+
+#[test]
+fn test_read_eval_hash_table() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define table (make-hash-table))");
+    eval_str(&interp, "(hash-set! table 'apple 1)");
+    eval_str(&interp, "(hash-set! table 42 \"answer\")");
+    eval_str(&interp, "(hash-set! table #\\a 'char)");
+    eval_str(&interp, "(hash-set! table 'apple 2)");
+    let inputs = vec![
+        ("(hash-table? table)", Value::Boolean(true)),
+        ("(hash-table? 'table)", Value::Boolean(false)),
+        ("(hash-count table)", Value::Number(Number::Int(3))),
+        ("(hash-ref table 'apple)", Value::Number(Number::Int(2))),
+        ("(equal? (hash-ref table 42) \"answer\")", Value::Boolean(true)),
+        ("(eq? (hash-ref table #\\a) 'char)", Value::Boolean(true)),
+        ("(hash-ref table 'pear 0)", Value::Number(Number::Int(0))),
+        ("(hash-delete! table 'apple)", Value::Boolean(true)),
+        ("(hash-delete! table 'apple)", Value::Boolean(false)),
+        ("(hash-ref table 'apple #f)", Value::Boolean(false)),
+        ("(hash-count table)", Value::Number(Number::Int(2))),
+    ];
+    check_exprs(&interp, &inputs);
+
+    assert!(matches!(eval_err(&interp, "(hash-ref table 'pear)"), SchemeError::EvalError(_)));
+}
+
+#[test]
+fn test_hash_table_ref_default_contains() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define table (make-hash-table))");
+    eval_str(&interp, "(hash-set! table 'apple 1)");
+    eval_str(&interp, "(hash-set! table 'nothing #f)");
+    let inputs = vec![
+        ("(hash-table-ref/default table 'apple 0)", Value::Number(Number::Int(1))),
+        ("(hash-table-ref/default table 'pear 0)", Value::Number(Number::Int(0))),
+        ("(hash-table-ref/default table 'nothing 0)", Value::Boolean(false)),
+        // The default is a value, a procedure is returned rather than called.
+        ("(procedure? (hash-table-ref/default table 'pear car))", Value::Boolean(true)),
+        ("(hash-table-contains? table 'apple)", Value::Boolean(true)),
+        ("(hash-table-contains? table 'nothing)", Value::Boolean(true)),
+        ("(hash-table-contains? table 'pear)", Value::Boolean(false)),
+        // Neither adds the missing keys.
+        ("(hash-count table)", Value::Number(Number::Int(2))),
+    ];
+    check_exprs(&interp, &inputs);
+
+    assert!(matches!(eval_err(&interp, "(hash-table-contains? 'table 'apple)"), SchemeError::TypeError(_)));
+}
+
+#[test]
+fn test_symbol_plists() {
+    let interp = Interp::new();
+    eval_str(&interp, "(put! 'apple 'color 'red)");
+    eval_str(&interp, "(put! 'apple 'weight 150)");
+    eval_str(&interp, "(put! 'banana 'color 'yellow)");
+    eval_str(&interp, "(put! 'apple 'color 'green)");
+    let inputs = vec![
+        ("(get 'apple 'color)", interp.symbol("green")),
+        ("(get 'apple 'weight)", Value::Number(Number::Int(150))),
+        ("(get 'banana 'color)", interp.symbol("yellow")),
+        ("(get 'banana 'weight)", Value::Boolean(false)),
+        ("(get 'cherry 'color)", Value::Boolean(false)),
+        ("(unspecified? (put! 'cherry 'color 'red))", Value::Boolean(true)),
+        ("(get 'cherry 'color)", interp.symbol("red")),
+        ("(get 'apple 'color)", interp.symbol("green")),
+        ("(unspecified? (put! 'cherry 2 'exact))", Value::Boolean(true)),
+        ("(get 'cherry 2.0)", Value::Boolean(false)),
+        ("(get 'cherry 2)", interp.symbol("exact")),
+    ];
+    check_exprs(&interp, &inputs);
+
+    // Property lists survive a collection, and go away on reset.
+    eval_str(&interp, "(put! 'apple 'sizes (list 1 2 3))");
+    eval_str(&interp, "(gc)");
+    assert_eq!(interp.write(eval_str(&interp, "(get 'apple 'sizes)")), "(1 2 3)");
+    interp.reset();
+    assert_eq!(eval_str(&interp, "(get 'apple 'color)"), Value::Boolean(false));
+
+    assert!(matches!(eval_err(&interp, "(put! \"apple\" 'color 'red)"), SchemeError::TypeError(_)));
+}
+
+fn primitive_documented(_interp: &Interp, _args: &[Value]) -> Result<Value, SchemeError> {
+    Ok(Value::Boolean(true))
+}
+
+#[test]
+fn test_help() {
+    let interp = Interp::new();
+    interp.define_primitive_doc("documented", primitive_documented, "(documented) returns #t.");
+    interp.define_primitive("undocumented", primitive_documented);
+    let inputs = vec![
+        ("(documented)", Value::Boolean(true)),
+        ("(string=? (help 'documented) \"(documented) returns #t.\")", Value::Boolean(true)),
+        ("(string=? (help 'car) \"(car pair) returns the first element of pair.\")", Value::Boolean(true)),
+        ("(help 'undocumented)", Value::Boolean(false)),
+        ("(help 'no-such-name)", Value::Boolean(false)),
+    ];
+    check_exprs(&interp, &inputs);
+
+    assert!(matches!(eval_err(&interp, "(help \"car\")"), SchemeError::TypeError(_)));
+}
+
+#[test]
+fn test_apropos() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define my-list-helper 1)");
+    let inputs = vec![
+        ("(list? (memq 'list (apropos \"list\")))", Value::Boolean(true)),
+        ("(list? (memq 'list? (apropos \"list\")))", Value::Boolean(true)),
+        ("(list? (memq 'list->vector (apropos \"list\")))", Value::Boolean(true)),
+        ("(list? (memq 'my-list-helper (apropos \"list\")))", Value::Boolean(true)),
+        ("(memq 'null? (apropos \"list\"))", Value::Boolean(false)),
+        ("(list? (memq 'null? (apropos \"null\")))", Value::Boolean(true)),
+        ("(equal? (apropos \"my-list\") '(my-list-helper))", Value::Boolean(true)),
+        ("(apropos \"no-such-name\")", Value::Nil),
+    ];
+    check_exprs(&interp, &inputs);
+
+    assert!(matches!(eval_err(&interp, "(apropos 'list)"), SchemeError::TypeError(_)));
+}
+
+#[test]
+fn test_hash_table_update() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define counts (make-hash-table))");
+    eval_str(&interp, "(define calls 0)");
+    eval_str(&interp, "(define zero (lambda () (set! calls (+ calls 1)) 0))");
+    eval_str(&interp, "(for-each (lambda (word) (hash-table-update! counts word (lambda (n) (+ n 1)) zero))
+        '(the cat saw the dog and the bird))");
+    let inputs = vec![
+        ("(hash-ref counts 'the)", Value::Number(Number::Int(3))),
+        ("(hash-ref counts 'cat)", Value::Number(Number::Int(1))),
+        ("(hash-count counts)", Value::Number(Number::Int(6))),
+        // The default thunk only runs for missing keys.
+        ("calls", Value::Number(Number::Int(6))),
+        ("(hash-table-update! counts 'cat (lambda (n) (* n 10)))", Value::Number(Number::Int(10))),
+        ("(hash-ref counts 'cat)", Value::Number(Number::Int(10))),
+    ];
+    check_exprs(&interp, &inputs);
+
+    assert!(matches!(eval_err(&interp, "(hash-table-update! counts 'fish (lambda (n) n))"), SchemeError::EvalError(_)));
+}
+
+#[test]
+fn test_read_eval_hash_table_walk_fold() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define table (make-hash-table))");
+    eval_str(&interp, "(hash-set! table 1 10)");
+    eval_str(&interp, "(hash-set! table 2 20)");
+    eval_str(&interp, "(hash-set! table 3 30)");
+    eval_str(&interp, "(define seen '())");
+    eval_str(&interp, "(hash-table-walk table (lambda (k v) (set! seen (cons k seen))))");
+    let inputs = vec![
+        ("(length seen)", Value::Number(Number::Int(3))),
+        ("(equal? (sort seen <) '(1 2 3))", Value::Boolean(true)),
+        ("(hash-table-fold table (lambda (k v acc) (+ v acc)) 0)", Value::Number(Number::Int(60))),
+        ("(hash-table-fold (make-hash-table) (lambda (k v acc) (+ v acc)) 0)", Value::Number(Number::Int(0))),
+    ];
+    check_exprs(&interp, &inputs);
+
+    // Mutating the table while walking it is safe.
+    eval_str(&interp, "(hash-table-walk table (lambda (k v) (hash-delete! table k)))");
+    assert_eq!(eval_str(&interp, "(hash-count table)"), Value::Number(Number::Int(0)));
+}
+
+#[test]
+fn test_read_eval_cond_case() {
+    let interp = Interp::new();
+    let inputs = vec![
+        ("(cond ((> 1 2) 'a) ((< 1 2) 10) (else 20))", Value::Number(Number::Int(10))),
+        ("(cond ((> 1 2) 'a) (else 20))", Value::Number(Number::Int(20))),
+        ("(cond (42))", Value::Number(Number::Int(42))),
+        ("(case (* 2 3) ((2 3 5 7) 1) ((1 4 6 8 9) 2) (else 3))", Value::Number(Number::Int(2))),
+        ("(case 'x ((a) 1) (else 3))", Value::Number(Number::Int(3))),
+        ("(case 2.0 ((2) 1) (else 3))", Value::Number(Number::Int(3))),
+        ("(eqv? 2 2.0)", Value::Boolean(false)),
+        ("(eqv? 'a 'a)", Value::Boolean(true)),
+        ("(cond (#f 1) ((cdr '(1 2)) => car) (else #f))", Value::Number(Number::Int(2))),
+        ("(cond ((> 1 2) => car) ((+ 1 2) => (lambda (x) (* x 10))))", Value::Number(Number::Int(30))),
+        ("(case 6 ((1 2 3) => -) ((4 5 6) => (lambda (x) (* x x))) (else 0))", Value::Number(Number::Int(36))),
+        ("(case 9 ((1 2 3) 'small) (else => (lambda (x) (+ x 1))))", Value::Number(Number::Int(10))),
+    ];
+    check_exprs(&interp, &inputs);
+    assert!(interp.take_warnings().is_empty());
+
+    assert!(matches!(eval_err(&interp, "(cond (1 => car cdr))"), SchemeError::EvalError(_)));
+}
+
+#[test]
+fn test_unspecified_values() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define x 1)");
+    eval_str(&interp, "(define port (open-output-string))");
+    let inputs = vec![
+        ("(unspecified? (if #f #f))", Value::Boolean(true)),
+        ("(eq? (if #f #f) '())", Value::Boolean(false)),
+        ("(null? (if #f #f))", Value::Boolean(false)),
+        ("(if #t 1)", Value::Number(Number::Int(1))),
+        ("(unspecified? (set! x 2))", Value::Boolean(true)),
+        ("x", Value::Number(Number::Int(2))),
+        ("(unspecified? (when #f 1))", Value::Boolean(true)),
+        ("(when (> x 1) 'a 'b)", interp.symbol("b")),
+        ("(unspecified? (unless #t 1))", Value::Boolean(true)),
+        ("(unless #f 'c)", interp.symbol("c")),
+        ("(unspecified? (cond (#f 1)))", Value::Boolean(true)),
+        ("(unspecified? (case 3 ((1) 'one)))", Value::Boolean(true)),
+        ("(unspecified? '())", Value::Boolean(false)),
+        ("(unspecified? (begin))", Value::Boolean(true)),
+        ("(unspecified? (fluid-let ((x 3))))", Value::Boolean(true)),
+        ("(unspecified? (for-each car '((1))))", Value::Boolean(true)),
+        ("(unspecified? (vector-for-each car (vector '(1))))", Value::Boolean(true)),
+        ("(unspecified? (write 1 port))", Value::Boolean(true)),
+        ("(unspecified? (write-shared 1 port))", Value::Boolean(true)),
+        ("(unspecified? (write-simple 1 port))", Value::Boolean(true)),
+        ("(unspecified? (display 1 port))", Value::Boolean(true)),
+        ("(unspecified? (newline port))", Value::Boolean(true)),
+    ];
+    check_exprs(&interp, &inputs);
+    assert_eq!(interp.write(eval_str(&interp, "(if #f #f)")), "#!unspecified");
+}
+
+#[test]
+fn test_case_duplicate_datum() {
+    let interp = Interp::new();
+    let text = "(case 1 ((1 2) 'first) ((3 1) 'second) (else 'other))";
+    assert_eq!(eval_str(&interp, text), interp.symbol("first"));
+    let warnings = interp.take_warnings();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("duplicate datum 1"), "{}", warnings[0]);
+
+    interp.set_warnings_as_errors(true);
+    assert!(matches!(eval_err(&interp, text), SchemeError::EvalError(_)));
+}
+
+#[test]
+fn test_primitive_redefinition_warning() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define + -)");
+    eval_str(&interp, "(define + *)");
+    assert_eq!(eval_str(&interp, "(+ 2 3)"), Value::Number(Number::Int(6)));
+    let warnings = interp.take_warnings();
+    assert_eq!(warnings, vec!["define: redefining the built-in primitive +.".to_string()]);
+
+    // Only global defines of primitives warn, not new names or local defines.
+    eval_str(&interp, "(define my-car car)");
+    eval_str(&interp, "(define f (lambda (l) (define car cdr) (car l)))");
+    eval_str(&interp, "(f '(1 2))");
+    eval_str(&interp, "(define my-car cdr)");
+    assert!(interp.take_warnings().is_empty());
+
+    eval_str(&interp, "(define-constant abs -)");
+    assert_eq!(interp.take_warnings(), vec!["define: redefining the built-in primitive abs.".to_string()]);
+
+    interp.set_warn_on_redefinition(false);
+    eval_str(&interp, "(define car cdr)");
+    assert!(interp.take_warnings().is_empty());
+    interp.set_warn_on_redefinition(true);
+
+    // A handler receives the warnings instead, define-constant's included.
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let sink = Rc::clone(&received);
+    interp.set_warning_handler(move |message| sink.borrow_mut().push(message.to_string()));
+    eval_str(&interp, "(define max min)");
+    eval_str(&interp, "(define-constant min max)");
+    assert_eq!(*received.borrow(), vec![
+        "define: redefining the built-in primitive max.".to_string(),
+        "define: redefining the built-in primitive min.".to_string(),
+    ]);
+    assert!(interp.take_warnings().is_empty());
+
+    interp.set_warnings_as_errors(true);
+    assert!(matches!(eval_err(&interp, "(define cdr car)"), SchemeError::EvalError(_)));
+}
+
+#[test]
+fn test_warning_handler() {
+    let interp = Interp::new();
+    // Without a handler, only the most recent warnings are kept.
+    for i in 0..150 {
+        interp.warn(&format!("warning {}", i)).unwrap();
+    }
+    let warnings = interp.take_warnings();
+    assert_eq!(warnings.len(), 100);
+    assert_eq!(warnings[0], "warning 50");
+    assert!(interp.take_warnings().is_empty());
+
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let sink = Rc::clone(&received);
+    interp.set_warning_handler(move |message| sink.borrow_mut().push(message.to_string()));
+    eval_str(&interp, "(case 1 ((1) 'a) ((1) 'b))");
+    assert_eq!(received.borrow().len(), 1);
+    assert!(received.borrow()[0].contains("duplicate datum 1"), "{}", received.borrow()[0]);
+    assert!(interp.take_warnings().is_empty());
+}
+
+#[test]
+fn test_write_and_display() {
+    let interp = Interp::new();
+    for (text, written, displayed) in [
+        ("\"hi\"", "\"hi\"", "hi"),
+        ("#\\a", "#\\a", "a"),
+        ("#\\space", "#\\space", " "),
+        ("'(1 \"two\" #\\c)", "(1 \"two\" #\\c)", "(1 two c)"),
+        ("'sym", "sym", "sym"),
+        ("2.5", "2.5", "2.5"),
+        ("\"a\\\"b\"", "\"a\\\"b\"", "a\"b"),
+        ("\"a\\nb\"", "\"a\\nb\"", "a\nb"),
+        ("'(quote a)", "'a", "'a"),
+        ("''(1 2)", "'(1 2)", "'(1 2)"),
+        ("'(quasiquote (a (unquote b) (unquote-splicing c)))", "`(a ,b ,@c)", "`(a ,b ,@c)"),
+        ("'(quote a b)", "(quote a b)", "(quote a b)"),
+        ("'(quote)", "(quote)", "(quote)"),
+        ("'(a quote b)", "(a quote b)", "(a quote b)"),
+    ] {
+        let value = eval_str(&interp, text);
+        assert_eq!(interp.write(value), written, "{}", text);
+        assert_eq!(interp.display(value), displayed, "{}", text);
+    }
+}
+
+#[test]
+fn test_write_read_round_trip() {
+    let interp = Interp::new();
+    for text in ["", "plain", "a\"b", "back\\slash", "two\nlines", "tab\tand\rreturn", "\"\\\n\"\\"] {
+        let value = interp.heap.borrow_mut().alloc_string(text.to_string()).unwrap();
+        let written = interp.write(value);
+        let read = Parser::new(written.as_bytes()).read(&interp).unwrap();
+        let mut buf = String::new();
+        assert!(interp.is_string(read, &mut buf), "{}", written);
+        assert_eq!(buf, text, "{}", written);
+    }
+}
+
+#[test]
+fn test_output_string_port() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define port (open-output-string))");
+    eval_str(&interp, "(display \"x\" port)");
+    let mut buf = String::new();
+    assert!(interp.is_string(eval_str(&interp, "(get-output-string port)"), &mut buf));
+    assert_eq!(buf, "x");
+
+    eval_str(&interp, "(write \"y\" port)");
+    eval_str(&interp, "(newline port)");
+    eval_str(&interp, "(display #\\z port)");
+    assert!(interp.is_string(eval_str(&interp, "(get-output-string port)"), &mut buf));
+    assert_eq!(buf, "x\"y\"\nz");
+
+    assert!(matches!(eval_err(&interp, "(display 1 'not-a-port)"), SchemeError::TypeError(_)));
+}
+
+#[test]
+fn test_flush_output_port() {
+    let interp = Interp::new();
+    // Flushing stdout, the default port, is always possible.
+    assert_eq!(eval_str(&interp, "(flush-output-port)"), Value::Unspecified);
+
+    // Flushing an output string leaves its text as is.
+    eval_str(&interp, "(define port (open-output-string))");
+    eval_str(&interp, "(display \"abc\" port)");
+    assert_eq!(eval_str(&interp, "(flush-output-port port)"), Value::Unspecified);
+    let mut buf = String::new();
+    assert!(interp.is_string(eval_str(&interp, "(get-output-string port)"), &mut buf));
+    assert_eq!(buf, "abc");
+
+    for text in ["(flush-output-port 'not-a-port)", "(flush-output-port (open-input-string \"a\"))"] {
+        assert!(matches!(eval_err(&interp, text), SchemeError::TypeError(_)), "{}", text);
+    }
+}
+
+#[test]
+fn test_profile_report() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define square (lambda (x) (* x x)))");
+    eval_str(&interp, "(square 1)");
+    eval_str(&interp, "(set-profiling! #t)");
+    eval_str(&interp, "(square 2)");
+    eval_str(&interp, "(square 3)");
+    eval_str(&interp, "(square 4)");
+    eval_str(&interp, "(set-profiling! #f)");
+    eval_str(&interp, "(square 5)");
+    let report = interp.list_to_vec(eval_str(&interp, "(profile-report)")).unwrap();
+    let entry = |name: &str| {
+        report.iter()
+            .map(|row| interp.list_to_vec(*row).unwrap())
+            .find(|row| row[0] == interp.symbol(name))
+            .unwrap_or_else(|| panic!("No profile entry for {}", name))
+    };
+    assert_eq!(entry("square")[1], Value::Number(Number::Int(3)));
+    assert_eq!(entry("*")[1], Value::Number(Number::Int(3)));
+    assert!(matches!(entry("square")[2], Value::Number(Number::Float(_))));
+}
+
+#[test]
+fn test_gc_stats() {
+    let interp = Interp::new();
+    // The total, live, free and symbols counts from one call to gc-stats.
+    let stats = || -> Vec<usize> {
+        let entries = interp.list_to_vec(eval_str(&interp, "(gc-stats)")).unwrap();
+        ["total", "live", "free", "symbols"].iter().zip(entries).map(|(name, entry)| {
+            match interp.to_pair(entry).unwrap() {
+                (key, Value::Number(Number::Int(count))) if key == interp.symbol(name) => count as usize,
+                _ => panic!("Unexpected {} entry {}", name, interp.write(entry)),
+            }
+        }).collect()
+    };
+    let before = stats();
+    assert_eq!(before[1] + before[2], before[0]);
+    assert_eq!(before[3], interp.heap.borrow().symbol_count());
+
+    eval_str(&interp, "(define items (list 1 2 3 4 5 6 7 8 9 10))");
+    eval_str(&interp, "(define a-new-symbol 'another-new-symbol)");
+    let after = stats();
+    assert!(after[1] >= before[1] + 10);
+    assert_eq!(after[1] + after[2], after[0]);
+    assert_eq!(after[3], before[3] + 3);
+
+    // A reset reclaims everything allocated since the interpreter started.
+    interp.reset();
+    let reset = stats();
+    assert!(reset[0] <= before[0]);
+    assert_eq!(reset[1] + reset[2], reset[0]);
+}
+
+#[test]
+fn test_weak_hash_table() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define cache (make-weak-hash-table))");
+    eval_str(&interp, "(define strong (make-hash-table))");
+    eval_str(&interp, "(define key (list 1 2))");
+    eval_str(&interp, "(define kept (list 3 4))");
+    eval_str(&interp, "(define cyclic (list 5))");
+    eval_str(&interp, "(define lookup (lambda (table k) (hash-table-ref/default table k 'missing)))");
+    eval_str(&interp, "(hash-set! cache key 'value)");
+    eval_str(&interp, "(hash-set! cache kept 'kept)");
+    eval_str(&interp, "(hash-set! cache 'symbol 'interned)");
+    eval_str(&interp, "(hash-set! cache 42 'number)");
+    // A value referring to its own key doesn't keep the entry alive.
+    eval_str(&interp, "(hash-set! cache cyclic (list cyclic))");
+    eval_str(&interp, "(hash-set! strong (list 6) 'strong)");
+    assert_eq!(eval_str(&interp, "(hash-count cache)"), Value::Number(Number::Int(5)));
+
+    eval_str(&interp, "(set! key #f)");
+    eval_str(&interp, "(set! cyclic #f)");
+    // Collection only runs once the evaluation calling gc is done.
+    eval_str(&interp, "(begin (gc) (hash-count cache))");
+    let inputs = vec![
+        ("(hash-count cache)", Value::Number(Number::Int(3))),
+        ("(lookup cache kept)", interp.symbol("kept")),
+        ("(lookup cache 'symbol)", interp.symbol("interned")),
+        ("(lookup cache 42)", interp.symbol("number")),
+        ("(hash-count strong)", Value::Number(Number::Int(1))),
+        ("(hash-table? cache)", Value::Boolean(true)),
+    ];
+    check_exprs(&interp, &inputs);
+    assert_eq!(interp.write(eval_str(&interp, "cache")).split(' ').next(), Some("<weak-hash-table"));
+
+    // Freed slots are reused by the next allocations.
+    let free = || match eval_str(&interp, "(cdr (assq 'free (gc-stats)))") {
+        Value::Number(Number::Int(count)) => count,
+        value => panic!("Unexpected free count {:?}", value),
+    };
+    assert!(free() > 0);
+    let before = free();
+    eval_str(&interp, "(define more (list 1 2 3 4 5 6 7 8 9 10))");
+    assert!(free() < before);
+    assert_eq!(interp.write(eval_str(&interp, "more")), "(1 2 3 4 5 6 7 8 9 10)");
+}
+
+#[test]
+fn test_read_input_string_port() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define port (open-input-string \"(1 2) 3 ; done\"))");
+    let inputs = vec![
+        ("(equal? (read port) '(1 2))", Value::Boolean(true)),
+        ("(read port)", Value::Number(Number::Int(3))),
+        ("(eof-object? (read port))", Value::Boolean(true)),
+        ("(eof-object? (read port))", Value::Boolean(true)),
+        ("(eof-object? (eof-object))", Value::Boolean(true)),
+        ("(eof-object? ())", Value::Boolean(false)),
+        ("(read (open-input-string \"()\"))", Value::Nil),
+    ];
+    check_exprs(&interp, &inputs);
+
+    // A long port is read form by form from where the last read stopped.
+    let text = "(a b) 12 \"str\" ".repeat(10_000);
+    let port = interp.heap.borrow_mut().alloc_input_string(text).unwrap();
+    let mut count = 0;
+    while interp.read_port(port).unwrap() != Value::Eof {
+        count += 1;
+    }
+    assert_eq!(count, 30_000);
+    interp.define("port", port);
+    assert_eq!(eval_str(&interp, "(read-string 1 port)"), Value::Eof);
+}
+
+#[test]
+fn test_read_string_u8() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define port (open-input-string \"hello world\"))");
+    let inputs = vec![
+        ("(equal? (read-string 5 port) \"hello\")", Value::Boolean(true)),
+        ("(equal? (read-string 0 port) \"\")", Value::Boolean(true)),
+        // Only the remaining characters are read when the port runs out.
+        ("(equal? (read-string 10 port) \" world\")", Value::Boolean(true)),
+        ("(eof-object? (read-string 1 port))", Value::Boolean(true)),
+    ];
+    check_exprs(&interp, &inputs);
+
+    eval_str(&interp, "(define bytes (open-input-bytevector #u8(1 255)))");
+    let inputs = vec![
+        ("(read-u8 bytes)", Value::Number(Number::Int(1))),
+        ("(read-u8 bytes)", Value::Number(Number::Int(255))),
+        ("(eof-object? (read-u8 bytes))", Value::Boolean(true)),
+        ("(eof-object? (read-u8 bytes))", Value::Boolean(true)),
+    ];
+    check_exprs(&interp, &inputs);
+
+    for text in ["(read-u8 port)", "(read-string 1 bytes)", "(open-input-bytevector \"abc\")"] {
+        assert!(matches!(eval_err(&interp, text), SchemeError::TypeError(_)), "{}", text);
+    }
+}
+
+#[test]
+fn test_read_eval_slice() {
+    let interp = Interp::new();
+    let inputs = vec![
+        ("(equal? (slice '(1 2 3 4) 1 -1) '(2 3))", Value::Boolean(true)),
+        ("(equal? (slice '(1 2 3 4) 1 3) '(2 3))", Value::Boolean(true)),
+        ("(equal? (slice '(1 2 3 4) -2) '(3 4))", Value::Boolean(true)),
+        ("(equal? (slice '(1 2 3 4) -10 10) '(1 2 3 4))", Value::Boolean(true)),
+        ("(slice '(1 2 3 4) 3 1)", Value::Nil),
+        ("(equal? (slice #(1 2 3 4) 1 -1) #(2 3))", Value::Boolean(true)),
+        ("(equal? (slice (vector 1 2 3 4) -3 2) #(2))", Value::Boolean(true)),
+        ("(vector-length (slice #(1 2 3 4) 2 100))", Value::Number(Number::Int(2))),
+        ("(vector-ref #(a b c) 1)", eval_str(&interp, "'b")),
+        ("(equal? (vector->list #(1 2)) '(1 2))", Value::Boolean(true)),
+        ("(equal? (list->vector '(1 2)) #(1 2))", Value::Boolean(true)),
+    ];
+    check_exprs(&interp, &inputs);
+    assert_eq!(interp.write(eval_str(&interp, "#(1 \"a\" #\\b)")), "#(1 \"a\" #\\b)");
+}
+
+#[test]
+fn test_type_predicates() {
+    let inputs = vec![
+        ("(symbol? 'a)", Value::Boolean(true)),
+        ("(symbol? \"a\")", Value::Boolean(false)),
+        ("(string? \"a\")", Value::Boolean(true)),
+        ("(string? 'a)", Value::Boolean(false)),
+        ("(pair? '(1 . 2))", Value::Boolean(true)),
+        ("(pair? ())", Value::Boolean(false)),
+        ("(procedure? car)", Value::Boolean(true)),
+        ("(procedure? (lambda () 1))", Value::Boolean(true)),
+        ("(procedure? '(lambda () 1))", Value::Boolean(false)),
+        ("(char? #\\a)", Value::Boolean(true)),
+        ("(char? \"a\")", Value::Boolean(false)),
+        ("(vector? #(1 2))", Value::Boolean(true)),
+        ("(vector? '(1 2))", Value::Boolean(false)),
+        ("(boolean? #f)", Value::Boolean(true)),
+        ("(boolean? 0)", Value::Boolean(false)),
+    ];
+    let interp = Interp::new();
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_primitives_as_values() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define lst '(3 -1 2 0))");
+    eval_str(&interp, "(define less <)");
+    let inputs = vec![
+        ("(equal? (sort '(3 1 2) <) '(1 2 3))", Value::Boolean(true)),
+        ("(equal? (sort lst >) '(3 2 0 -1))", Value::Boolean(true)),
+        ("(equal? (sort #(3 1 2) less) #(1 2 3))", Value::Boolean(true)),
+        ("(equal? (sort '((b . 1) (a . 1) (c . 0)) (lambda (x y) (< (cdr x) (cdr y))))
+            '((c . 0) (b . 1) (a . 1)))", Value::Boolean(true)),
+        ("(equal? (filter (lambda (x) (> x 0)) lst) '(3 2))", Value::Boolean(true)),
+        ("(equal? (map - lst) '(-3 1 -2 0))", Value::Boolean(true)),
+        ("(equal? (map = '(1 2) '(1 3)) '(#t #f))", Value::Boolean(true)),
+        ("(apply max lst)", Value::Number(Number::Int(3))),
+        ("(fold * 1 '(1 2 3 4))", Value::Number(Number::Int(24))),
+        ("((if (> 1 0) + -) 5 3)", Value::Number(Number::Int(8))),
+    ];
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_read_eval_truthiness() {
+    let interp = Interp::new();
+    let inputs = vec![
+        ("(if 0 1 2)", Value::Number(Number::Int(1))),
+        ("(if () 1 2)", Value::Number(Number::Int(1))),
+        ("(if #f 1 2)", Value::Number(Number::Int(2))),
+        ("(if \"\" 1 2)", Value::Number(Number::Int(1))),
+        ("(cond (() 1) (else 2))", Value::Number(Number::Int(1))),
+        ("(and 1 () 3)", Value::Number(Number::Int(3))),
+        ("(and 1 #f 3)", Value::Boolean(false)),
+        ("(and)", Value::Boolean(true)),
+        ("(or #f 0)", Value::Number(Number::Int(0))),
+        ("(or #f #f)", Value::Boolean(false)),
+        ("(or)", Value::Boolean(false)),
+        ("(not 0)", Value::Boolean(false)),
+        ("(not #f)", Value::Boolean(true)),
+        ("(length (filter (lambda (x) x) '(0 () #f #t)))", Value::Number(Number::Int(3))),
+    ];
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_read_eval_string_search() {
+    let interp = Interp::new();
+    let inputs = vec![
+        ("(string-prefix? \"sch\" \"scheme\")", Value::Boolean(true)),
+        ("(string-prefix? \"eme\" \"scheme\")", Value::Boolean(false)),
+        ("(string-prefix? \"\" \"scheme\")", Value::Boolean(true)),
+        ("(string-prefix? \"schemer\" \"scheme\")", Value::Boolean(false)),
+        ("(string-suffix? \"eme\" \"scheme\")", Value::Boolean(true)),
+        ("(string-suffix? \"sch\" \"scheme\")", Value::Boolean(false)),
+        ("(string-suffix? \"\" \"\")", Value::Boolean(true)),
+        ("(substring? \"hem\" \"scheme\")", Value::Boolean(true)),
+        ("(substring? \"hex\" \"scheme\")", Value::Boolean(false)),
+        ("(substring? \"\" \"scheme\")", Value::Boolean(true)),
+    ];
+    check_exprs(&interp, &inputs);
+}
+
+#[test]
+fn test_read_eval_string_index_count() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define vowel? (lambda (ch) (if (memv ch '(#\\a #\\e #\\i #\\o #\\u)) #t #f)))");
+    let inputs = vec![
+        ("(string-count \"the quick brown fox\" vowel?)", Value::Number(Number::Int(5))),
+        ("(string-count \"the quick brown fox\" #\\space)", Value::Number(Number::Int(3))),
+        ("(string-count \"\" vowel?)", Value::Number(Number::Int(0))),
+        ("(string-index \"the quick brown fox\" #\\space)", Value::Number(Number::Int(3))),
+        ("(string-index-right \"the quick brown fox\" #\\space)", Value::Number(Number::Int(15))),
+        ("(string-index \"the quick brown fox\" vowel?)", Value::Number(Number::Int(2))),
+        ("(string-index-right \"the quick brown fox\" vowel?)", Value::Number(Number::Int(17))),
+        ("(string-index \"xyz\" vowel?)", Value::Boolean(false)),
+        ("(string-index-right \"xyz\" #\\a)", Value::Boolean(false)),
+    ];
+    check_exprs(&interp, &inputs);
+
+    // Positions count characters, so they agree with read-string on non-ASCII text.
+    interp.define("text", interp.string("héllo wörld").unwrap());
+    let inputs = vec![
+        ("(string-index text #\\space)", Value::Number(Number::Int(5))),
+        ("(string-index-right text #\\l)", Value::Number(Number::Int(9))),
+        ("(string-count text vowel?)", Value::Number(Number::Int(1))),
+    ];
+    check_exprs(&interp, &inputs);
+    assert_eq!(
+        interp.display(eval_str(&interp, "(read-string (string-index text #\\space) (open-input-string text))")),
+        "héllo"
+    );
+
+    for text in ["(string-count 'abc vowel?)", "(string-index \"abc\" 'a)"] {
+        assert!(matches!(eval_err(&interp, text), SchemeError::TypeError(_)), "{}", text);
+    }
+}
+
+#[test]
+fn test_read_eval_string_take_drop() {
+    let interp = Interp::new();
+    let inputs = vec![
+        ("(string-take \"hello\" 2)", "\"he\""),
+        ("(string-drop \"hello\" 2)", "\"llo\""),
+        ("(string-take-right \"hello\" 2)", "\"lo\""),
+        ("(string-drop-right \"hello\" 2)", "\"hel\""),
+        ("(string-take \"hello\" 0)", "\"\""),
+        ("(string-drop \"hello\" 5)", "\"\""),
+        ("(string-take-right \"hello\" 5)", "\"hello\""),
+    ];
+    for (text, expected) in inputs {
+        assert_eq!(interp.write(eval_str(&interp, text)), expected, "{}", text);
+    }
+
+    for (text, message) in [
+        ("(string-take \"hello\" 6)", "string-take: count 6 out of range for length 5."),
+        ("(string-drop-right \"hello\" -1)", "string-drop-right: count -1 out of range for length 5."),
+    ] {
+        assert_eq!(eval_err(&interp, text), SchemeError::EvalError(message.to_string()), "{}", text);
+    }
+    assert!(matches!(eval_err(&interp, "(string-take 'hello 1)"), SchemeError::TypeError(_)));
+}
+
+#[test]
+fn test_char_output() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define port (open-output-string))");
+    eval_str(&interp, "(display #\\A port)");
+    eval_str(&interp, "(write #\\newline port)");
+    eval_str(&interp, "(write #\\space port)");
+    eval_str(&interp, "(write #\\x01 port)");
+    eval_str(&interp, "(display #\\( port)");
+    let mut buf = String::new();
+    assert!(interp.is_string(eval_str(&interp, "(get-output-string port)"), &mut buf));
+    assert_eq!(buf, "A#\\newline#\\space#\\x01(");
+
+    // Written characters read back as the same character.
+    for byte in 0..=127u8 {
+        let text = interp.write(Value::Char(byte));
+        assert_eq!(eval_str(&interp, &text), Value::Char(byte), "{}", text);
+    }
+}
+
+#[test]
+fn test_read_eval_syntax_rules() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define-syntax swap! (syntax-rules ()
+        ((_ a b) ((lambda (tmp) (set! a b) (set! b tmp)) a))))");
+    eval_str(&interp, "(define-syntax my-or (syntax-rules ()
+        ((_) #f)
+        ((_ e) e)
+        ((_ e rest ...) ((lambda (t) (if t t (my-or rest ...))) e))))");
+    eval_str(&interp, "(define-syntax pair-up (syntax-rules (=>)
+        ((_ a => b) (cons a b))
+        ((_ a b) (list a b))))");
+    eval_str(&interp, "(define x 1)");
+    eval_str(&interp, "(define y 2)");
+    let inputs = vec![
+        ("(begin (swap! x y) (list x y))", "(2 1)"),
+        ("(my-or)", "#f"),
+        ("(my-or #f 3 (car '()))", "3"),
+        ("(pair-up 1 => 2)", "(1 . 2)"),
+        ("(pair-up 1 2)", "(1 2)"),
+    ];
+    for (text, expected) in inputs {
+        assert_eq!(interp.write(eval_str(&interp, text)), expected, "{}", text);
+    }
+    assert!(matches!(eval_err(&interp, "(pair-up 1 2 3)"), SchemeError::SyntaxError(_)));
+
+    // A use is expanded the first time it's evaluated only, and the expansion
+    // is kept across collections for as long as the use is reachable.
+    eval_str(&interp, "(define twice (lambda (n) (pair-up n n)))");
+    eval_str(&interp, "(twice 1)");
+    let expr = Parser::new("(twice 2)".as_bytes()).read(&interp).unwrap();
+    let allocations = interp.heap.borrow().allocation_count();
+    interp.eval(expr).unwrap();
+    // Only the list the expansion builds.
+    assert_eq!(interp.heap.borrow().allocation_count() - allocations, 2);
+    eval_str(&interp, "(gc)");
+    assert_eq!(interp.write(eval_str(&interp, "(twice 3)")), "(3 3)");
+}
+
+#[test]
+fn test_read_eval_let_syntax() {
+    let interp = Interp::new();
+    let inputs = vec![
+        ("(let-syntax ((double (syntax-rules () ((_ x) (* 2 x))))) (double 21))", Value::Number(Number::Int(42))),
+        ("(letrec-syntax ((my-and (syntax-rules ()
+            ((_) #t)
+            ((_ e) e)
+            ((_ e rest ...) (if e (my-and rest ...) #f)))))
+          (my-and 1 2 3))", Value::Number(Number::Int(3))),
+    ];
+    check_exprs(&interp, &inputs);
+    // The macro is not bound outside of the let-syntax body.
+    assert!(matches!(eval_err(&interp, "(double 21)"), SchemeError::UnboundVariable(_)));
+}
+
+#[test]
+fn test_read_eval_syntax_rules_nested_ellipsis() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define-syntax my-let (syntax-rules ()
+        ((_ ((name value) ...) body1 body2 ...) ((lambda (name ...) body1 body2 ...) value ...))))");
+    eval_str(&interp, "(define-syntax my-cond (syntax-rules (else)
+        ((_ (else e ...)) (begin e ...))
+        ((_ (test e ...) clause ...) (if test (begin e ...) (my-cond clause ...)))))");
+    eval_str(&interp, "(define-syntax area (syntax-rules ()
+        ((_ side) (* side side))
+        ((_ width height) (* width height))))");
+    // Each group keeps its own items, and a variable under fewer ellipses is repeated.
+    eval_str(&interp, "(define-syntax tag-all (syntax-rules ()
+        ((_ tag (group item ...) ...) '((group (tag item) ...) ...))))");
+    eval_str(&interp, "(define-syntax flatten (syntax-rules ()
+        ((_ (item ...) ...) '(item ... ...))))");
+    eval_str(&interp, "(define-syntax swap-vector (syntax-rules ()
+        ((_ #(a b ...)) '#(b ... a))))");
+    let inputs = vec![
+        ("(my-let ((x 1) (y 2)) (+ x y))", "3"),
+        ("(my-let () 1 2)", "2"),
+        ("(my-cond (#f 1) ((= 1 1) 2 3) (else 4))", "3"),
+        ("(my-cond (#f 1) (else 4))", "4"),
+        ("(area 3)", "9"),
+        ("(area 3 4)", "12"),
+        ("(tag-all t (a 1 2) (b) (c 3))", "((a (t 1) (t 2)) (b) (c (t 3)))"),
+        ("(flatten (1 2) () (3))", "(1 2 3)"),
+        ("(swap-vector #(1 2 3))", "#(2 3 1)"),
+    ];
+    for (text, expected) in inputs {
+        assert_eq!(interp.write(eval_str(&interp, text)), expected, "{}", text);
+    }
+    for text in ["(area 1 2 3)", "(my-let ((x 1)))"] {
+        assert!(matches!(eval_err(&interp, text), SchemeError::SyntaxError(_)), "{}", text);
+    }
+}
+
+#[test]
+fn test_read_eval_quasiquote() {
+    let interp = Interp::new();
+    eval_str(&interp, "(define x 42)");
+    eval_str(&interp, "(define ys (list 1 2))");
+    let inputs = vec![
+        ("`(a ,x)", "(a 42)"),
+        ("`(a ,@ys b)", "(a 1 2 b)"),
+        ("`(a . ,x)", "(a . 42)"),
+        ("`(,@ys . tail)", "(1 2 . tail)"),
+        ("`(,@'() ,@ys)", "(1 2)"),
+        ("`#(1 ,x ,@ys)", "#(1 42 1 2)"),
+        ("`(1 #(,x) (,(+ x 1)))", "(1 #(42) (43))"),
+        // The inner unquote belongs to the inner quasiquote and is left alone.
+        ("`(a `(b ,(c ,x)))", "(a `(b ,(c 42)))"),
+        ("`(a `(b ,(c ,@ys)))", "(a `(b ,(c 1 2)))"),
+        ("`(a `(b ,,x))", "(a `(b ,42))"),
+        ("`(a `(b ,(c x)))", "(a `(b ,(c x)))"),
+        ("(quasiquote (1 (unquote (+ 1 1))))", "(1 2)"),
+        ("`,x", "42"),
+    ];
+    for (text, expected) in inputs {
+        assert_eq!(interp.write(eval_str(&interp, text)), expected, "{}", text);
+    }
+    // The spliced list is copied, not shared with the result.
+    eval_str(&interp, "(define joined `(0 ,@ys))");
+    assert_eq!(eval_str(&interp, "(eq? (cdr joined) ys)"), Value::Boolean(false));
+}
@@ -1,4 +1,4 @@
-use crate::{interp::Interp, parser::Parser};
+use crate::{interp::Interp, parser::Parser, types::{Number, SchemeError, Value}};
 
 
 #[test]
@@ -8,12 +8,13 @@ fn test_parse_some_exprs() {
     let inputs = vec![
         "(* 2 3)",
         "(1 2 3)",
-        "((lambda (x) (+ x 1)) 2)"
+        "((lambda (x) (+ x 1)) 2)",
+        "(_ x ... (y ...))",
     ];
     for text in inputs {
         let mut parser = Parser::new(text.as_bytes());
         let expr = parser.read(&interp);
-        assert!(matches!(expr, Ok(_)));
+        assert!(expr.is_ok());
     }
 }
 
@@ -23,10 +24,117 @@ fn test_parse_fails() {
 
     let inputs = vec![
         "(*, 2 3)",
+        "(a,b)",
+        "`(a ,)",
     ];
     for text in inputs {
         let mut parser = Parser::new(text.as_bytes());
         let expr = parser.read(&interp);
-        assert!(matches!(expr, Err(_)));
+        assert!(expr.is_err());
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_fold_case() {
+    let interp = Interp::new();
+    let eq_p = |text: &str| {
+        let expr = Parser::new(text.as_bytes()).read(&interp).unwrap();
+        interp.eval(expr).unwrap()
+    };
+    assert!(!interp.fold_case());
+    assert_eq!(eq_p("(eq? 'Foo 'foo)"), Value::Boolean(false));
+    assert_eq!(eq_p("(eq? 'foo 'foo)"), Value::Boolean(true));
+
+    interp.set_fold_case(true);
+    assert_eq!(eq_p("(eq? 'Foo 'foo)"), Value::Boolean(true));
+    interp.set_fold_case(false);
+
+    // The reader directives toggle the same setting.
+    assert_eq!(eq_p("#!fold-case (eq? 'FOO 'foo)"), Value::Boolean(true));
+    assert!(interp.fold_case());
+    assert_eq!(eq_p("#!no-fold-case (eq? 'FOO 'foo)"), Value::Boolean(false));
+    assert!(!interp.fold_case());
+
+    // A directive right before a closing paren, or at the end of the input,
+    // isn't followed by any datum.
+    assert_eq!(interp.write(eq_p("(list 1 #!fold-case)")), "(1)");
+    assert_eq!(interp.write(eq_p("(vector 'A #!no-fold-case 'B)")), "#(a B)");
+    assert_eq!(interp.write(eq_p("'#(1 #!fold-case)")), "#(1)");
+    assert_eq!(interp.write(eq_p("#u8(1 #!no-fold-case)")), "#u8(1)");
+    let mut parser = Parser::new("#!fold-case  ".as_bytes());
+    assert_eq!(parser.read(&interp), Ok(Value::Unspecified));
+    assert!(interp.fold_case());
+    assert!(parser.at_eof());
+    let mut parser = Parser::new("#!no-fold-case\nFOO #!fold-case".as_bytes());
+    assert_eq!(parser.read(&interp), Ok(interp.symbol("FOO")));
+    assert_eq!(parser.read(&interp), Ok(Value::Unspecified));
+    assert_eq!(parser.read(&interp), Ok(Value::Nil));
+    interp.set_fold_case(false);
+
+    let mut parser = Parser::new("#!bogus 1".as_bytes());
+    assert!(matches!(parser.read(&interp), Err(SchemeError::SyntaxError(_))));
+}
+
+#[test]
+fn test_parse_nesting_limit() {
+    let interp = Interp::new();
+
+    let deep = "(".repeat(1_000_000);
+    let mut parser = Parser::new(deep.as_bytes());
+    assert!(matches!(parser.read(&interp), Err(SchemeError::SyntaxError(_))));
+
+    for deep in ["#(".repeat(1_000_000), "'".repeat(1_000_000)] {
+        let mut parser = Parser::new(deep.as_bytes());
+        assert!(matches!(parser.read(&interp), Err(SchemeError::SyntaxError(_))));
+    }
+
+    let nested = |depth: usize| format!("{}{}", "(".repeat(depth), ")".repeat(depth));
+    for (depth, ok) in [(3, true), (4, false)] {
+        let text = nested(depth);
+        let mut parser = Parser::new(text.as_bytes());
+        parser.set_max_depth(3);
+        assert_eq!(parser.read(&interp).is_ok(), ok);
+    }
+
+    // The depth unwinds after each datum.
+    let text = format!("{} 42", nested(4));
+    let mut parser = Parser::new(text.as_bytes());
+    parser.set_max_depth(4);
+    assert!(parser.read(&interp).is_ok());
+    assert!(parser.read(&interp).is_ok_and(|v| v == Value::Number(Number::Int(42))));
+}
+
+#[test]
+fn test_parse_shebang() {
+    let interp = Interp::new();
+    let path = std::env::temp_dir().join(format!("shebang-{}.scm", std::process::id()));
+    std::fs::write(&path, "#!/usr/bin/env scheme\n(define x 40)\n(+ x 2)\n").unwrap();
+    let result = interp.load(path.to_str().unwrap());
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(result, Ok(Value::Number(Number::Int(42))));
+
+    let mut parser = Parser::new("#! /bin/scheme -s\n7".as_bytes());
+    assert_eq!(parser.read(&interp), Ok(Value::Number(Number::Int(7))));
+
+    // Directives at the start of the input are not shebang lines.
+    let mut parser = Parser::new("#!eof".as_bytes());
+    assert_eq!(parser.read(&interp), Ok(Value::Eof));
+
+    // Past the start of the input, #!/ is not a shebang.
+    let mut parser = Parser::new("1 #!/usr/bin/env scheme\n2".as_bytes());
+    assert!(parser.read(&interp).is_ok());
+    assert!(matches!(parser.read(&interp), Err(SchemeError::SyntaxError(_))));
+}
+
+#[test]
+fn test_parse_shebang_then_directives() {
+    let interp = Interp::new();
+    let mut parser = Parser::new("#!/usr/bin/env scheme\n#!default #!eof 5".as_bytes());
+    assert_eq!(parser.read(&interp), Ok(Value::Unspecified));
+    assert_eq!(parser.read(&interp), Ok(Value::Eof));
+    assert_eq!(parser.read(&interp), Ok(Value::Number(Number::Int(5))));
+
+    // Only the very first bytes of the input can start a shebang line.
+    let mut parser = Parser::new("\n#!/usr/bin/env scheme\n5".as_bytes());
+    assert!(matches!(parser.read(&interp), Err(SchemeError::SyntaxError(_))));
+}
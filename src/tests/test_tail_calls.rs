@@ -0,0 +1,144 @@
+use std::thread;
+
+use crate::{interp::{Interp, DEFAULT_MAX_DEPTH}, parser::Parser, types::{Number, SchemeError, Value}};
+
+// Deep enough to overflow the stack of a test thread if any of these calls
+// were not made in constant stack.
+const DEPTH: i64 = 100_000;
+
+fn eval_all(interp: &Interp, text: &str) -> Value {
+    let mut parser = Parser::new(text.as_bytes());
+    let mut result = Value::Unspecified;
+    loop {
+        match parser.read(interp) {
+            // The reader returns nil at the end of its input.
+            Ok(Value::Nil) => return result,
+            Ok(expr) => match interp.eval(expr) {
+                Ok(value) => result = value,
+                Err(e) => panic!("Eval {} failed with error: {:?}", text, e),
+            },
+            Err(e) => panic!("Parse {} failed, error: {:?}.", text, e),
+        }
+    }
+}
+
+#[test]
+fn test_tail_calls_if() {
+    let interp = Interp::new();
+    let result = eval_all(&interp, &format!("
+        (define my-even? (lambda (n) (if (= n 0) #t (my-odd? (- n 1)))))
+        (define my-odd? (lambda (n) (if (= n 0) #f (my-even? (- n 1)))))
+        (list (my-even? {depth}) (my-odd? {depth}))", depth = DEPTH));
+    assert_eq!(interp.write(result), "(#t #f)");
+}
+
+#[test]
+fn test_tail_calls_state_machine() {
+    let interp = Interp::new();
+    // Each state hands over to the next from a different tail position.
+    let result = eval_all(&interp, &format!("
+        (define state-a (lambda (n count)
+            (cond ((= n 0) count)
+                  ((even? n) (state-b (- n 1) (+ count 1)))
+                  (else (state-c (- n 1) count)))))
+        (define state-b (lambda (n count)
+            (and (>= n 0) (begin (set! count (+ count 0)) (state-c n count)))))
+        (define state-c (lambda (n count)
+            (or (and (= n 0) count)
+                (case (remainder n 3)
+                  ((0) (state-a n count))
+                  (else (when #t (state-d n count)))))))
+        (define state-d (lambda (n count)
+            (unless #f (state-a n count))))
+        (state-a {depth} 0)", depth = DEPTH));
+    assert_eq!(result, Value::Number(Number::Int(DEPTH / 2)));
+}
+
+#[test]
+fn test_tail_calls_cps() {
+    let interp = Interp::new();
+    // The continuations nest DEPTH deep, yet each one calls the next in tail position.
+    let result = eval_all(&interp, &format!("
+        (define count (lambda (n k)
+            (if (= n 0)
+                (k 0)
+                (count (- n 1) (lambda (v) (k (+ v 1)))))))
+        (count {depth} (lambda (v) v))", depth = DEPTH));
+    assert_eq!(result, Value::Number(Number::Int(DEPTH)));
+}
+
+#[test]
+fn test_tail_calls_memoized_ackermann() {
+    let interp = Interp::new();
+    let result = eval_all(&interp, "
+        (define memo (make-hash-table))
+        (define remember (lambda (key value) (hash-set! memo key value) value))
+        (define ack (lambda (m n)
+            (cond ((hash-ref memo (+ (* m 100000) n) #f) => (lambda (value) value))
+                  (else (remember (+ (* m 100000) n) (ack-step m n))))))
+        (define ack-step (lambda (m n)
+            (cond ((= m 0) (+ n 1))
+                  ((= n 0) (ack (- m 1) 1))
+                  (else (ack (- m 1) (ack m (- n 1)))))))
+        (list (ack 2 3) (ack 3 3) (ack 2 60))");
+    assert_eq!(interp.write(result), "(9 61 123)");
+}
+
+#[test]
+fn test_tail_calls_loop_after_defines() {
+    let interp = Interp::new();
+    // Tail calls still return their value to a non tail context.
+    let result = eval_all(&interp, &format!("
+        (define loop (lambda (n acc) (if (= n 0) acc (loop (- n 1) (+ acc 1)))))
+        (+ 1 (loop {depth} 0))", depth = DEPTH));
+    assert_eq!(result, Value::Number(Number::Int(DEPTH + 1)));
+}
+
+#[test]
+fn test_recursion_overflow_trace() {
+    let interp = Interp::new();
+    interp.set_max_depth(200);
+    interp.set_optimize_tail_calls(false);
+    eval_all(&interp, "
+        (define count-down (lambda (n) (if (= n 0) 0 (+ 1 (count-down (- n 1))))))
+        (define loop (lambda (n) (if (= n 0) 'done (loop (- n 1)))))");
+    let eval = |text: &str| {
+        let expr = Parser::new(text.as_bytes()).read(&interp).unwrap();
+        interp.eval(expr)
+    };
+    match eval("(count-down 1000)") {
+        Err(SchemeError::OverflowError(message)) => {
+            assert!(message.starts_with("Maximum recursion depth 200 exceeded"), "{}", message);
+            assert!(message.matches("count-down").count() >= 3, "{}", message);
+            assert!(message.ends_with("more"), "{}", message);
+        },
+        result => panic!("Expected a recursion overflow, got {:?}", result),
+    }
+    // The depth unwinds with the error.
+    assert_eq!(eval("(count-down 50)"), Ok(Value::Number(Number::Int(50))));
+
+    // Without the loop, tail calls grow the stack as well.
+    assert!(matches!(eval("(loop 1000)"), Err(SchemeError::OverflowError(_))));
+    interp.set_optimize_tail_calls(true);
+    assert_eq!(eval("(loop 1000)"), Ok(interp.lookup("done")));
+}
+
+#[test]
+fn test_default_depth_fits_main_stack() {
+    // The default limit must fail cleanly before a debug build overflows the
+    // 8MB stack of a main thread, even when recursing through map.
+    let result = thread::Builder::new()
+        .stack_size(8 * 1024 * 1024)
+        .spawn(|| {
+            let interp = Interp::new();
+            eval_all(&interp, "
+                (define nest (lambda (n)
+                  (if (= n 0) 0 (car (map (lambda (x) (+ x (nest (- n 1)))) '(1))))))");
+            let expr = Parser::new(format!("(nest {})", DEFAULT_MAX_DEPTH).as_bytes()).read(&interp).unwrap();
+            interp.eval(expr)
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    assert!(matches!(result, Err(SchemeError::OverflowError(_))), "{:?}", result);
+}
@@ -4,7 +4,7 @@ use crate::{env::Env, interp::Interp};
 
 pub type GcId = usize;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum SchemeError {
     EvalError(String),
     TypeError(String),
@@ -14,13 +14,54 @@ pub enum SchemeError {
     ArgCountError(String),
     OverflowError(String),
     FileNotFound(String),
+    DivisionByZero(String),
+    // Raised by (error "message" irritant ...).
+    UserError(String, Vec<Value>),
+    // Raised by (raise obj) with any object, which guard hands to its clauses as is.
+    Raised(Value),
+    // Unwinds the stack up to the call/cc frame with the matching escape id.
+    Continuation(usize, Value),
     // Other error types can be added here
 }
 
+impl SchemeError {
+
+    pub fn message(&self) -> String {
+        match self {
+            SchemeError::EvalError(msg)
+            | SchemeError::TypeError(msg)
+            | SchemeError::UnboundVariable(msg)
+            | SchemeError::SyntaxError(msg)
+            | SchemeError::ImplementationError(msg)
+            | SchemeError::ArgCountError(msg)
+            | SchemeError::OverflowError(msg)
+            | SchemeError::FileNotFound(msg)
+            | SchemeError::DivisionByZero(msg)
+            | SchemeError::UserError(msg, _) => msg.clone(),
+            SchemeError::Continuation(..) => "Continuation invoked outside of its extent.".to_string(),
+            SchemeError::Raised(_) => "Uncaught raised object.".to_string(),
+        }
+    }
+
+    // The values carried along by the error, which the collector keeps alive.
+    pub fn values(&self) -> Vec<Value> {
+        match self {
+            SchemeError::UserError(_, irritants) => irritants.clone(),
+            SchemeError::Raised(value) | SchemeError::Continuation(_, value) => vec![*value],
+            _ => Vec::new(),
+        }
+    }
+}
+
 pub trait SchemeObject {
     fn eval(&self, interp: &Interp, env: &Rc<RefCell<Env>>) -> Result<Value, SchemeError>;
+    // Only #f is false in Scheme, every other value counts as true.
     fn is_false(&self) -> bool;
-    fn write_to(&self, interp: &Interp, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+    fn is_truthy(&self) -> bool {
+        !self.is_false()
+    }
+    // Write mode produces re-readable output, display mode human-readable output.
+    fn write_to(&self, interp: &Interp, f: &mut fmt::Formatter<'_>, write: bool) -> fmt::Result;
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -29,6 +70,30 @@ pub enum Number {
     Float(f64),
 }
 
+impl Number {
+
+    pub fn is_exact(&self) -> bool {
+        matches!(self, Number::Int(_))
+    }
+
+    pub fn to_inexact(self) -> Number {
+        match self {
+            Number::Int(i) => Number::Float(i as f64),
+            float => float,
+        }
+    }
+
+    // Exactness contagion: a result is inexact as soon as any operand is,
+    // even when the operation itself picked an exact operand (e.g. max).
+    pub fn contagion(operands: &[Number], result: Number) -> Number {
+        if operands.iter().all(Number::is_exact) {
+            result
+        } else {
+            result.to_inexact()
+        }
+    }
+}
+
 impl PartialEq for Number {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
@@ -113,7 +178,8 @@ impl std::ops::Div for Number {
 
     fn div(self, other: Self) -> Self::Output {
         match (self, other) {
-            // Strict promotion (simplest for now), even 4 / 2 becomes 2.0
+            // Exact only when the division is, there are no rationals: 4 / 2 is 2 but 1 / 2 is 0.5.
+            (Number::Int(a), Number::Int(b)) if a.checked_rem(b) == Some(0) => Number::Int(a / b),
             (Number::Int(a), Number::Int(b)) => Number::Float(a as f64 / b as f64),
             (Number::Int(a), Number::Float(b)) => Number::Float(a as f64 / b),
             (Number::Float(a), Number::Int(b)) => Number::Float(a / b as f64),
@@ -175,7 +241,9 @@ pub enum Value {
     Char(u8),
     Boolean(bool),
     Object(GcId),
-    Nil
+    Nil,
+    Eof,
+    Unspecified,
 }
 
 impl Value {
@@ -187,6 +255,8 @@ impl Value {
             Self::Boolean(_) => "Boolean",
             Self::Object(_) => "Object",
             Self::Nil => "Nil",
+            Self::Eof => "Eof",
+            Self::Unspecified => "Unspecified",
         }
     }
 }
@@ -194,11 +264,12 @@ impl Value {
 pub struct DisplayWrapper<'a> {
     pub obj: &'a Value,
     pub interp: &'a Interp,
+    pub write: bool,
 }
 
 impl<'a> std::fmt::Display for DisplayWrapper<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.obj.write_to(self.interp, f)
+        self.obj.write_to(self.interp, f, self.write)
     }
 }
 
@@ -214,16 +285,14 @@ impl SchemeObject for Value {
     }
 
     fn is_false(&self) -> bool {
-        match self {
-            Value::Boolean(false) | Value::Nil => false,
-            _ => true,
-        }
+        matches!(self, Value::Boolean(false))
     }
 
-    fn write_to(&self, interp: &Interp, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fn write_to(&self, interp: &Interp, f: &mut fmt::Formatter<'_>, write: bool) -> fmt::Result {
         match self {
-            Value::Object(id) => id.write_to(interp, f),
-            Value::Number(n) => write!(f, "{}", n),
+            Value::Object(id) => id.write_to(interp, f, write),
+            Value::Number(n) => write!(f, "{}", interp.format_number(*n)),
+            Value::Char(ch) if !write => write!(f, "{}", *ch as char),
             Value::Char(ch) => {
                 let ch = *ch as char;
                 match ch {
@@ -232,12 +301,19 @@ impl SchemeObject for Value {
                     ' ' => write!(f, "#\\space"),
                     '\n' => write!(f, "#\\newline"),
                     '\r' => write!(f, "#\\return"),
-                    any => write!(f, "{}", any)
+                    '\0' => write!(f, "#\\null"),
+                    '\x07' => write!(f, "#\\alarm"),
+                    '\x1b' => write!(f, "#\\escape"),
+                    '\x7f' => write!(f, "#\\delete"),
+                    any if any.is_ascii_graphic() => write!(f, "#\\{}", any),
+                    any => write!(f, "#\\x{:02x}", any as u8),
                 }
             }
             Value::Boolean(true) => write!(f, "#t"),
             Value::Boolean(false) => write!(f, "#f"),
             Value::Nil => write!(f, "()"),
+            Value::Eof => write!(f, "#!eof"),
+            Value::Unspecified => write!(f, "#!unspecified"),
         }
     }
 